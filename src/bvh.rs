@@ -1,8 +1,26 @@
-use std::cmp::Ordering;
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    },
+};
 
-use rand::Rng;
+use rand::RngCore;
 
-use crate::{geometry::Hittable, ray::Ray, vec3::Vec3};
+use crate::{
+    error::PathtracerError,
+    geometry::{collect_lights_of, HitRecord, Hittable},
+    ray::Ray,
+    vec3::{Color, Vec3},
+};
+
+/// Padding of boxes around flat objects relative to the length of the box
+/// diagonal, see `Aabb::padding`.
+const RELATIVE_PADDING: f64 = 1e-6;
+/// Smallest padding, for objects which are tiny or collapsed to a point.
+const MIN_PADDING: f64 = 1e-9;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Aabb {
@@ -15,6 +33,10 @@ impl Aabb {
         Self { minimum, maximum }
     }
 
+    /// Slab test. A zero direction component makes `inv_d` infinite, which
+    /// gives `±inf` distances outside the slab and NaN for origins on one of
+    /// its planes. Comparisons with NaN are false and keep the interval, so
+    /// rays grazing a face count as hits.
     pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
         let mut found_t_min = t_min;
         let mut found_t_max = t_max;
@@ -25,9 +47,13 @@ impl Aabb {
             if inv_d < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
-            found_t_min = t0.max(found_t_min);
-            found_t_max = t1.min(found_t_max);
-            if found_t_max <= found_t_min {
+            if t0 > found_t_min {
+                found_t_min = t0;
+            }
+            if t1 < found_t_max {
+                found_t_max = t1;
+            }
+            if found_t_max < found_t_min {
                 return false;
             }
         }
@@ -49,78 +75,608 @@ impl Aabb {
         )
     }
 
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
+    /// Moves every face of the box outwards by `delta`, or inwards for a
+    /// negative `delta`. Shrinking stops at the center of the box.
+    pub fn pad(&self, delta: f64) -> Self {
+        let center = 0.5 * (self.minimum + self.maximum);
+        let delta = Vec3::new(delta, delta, delta);
+        Self::new(
+            (self.minimum - delta).min(center),
+            (self.maximum + delta).max(center),
+        )
+    }
+
+    /// Padding which keeps the box of a flat object from being infinitely
+    /// thin, which rays could slip past. It is a small fraction of the
+    /// diagonal, so that it stays negligible for tiny objects and is not lost
+    /// to rounding for huge ones.
+    pub fn padding(&self) -> f64 {
+        (RELATIVE_PADDING * (self.maximum - self.minimum).len()).max(MIN_PADDING)
+    }
+
     pub fn compare_axis(&self, other: &Self, axis: usize) -> Ordering {
         self.minimum.e[axis].total_cmp(&other.minimum.e[axis])
     }
 }
 
+/// Intersection tests done by `BvhNode::hit`, see `TraversalStats::measure`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraversalStats {
+    /// Bounding boxes of nodes tested against the ray.
+    pub box_tests: usize,
+    /// Objects in the leaves tested against the ray.
+    pub primitive_tests: usize,
+}
+
+thread_local! {
+    static TRAVERSAL_STATS: Cell<TraversalStats> = Cell::new(TraversalStats::default());
+}
+
+/// Number of `TraversalStats::measure` calls running on any thread. Trees
+/// only count their tests while there is one, so that renders which do not
+/// look at the stats skip the bookkeeping in their hottest loop.
+static MEASUREMENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps the trees counting until it is dropped, even if the measured code
+/// panics.
+struct Measurement;
+
+impl Measurement {
+    fn start() -> Self {
+        MEASUREMENTS.fetch_add(1, atomic::Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for Measurement {
+    fn drop(&mut self) {
+        MEASUREMENTS.fetch_sub(1, atomic::Ordering::Relaxed);
+    }
+}
+
+impl TraversalStats {
+    /// Runs `f` and returns the tests that all trees did on this thread
+    /// meanwhile, e.g. to measure a single `hit` call.
+    pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Self) {
+        let _measurement = Measurement::start();
+        let before = TRAVERSAL_STATS.get();
+        let result = f();
+        let after = TRAVERSAL_STATS.get();
+        (
+            result,
+            Self {
+                box_tests: after.box_tests.wrapping_sub(before.box_tests),
+                primitive_tests: after.primitive_tests.wrapping_sub(before.primitive_tests),
+            },
+        )
+    }
+
+    /// Total number of tests.
+    pub fn cost(&self) -> usize {
+        self.box_tests + self.primitive_tests
+    }
+
+    fn record(box_tests: usize, primitive_tests: usize) {
+        if MEASUREMENTS.load(atomic::Ordering::Relaxed) == 0 {
+            return;
+        }
+        TRAVERSAL_STATS.with(|stats| {
+            let mut current = stats.get();
+            current.box_tests = current.box_tests.wrapping_add(box_tests);
+            current.primitive_tests = current.primitive_tests.wrapping_add(primitive_tests);
+            stats.set(current);
+        });
+    }
+}
+
+/// An object together with its bounding box, computed once while building.
+type BoxedObject = (Aabb, Arc<dyn Hittable>);
+
 #[derive(Clone)]
 pub struct BvhNode {
-    left: Box<dyn Hittable>,
-    right: Box<dyn Hittable>,
+    left: Arc<dyn Hittable>,
+    /// `None` only for a tree of a single object.
+    right: Option<Arc<dyn Hittable>>,
     bbox: Aabb,
+    depth: usize,
+    /// How many of the children are objects instead of nodes.
+    primitives: usize,
 }
 
 impl BvhNode {
-    pub fn new(source_objects: Vec<Box<dyn Hittable>>) -> Self {
-        let mut objects = source_objects;
-        let axis = rand::thread_rng().gen_range(0..3);
-        match axis {
-            0 => objects.sort_by(|a, b| a.bounding_box().compare_axis(&b.bounding_box(), 0)),
-            1 => objects.sort_by(|a, b| a.bounding_box().compare_axis(&b.bounding_box(), 1)),
-            2 => objects.sort_by(|a, b| a.bounding_box().compare_axis(&b.bounding_box(), 2)),
-            _ => unreachable!(),
-        }
+    /// Builds the tree without any randomness, so the same objects in the
+    /// same order always give the same tree. Fails with `EmptyScene` for no
+    /// objects.
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Result<Self, PathtracerError> {
+        // every object's box is computed once here instead of on every
+        // comparison while sorting
+        Self::from_boxed(
+            objects
+                .into_iter()
+                .map(|object| (object.bounding_box(), object))
+                .collect(),
+        )
+    }
+
+    /// Number of nodes on the longest path from this node down to an object.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
 
+    fn from_boxed(mut objects: Vec<BoxedObject>) -> Result<Self, PathtracerError> {
         match objects.len() {
-            0 | 1 => panic!("creating BVH node from empty object list"),
+            0 => Err(PathtracerError::EmptyScene),
+            1 => {
+                let (bbox, left) = objects.pop().expect("length is 1");
+                Ok(Self {
+                    left,
+                    right: None,
+                    bbox,
+                    depth: 1,
+                    primitives: 1,
+                })
+            }
             2 => {
-                let right = objects.pop().expect("no pop possible on length 2 vector?");
-                let left = objects.pop().expect("no pop possible on length 1 vector?");
-                let bbox = left.bounding_box().surrounding_box(&right.bounding_box());
-                Self { left, right, bbox }
+                let (right_box, right) = objects.pop().expect("length is 2");
+                let (left_box, left) = objects.pop().expect("length is 1");
+                Ok(Self {
+                    left,
+                    right: Some(right),
+                    bbox: left_box.surrounding_box(&right_box),
+                    depth: 1,
+                    primitives: 2,
+                })
             }
-            len => {
-                let half_index = len / 2;
-                let right_list = objects.split_off(half_index);
-
-                let left = match objects.len() {
-                    0 => panic!("empty list after split"),
-                    1 => objects[0].clone(),
-                    _ => Box::new(Self::new(objects)),
-                };
+            _ => {
+                let split_index = Self::sort_for_split(&mut objects);
+                let right_list = objects.split_off(split_index);
 
-                let right = match right_list.len() {
-                    0 => panic!("empty list after split"),
-                    1 => right_list[0].clone(),
-                    _ => Box::new(Self::new(right_list.to_vec())),
-                };
+                let (left_box, left, left_depth) = Self::new_child(objects)?;
+                let (right_box, right, right_depth) = Self::new_child(right_list)?;
 
-                let bbox = left.bounding_box().surrounding_box(&right.bounding_box());
-                Self { left, right, bbox }
+                Ok(Self {
+                    left,
+                    right: Some(right),
+                    bbox: left_box.surrounding_box(&right_box),
+                    depth: 1 + left_depth.max(right_depth),
+                    primitives: (left_depth == 0) as usize + (right_depth == 0) as usize,
+                })
             }
         }
     }
+
+    fn new_child(
+        mut objects: Vec<BoxedObject>,
+    ) -> Result<(Aabb, Arc<dyn Hittable>, usize), PathtracerError> {
+        if objects.len() == 1 {
+            let (bbox, object) = objects.pop().expect("length is 1");
+            return Ok((bbox, object, 0));
+        }
+
+        let node = Self::from_boxed(objects)?;
+        let (bbox, depth) = (node.bbox, node.depth);
+        Ok((bbox, Arc::new(node), depth))
+    }
+
+    /// Sorts the objects along the axis with the cheapest split according to
+    /// the surface area heuristic and returns the index to split at. The cost
+    /// of a split is the surface area of each side times its object count.
+    fn sort_for_split(objects: &mut [BoxedObject]) -> usize {
+        let centroid =
+            |(bbox, _): &BoxedObject, axis: usize| bbox.minimum[axis] + bbox.maximum[axis];
+
+        let n = objects.len();
+        let mut best = (f64::INFINITY, 0, n / 2);
+        let mut left_areas = vec![0.0; n];
+        let mut right_areas = vec![0.0; n];
+        for axis in 0..3 {
+            objects.sort_by(|a, b| centroid(a, axis).total_cmp(&centroid(b, axis)));
+
+            // areas of the boxes around everything before (left) and from
+            // (right) each split index on
+            let mut left_box = objects[0].0;
+            let mut right_box = objects[n - 1].0;
+            for i in 1..n {
+                left_areas[i] = left_box.surface_area();
+                left_box = left_box.surrounding_box(&objects[i].0);
+
+                right_areas[n - i] = right_box.surface_area();
+                right_box = right_box.surrounding_box(&objects[n - i - 1].0);
+            }
+
+            for i in 1..n {
+                let cost = left_areas[i] * i as f64 + right_areas[i] * (n - i) as f64;
+                if cost < best.0 {
+                    best = (cost, axis, i);
+                }
+            }
+        }
+
+        let (_, axis, split_index) = best;
+        if axis != 2 {
+            objects.sort_by(|a, b| centroid(a, axis).total_cmp(&centroid(b, axis)));
+        }
+        split_index
+    }
 }
 
-impl Hittable for BvhNode {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<crate::geometry::HitRecord> {
+impl BvhNode {
+    /// Closest of the hits `hit` finds on the children, each called with the
+    /// distance to the closest hit so far.
+    fn closest_hit<'a>(
+        &'a self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut hit: impl FnMut(&'a dyn Hittable, f64) -> Option<HitRecord<'a>>,
+    ) -> Option<HitRecord<'a>> {
         if !self.bbox.hit(ray, t_min, t_max) {
+            TraversalStats::record(1, 0);
             return None;
         }
+        TraversalStats::record(1, self.primitives);
 
-        let result = self.left.hit(ray, t_min, t_max);
+        let result = hit(&*self.left, t_max);
+        let Some(right) = &self.right else {
+            return result;
+        };
         let new_t_max = match result {
             Some(ref hit_record) => hit_record.t,
             None => t_max,
         };
-        match self.right.hit(ray, t_min, new_t_max) {
+        match hit(&**right, new_t_max) {
             Some(hit_record) => Some(hit_record),
             None => result,
         }
     }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.closest_hit(ray, t_min, t_max, |child, t_max| {
+            child.hit(ray, t_min, t_max)
+        })
+    }
+
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        self.closest_hit(ray, t_min, t_max, |child, t_max| {
+            child.hit_with_rng(ray, t_min, t_max, rng)
+        })
+    }
 
     fn bounding_box(&self) -> Aabb {
         self.bbox
     }
+
+    fn area(&self) -> f64 {
+        self.left.area() + self.right.as_ref().map_or(0.0, |right| right.area())
+    }
+
+    fn emitted_power(&self) -> Color {
+        let right = self.right.as_ref().map(|right| right.emitted_power());
+        self.left.emitted_power() + right.unwrap_or_default()
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        let children: Vec<_> = [Some(&self.left), self.right.as_ref()]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        collect_lights_of(&children, lights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        camera::Camera,
+        environment::Environment,
+        geometry::{HittableList, Sphere, Triangle},
+        integrator::NaivePathIntegrator,
+        material::{LambertianMaterial, Material},
+        renderer::render,
+        sampler::SampleSource,
+        scene::ImageSettings,
+        vec3::Color,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.5, 0.5, 0.5,
+        )))
+    }
+
+    #[test]
+    fn sah_tree_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1262);
+        let material = material();
+        // a dense cluster and a few far outliers
+        let objects: Vec<Arc<dyn Hittable>> = (0..3000)
+            .map(|i| {
+                let spread = if i % 100 == 0 { 500.0 } else { 20.0 };
+                let center = Vec3::random_range(&mut rng, -spread, spread);
+                Arc::new(Sphere::new(
+                    center,
+                    rng.gen_range(0.1..1.0),
+                    material.clone(),
+                )) as Arc<dyn Hittable>
+            })
+            .collect();
+
+        let bvh = BvhNode::new(objects.clone()).unwrap();
+        let brute_force = HittableList::from(objects);
+        // a balanced tree over 3000 objects has depth 12
+        assert!(bvh.depth() <= 30, "depth {}", bvh.depth());
+
+        for _ in 0..2000 {
+            let ray = Ray::new(
+                Vec3::random_range(&mut rng, -30.0, 30.0),
+                Vec3::random_on_unitsphere(&mut rng),
+            );
+            let expected = brute_force.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn builds_are_reproducible() {
+        let material = material();
+        // many identical boxes, so that the split has to break ties
+        let objects: Vec<Arc<dyn Hittable>> = (0..200)
+            .map(|i| {
+                let center = Vec3::new((i % 5) as f64, 0.0, 0.0);
+                Arc::new(Sphere::new(center, 0.75, material.clone())) as Arc<dyn Hittable>
+            })
+            .collect();
+
+        let first = BvhNode::new(objects.clone()).unwrap();
+        let second = BvhNode::new(objects).unwrap();
+        assert_eq!(first.depth(), second.depth());
+
+        let mut rng = StdRng::seed_from_u64(1263);
+        for _ in 0..500 {
+            let ray = Ray::new(
+                Vec3::random_range(&mut rng, -5.0, 5.0),
+                Vec3::random_on_unitsphere(&mut rng),
+            );
+            let first_hit = first.hit(&ray, 0.001, f64::INFINITY);
+            let second_hit = second.hit(&ray, 0.001, f64::INFINITY);
+            assert_eq!(first_hit.map(|hit| hit.t), second_hit.map(|hit| hit.t));
+        }
+    }
+
+    #[test]
+    fn leaves_are_shared_not_cloned() {
+        let material = material();
+        // a 250 x 200 grid of quads facing +z
+        let objects: Vec<Arc<dyn Hittable>> = (0..100_000)
+            .map(|i| {
+                let (x, y) = ((i / 2 % 250) as f64, (i / 2 / 250) as f64);
+                let triangle = if i % 2 == 0 {
+                    Triangle::new_without_normal(
+                        Vec3::new(x, y, 0.0),
+                        Vec3::new(x + 1.0, y, 0.0),
+                        Vec3::new(x, y + 1.0, 0.0),
+                        material.clone(),
+                    )
+                } else {
+                    Triangle::new_without_normal(
+                        Vec3::new(x + 1.0, y, 0.0),
+                        Vec3::new(x + 1.0, y + 1.0, 0.0),
+                        Vec3::new(x, y + 1.0, 0.0),
+                        material.clone(),
+                    )
+                };
+                Arc::new(triangle) as Arc<dyn Hittable>
+            })
+            .collect();
+
+        let bvh = BvhNode::new(objects.clone()).unwrap();
+        // every triangle is referenced once here and once by the tree
+        assert!(objects.iter().all(|object| Arc::strong_count(object) == 2));
+
+        let ray = Ray::new(Vec3::new(100.25, 50.25, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(
+            Some(5.0),
+            bvh.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t)
+        );
+    }
+
+    #[test]
+    fn renders_like_brute_force() {
+        let material = material();
+        let mut rng = StdRng::seed_from_u64(1264);
+        let objects: Vec<Arc<dyn Hittable>> = (0..50)
+            .map(|_| {
+                let center = Vec3::random_range(&mut rng, -3.0, 3.0) + Vec3::new(0.0, 0.0, -8.0);
+                Arc::new(Sphere::new(center, 0.5, material.clone())) as Arc<dyn Hittable>
+            })
+            .collect();
+        let bvh = BvhNode::new(objects.clone()).unwrap();
+        let brute_force = HittableList::from(objects);
+
+        let camera = Camera::new(
+            Vec3::default(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let settings = ImageSettings {
+            width: 16,
+            height: 16,
+            samples_per_pixel: 2,
+            background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+            sample_source: SampleSource::Table { seed: 5 },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(
+                &brute_force,
+                &camera,
+                &settings,
+                &NaivePathIntegrator::default(),
+                |_| {}
+            )
+            .unwrap(),
+            render(
+                &bvh,
+                &camera,
+                &settings,
+                &NaivePathIntegrator::default(),
+                |_| {}
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_boxes() {
+        let bbox = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let hits = |origin, direction| bbox.hit(&Ray::new(origin, direction), 0.001, f64::INFINITY);
+
+        // parallel to x and y, straight through
+        assert!(hits(Vec3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(hits(Vec3::new(0.5, 0.5, 2.0), Vec3::new(-0.0, -0.0, -1.0)));
+        // grazing the top and side faces
+        assert!(hits(Vec3::new(0.5, 1.0, -1.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(hits(Vec3::new(1.0, 1.0, -1.0), Vec3::new(0.0, 0.0, 1.0)));
+        // parallel, but beside the box
+        assert!(!hits(Vec3::new(0.5, 1.5, -1.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(!hits(Vec3::new(-0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0)));
+        // pointing away
+        assert!(!hits(Vec3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn rays_starting_inside_hit_boxes() {
+        let bbox = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(1.0, 2.0, -3.0),
+        ] {
+            let ray = Ray::new(Vec3::new(0.2, -0.3, 0.1), direction);
+            assert!(bbox.hit(&ray, 0.001, f64::INFINITY));
+        }
+
+        // flat boxes still have an inside for rays crossing them
+        let flat = Aabb::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(flat.hit(&ray, 0.001, f64::INFINITY));
+    }
+
+    #[test]
+    fn single_object_trees() {
+        let sphere: Arc<dyn Hittable> =
+            Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -2.0), 0.5, material()));
+        let bvh = BvhNode::new(vec![sphere]).unwrap();
+        assert_eq!(1, bvh.depth());
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = bvh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.t - 1.5).abs() < 1e-9);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(bvh.hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        assert!(matches!(
+            BvhNode::new(vec![]),
+            Err(PathtracerError::EmptyScene)
+        ));
+    }
+
+    #[test]
+    fn missing_rays_are_cheap() {
+        let material = material();
+        let objects: Vec<Arc<dyn Hittable>> = (0..100)
+            .map(|i| {
+                let center = Vec3::new((i % 10) as f64, (i / 10) as f64, -5.0);
+                Arc::new(Sphere::new(center, 0.3, material.clone())) as Arc<dyn Hittable>
+            })
+            .collect();
+        let bvh = BvhNode::new(objects).unwrap();
+
+        let away = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let (hit, stats) = TraversalStats::measure(|| bvh.hit(&away, 0.001, f64::INFINITY));
+        assert!(hit.is_none());
+        assert_eq!(1, stats.box_tests);
+        assert_eq!(0, stats.primitive_tests);
+
+        // through the grid between the spheres, every box is close by
+        let between = Ray::new(Vec3::new(4.5, 4.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let (hit, stats) = TraversalStats::measure(|| bvh.hit(&between, 0.001, f64::INFINITY));
+        assert!(hit.is_none());
+        assert!(stats.cost() < 50, "{:?}", stats);
+
+        let onto = Ray::new(Vec3::new(3.0, 7.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let (hit, stats) = TraversalStats::measure(|| bvh.hit(&onto, 0.001, f64::INFINITY));
+        assert!(hit.is_some());
+        assert!(stats.primitive_tests >= 1 && stats.box_tests >= bvh.depth());
+    }
+
+    fn random_triangles(count: usize, rng: &mut StdRng) -> Vec<Arc<dyn Hittable>> {
+        let material = material();
+        (0..count)
+            .map(|_| {
+                let corner = Vec3::random_range(rng, -50.0, 50.0);
+                Arc::new(Triangle::new_without_normal(
+                    corner,
+                    corner + Vec3::random_range(rng, -1.0, 1.0),
+                    corner + Vec3::random_range(rng, -1.0, 1.0),
+                    material.clone(),
+                )) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    fn assert_matches_brute_force(objects: Vec<Arc<dyn Hittable>>, rng: &mut StdRng) {
+        let bvh = BvhNode::new(objects.clone()).unwrap();
+        let brute_force = HittableList::from(objects);
+
+        for _ in 0..50 {
+            let ray = Ray::new(
+                Vec3::random_range(rng, -60.0, 60.0),
+                Vec3::random_range(rng, -1.0, 1.0),
+            );
+            let expected = brute_force.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn meshes_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1302);
+        let objects = random_triangles(2_000, &mut rng);
+        assert_matches_brute_force(objects, &mut rng);
+    }
+
+    #[test]
+    #[ignore = "slow in debug builds, run with --ignored --release"]
+    fn large_meshes_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1302);
+        let objects = random_triangles(50_000, &mut rng);
+        assert_matches_brute_force(objects, &mut rng);
+    }
 }
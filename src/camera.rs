@@ -1,5 +1,13 @@
-use crate::{ray::Ray, vec3::Vec3};
+use std::fmt;
 
+use rand::{Rng, RngCore};
+
+use crate::{
+    ray::Ray,
+    vec3::{OrthonormalBasis, Vec3},
+};
+
+#[derive(Debug)]
 pub struct Camera {
     origin: Vec3,
     lower_left_corner: Vec3,
@@ -8,6 +16,8 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -23,8 +33,15 @@ impl Camera {
         let h = (vertical_fov.to_radians() / 2.0).tan();
         let viewport = (aspect_ratio * 2.0 * h, 2.0 * h);
 
-        let w = (lookfrom - lookat).unit_vector();
-        let u = up.cross(w).unit_vector();
+        let w = (lookfrom - lookat)
+            .try_unit_vector()
+            .unwrap_or(Vec3::new(0.0, 0.0, 1.0));
+        // looking along `up` leaves the roll of the camera undefined, any
+        // horizontal axis will do then
+        let u = up
+            .cross(w)
+            .try_unit_vector()
+            .unwrap_or_else(|| OrthonormalBasis::from_normal(w).u);
         let v = w.cross(u);
 
         let origin = lookfrom;
@@ -40,18 +57,406 @@ impl Camera {
             u,
             v,
             lens_radius: aperture / 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
-    pub fn ray_at(&self, s: f64, t: f64) -> Ray {
-        let rng = self.lens_radius * Vec3::random_in_unitdisk_xy();
-        let blur_offset = self.u * rng.x() + self.v * rng.y();
+    /// Keeps the shutter open from `open` until `close`, so that rays are
+    /// spread over that time and moving objects are blurred.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter_open = open.min(close);
+        self.shutter_close = open.max(close);
+        self
+    }
+
+    pub fn ray_at(&self, s: f64, t: f64, rng: &mut dyn RngCore) -> Ray {
+        let lens_offset = self.lens_radius * Vec3::random_in_unitdisk_xy(rng);
+        let blur_offset = self.u * lens_offset.x() + self.v * lens_offset.y();
 
-        Ray::new(
+        let time = match self.shutter_close > self.shutter_open {
+            true => rng.gen_range(self.shutter_open..self.shutter_close),
+            false => self.shutter_open,
+        };
+
+        Ray::new_at_time(
             self.origin + blur_offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
                 - blur_offset,
+            time,
+        )
+    }
+
+    /// Like `ray_at`, but always through the center of the lens at the time
+    /// the shutter opens, so the same `(s, t)` gives the same ray.
+    pub fn pinhole_ray_at(&self, s: f64, t: f64) -> Ray {
+        Ray::new_at_time(
+            self.origin,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin,
+            self.shutter_open,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CameraError {
+    /// `look_from` equals `look_at` or `up` is parallel to the view
+    /// direction, so no orthonormal basis can be built.
+    DegenerateBasis,
+    InvalidFieldOfView,
+    InvalidAspectRatio,
+    InvalidAperture,
+    InvalidFocusDistance,
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DegenerateBasis => write!(
+                f,
+                "camera look_from equals look_at or up is parallel to the view direction"
+            ),
+            Self::InvalidFieldOfView => write!(f, "camera field of view must be in (0, 180)"),
+            Self::InvalidAspectRatio => write!(f, "camera aspect ratio must be positive"),
+            Self::InvalidAperture => write!(f, "camera aperture must not be negative"),
+            Self::InvalidFocusDistance => write!(f, "camera focus distance must be positive"),
+        }
+    }
+}
+
+/// Builds a `Camera` from named parameters. Unless set otherwise, the camera
+/// sits at the origin looking down -z with a 90° field of view, a square
+/// aspect ratio, no defocus blur, a closed shutter and the focus on
+/// `look_at`.
+#[derive(Clone)]
+pub struct CameraBuilder {
+    look_from: Vec3,
+    look_at: Vec3,
+    up: Vec3,
+    vertical_fov: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    focus_dist: Option<f64>,
+    shutter: (f64, f64),
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        Self {
+            look_from: Vec3::new(0.0, 0.0, 0.0),
+            look_at: Vec3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vertical_fov: 90.0,
+            aspect_ratio: 1.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter: (0.0, 0.0),
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn look_from(mut self, look_from: Vec3) -> Self {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Vec3) -> Self {
+        self.look_at = look_at;
+        self
+    }
+
+    pub fn up(mut self, up: Vec3) -> Self {
+        self.up = up;
+        self
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn fov(mut self, vertical_fov: f64) -> Self {
+        self.vertical_fov = vertical_fov;
+        self
+    }
+
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn aperture(mut self, aperture: f64) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn focus_dist(mut self, focus_dist: f64) -> Self {
+        self.focus_dist = Some(focus_dist);
+        self
+    }
+
+    /// See `Camera::with_shutter`.
+    pub fn shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn build(self) -> Result<Camera, CameraError> {
+        let view_direction = self.look_at - self.look_from;
+        if view_direction.near_zero() || self.up.cross(view_direction).near_zero() {
+            return Err(CameraError::DegenerateBasis);
+        }
+        if !(self.vertical_fov > 0.0 && self.vertical_fov < 180.0) {
+            return Err(CameraError::InvalidFieldOfView);
+        }
+        if self.aspect_ratio.is_nan() || self.aspect_ratio <= 0.0 {
+            return Err(CameraError::InvalidAspectRatio);
+        }
+        if !(self.aperture.is_finite() && self.aperture >= 0.0) {
+            return Err(CameraError::InvalidAperture);
+        }
+        if self
+            .focus_dist
+            .is_some_and(|focus_dist| !(focus_dist.is_finite() && focus_dist > 0.0))
+        {
+            return Err(CameraError::InvalidFocusDistance);
+        }
+
+        Ok(Camera::new(
+            self.look_from,
+            self.look_at,
+            self.up,
+            self.vertical_fov,
+            self.aspect_ratio,
+            self.aperture,
+            self.focus_dist.unwrap_or_else(|| view_direction.len()),
         )
+        .with_shutter(self.shutter.0, self.shutter.1))
+    }
+}
+
+/// Camera placement at time `t` of a `CameraAnimation`.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKeyframe {
+    pub t: f64,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in degrees.
+    pub vertical_fov: f64,
+}
+
+impl CameraKeyframe {
+    pub fn new(t: f64, look_from: Vec3, look_at: Vec3, up: Vec3, vertical_fov: f64) -> Self {
+        Self {
+            t,
+            look_from,
+            look_at,
+            up,
+            vertical_fov,
+        }
+    }
+}
+
+/// How a `CameraAnimation` moves between two keyframes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Starts and stops smoothly at every keyframe.
+    EaseInOut,
+}
+
+/// A camera moving through keyframes. Before the first and after the last
+/// keyframe it stays put; the focus is always on `look_at`.
+#[derive(Debug, Clone)]
+pub struct CameraAnimation {
+    keyframes: Vec<CameraKeyframe>,
+    pub easing: Easing,
+    pub aspect_ratio: f64,
+    pub aperture: f64,
+}
+
+impl CameraAnimation {
+    /// Panics without any keyframes.
+    pub fn new(mut keyframes: Vec<CameraKeyframe>, aspect_ratio: f64) -> Self {
+        assert!(!keyframes.is_empty(), "camera animation without keyframes");
+        keyframes.sort_by(|a, b| a.t.total_cmp(&b.t));
+        Self {
+            keyframes,
+            easing: Easing::Linear,
+            aspect_ratio,
+            aperture: 0.0,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: f64) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    /// Keyframe values interpolated at time `t`.
+    pub fn keyframe_at(&self, t: f64) -> CameraKeyframe {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.t <= t);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+
+        let (a, b) = (self.keyframes[next - 1], self.keyframes[next]);
+        let mut s = (t - a.t) / (b.t - a.t);
+        if self.easing == Easing::EaseInOut {
+            s = s * s * (3.0 - 2.0 * s);
+        }
+        CameraKeyframe {
+            t,
+            look_from: a.look_from.lerp(b.look_from, s),
+            look_at: a.look_at.lerp(b.look_at, s),
+            up: a.up.lerp(b.up, s),
+            vertical_fov: (1.0 - s) * a.vertical_fov + s * b.vertical_fov,
+        }
+    }
+
+    pub fn camera_at(&self, t: f64) -> Camera {
+        let keyframe = self.keyframe_at(t);
+        Camera::new(
+            keyframe.look_from,
+            keyframe.look_at,
+            keyframe.up,
+            keyframe.vertical_fov,
+            self.aspect_ratio,
+            self.aperture,
+            (keyframe.look_at - keyframe.look_from).len(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_validates_basis() {
+        let camera = CameraBuilder::new()
+            .look_from(Vec3::new(0.0, 0.0, 5.0))
+            .look_at(Vec3::new(0.0, 0.0, 0.0))
+            .fov(40.0)
+            .build()
+            .unwrap();
+        let ray = camera.ray_at(0.5, 0.5, &mut rand::thread_rng());
+        assert!(ray.direction.unit_vector().z() < -0.999);
+
+        assert_eq!(
+            Some(CameraError::DegenerateBasis),
+            CameraBuilder::new()
+                .look_from(Vec3::new(0.0, 3.0, 0.0))
+                .look_at(Vec3::new(0.0, 0.0, 0.0))
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(CameraError::DegenerateBasis),
+            CameraBuilder::new()
+                .look_from(Vec3::new(1.0, 2.0, 3.0))
+                .look_at(Vec3::new(1.0, 2.0, 3.0))
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(CameraError::InvalidFieldOfView),
+            CameraBuilder::new().fov(180.0).build().err()
+        );
+    }
+
+    #[test]
+    fn builder_validates_lens() {
+        assert!(CameraBuilder::new()
+            .aperture(0.1)
+            .focus_dist(2.0)
+            .build()
+            .is_ok());
+
+        for aperture in [-0.1, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                Some(CameraError::InvalidAperture),
+                CameraBuilder::new().aperture(aperture).build().err()
+            );
+        }
+        for focus_dist in [0.0, -1.0, f64::NAN] {
+            assert_eq!(
+                Some(CameraError::InvalidFocusDistance),
+                CameraBuilder::new().focus_dist(focus_dist).build().err()
+            );
+        }
+    }
+
+    #[test]
+    fn degenerate_cameras_stay_finite() {
+        let mut rng = rand::thread_rng();
+        let origin = Vec3::new(0.0, 3.0, 0.0);
+        for look_at in [Vec3::default(), origin] {
+            let camera = Camera::new(
+                origin,
+                look_at,
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+                1.0,
+                0.0,
+                1.0,
+            );
+            let ray = camera.ray_at(0.3, 0.7, &mut rng);
+            assert!(ray.direction.e.iter().all(|v| v.is_finite()));
+            assert!(!ray.direction.near_zero());
+        }
+    }
+
+    #[test]
+    fn animations_start_and_end_on_keyframes() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let first = CameraKeyframe::new(0.0, Vec3::new(0.0, 0.0, 5.0), Vec3::default(), up, 40.0);
+        let middle = CameraKeyframe::new(0.5, Vec3::new(5.0, 0.0, 0.0), Vec3::default(), up, 40.0);
+        let last = CameraKeyframe::new(1.0, Vec3::new(0.0, 2.0, -5.0), Vec3::default(), up, 60.0);
+        let animation = CameraAnimation::new(vec![last, first, middle], 1.5);
+
+        let same = |a: &Camera, b: &Camera| {
+            (a.origin - b.origin).near_zero()
+                && (a.lower_left_corner - b.lower_left_corner).near_zero()
+                && (a.horizontal - b.horizontal).near_zero()
+                && (a.vertical - b.vertical).near_zero()
+        };
+        let camera_of = |keyframe: CameraKeyframe| {
+            Camera::new(
+                keyframe.look_from,
+                keyframe.look_at,
+                keyframe.up,
+                keyframe.vertical_fov,
+                1.5,
+                0.0,
+                (keyframe.look_at - keyframe.look_from).len(),
+            )
+        };
+        for easing in [Easing::Linear, Easing::EaseInOut] {
+            let animation = animation.clone().with_easing(easing);
+            assert!(same(&camera_of(first), &animation.camera_at(0.0)));
+            assert!(same(&camera_of(last), &animation.camera_at(1.0)));
+            assert!(same(&camera_of(last), &animation.camera_at(2.0)));
+        }
+
+        let linear = animation.keyframe_at(0.75);
+        assert!((linear.look_from - Vec3::new(2.5, 1.0, -2.5)).near_zero());
+        assert!((linear.vertical_fov - 50.0).abs() < 1e-9);
+        // easing lingers near the previous keyframe
+        let eased = animation.clone().with_easing(Easing::EaseInOut);
+        assert!(eased.keyframe_at(0.55).look_from.x() > animation.keyframe_at(0.55).look_from.x());
     }
 }
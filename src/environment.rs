@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::{
+    texture::{ImageTexture, Texture},
+    vec3::{Color, Vec3},
+};
+
+/// Light arriving from infinitely far away, seen by rays which miss all
+/// geometry.
+#[derive(Clone)]
+pub enum Environment {
+    Constant(Color),
+    /// An equirectangular panorama: u goes once around the y axis and v from
+    /// straight down (0) to straight up (1).
+    Image(Arc<ImageTexture>),
+    /// Blends from `bottom` for rays straight down to `top` for rays straight
+    /// up, linearly in the y component of the direction.
+    SkyGradient {
+        bottom: Color,
+        top: Color,
+    },
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::Constant(Color::default())
+    }
+}
+
+impl Environment {
+    /// The white to light blue sky of "Ray Tracing in One Weekend".
+    pub fn sky() -> Self {
+        Self::SkyGradient {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+
+    /// Radiance arriving from `direction`, which does not have to be
+    /// normalized.
+    pub fn value(&self, direction: Vec3) -> Color {
+        match self {
+            Self::Constant(color) => *color,
+            Self::Image(texture) => {
+                let direction = direction.unit_vector();
+                let theta = (-direction.y()).clamp(-1.0, 1.0).acos();
+                let phi = (-direction.z()).atan2(direction.x()) + std::f64::consts::PI;
+                texture.value(
+                    phi / (2.0 * std::f64::consts::PI),
+                    theta / std::f64::consts::PI,
+                    direction,
+                )
+            }
+            Self::SkyGradient { bottom, top } => {
+                let t = 0.5 * (direction.unit_vector().y() + 1.0);
+                bottom.lerp(*top, t)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn downward_rays_see_the_bottom_row() {
+        // 1 x 2 image, white on top and red at the bottom
+        let path = std::env::temp_dir().join("pathtracer_environment.png");
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 1, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[255, 255, 255, 255, 0, 0])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let environment = Environment::Image(Arc::new(ImageTexture::load(&path).unwrap()));
+        let down = environment.value(Vec3::new(0.0, -3.0, 0.0));
+        let up = environment.value(Vec3::new(0.0, 1.0, 0.0));
+        assert!((down - Color::new(1.0, 0.0, 0.0)).near_zero());
+        assert!((up - Color::new(1.0, 1.0, 1.0)).near_zero());
+
+        let constant = Environment::Constant(Color::new(0.1, 0.2, 0.3));
+        assert_eq!(0.2, constant.value(Vec3::new(1.0, 0.0, 0.0)).y());
+    }
+
+    #[test]
+    fn sky_gradient_blends_by_height() {
+        let sky = Environment::sky();
+        assert!((sky.value(Vec3::new(0.0, 2.0, 0.0)) - Color::new(0.5, 0.7, 1.0)).near_zero());
+        assert!((sky.value(Vec3::new(0.0, -1.0, 0.0)) - Color::new(1.0, 1.0, 1.0)).near_zero());
+        let horizon = sky.value(Vec3::new(3.0, 0.0, -1.0));
+        assert!((horizon - Color::new(0.75, 0.85, 1.0)).near_zero());
+    }
+}
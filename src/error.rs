@@ -0,0 +1,106 @@
+use std::{fmt, io};
+
+use crate::camera::CameraError;
+
+/// Everything that can go wrong while loading, rendering or writing a scene.
+#[derive(Debug)]
+pub enum PathtracerError {
+    Io(io::Error),
+    Parse(String),
+    ObjLoad(tobj::LoadError),
+    /// A scene or mesh without any objects to build a BVH from.
+    EmptyScene,
+    DegenerateCamera(CameraError),
+    InvalidImageSize {
+        width: usize,
+        height: usize,
+    },
+    /// A JPEG quality outside of 1 to 100.
+    InvalidJpegQuality(u8),
+    /// The threads to render in could not be started.
+    ThreadPool(String),
+    /// A render checkpoint which was saved for a different image size, scene
+    /// or settings.
+    CheckpointMismatch(String),
+}
+
+impl fmt::Display for PathtracerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "i/o error: {}", error),
+            Self::Parse(message) => write!(f, "parse error: {}", message),
+            Self::ObjLoad(error) => write!(f, "could not load obj file: {}", error),
+            Self::EmptyScene => write!(f, "scene does not contain any objects"),
+            Self::DegenerateCamera(error) => write!(f, "invalid camera: {}", error),
+            Self::InvalidImageSize { width, height } => {
+                write!(f, "invalid image size {}x{}", width, height)
+            }
+            Self::InvalidJpegQuality(quality) => {
+                write!(f, "invalid jpeg quality {}, expected 1 to 100", quality)
+            }
+            Self::ThreadPool(message) => write!(f, "could not start threads: {}", message),
+            Self::CheckpointMismatch(message) => write!(f, "cannot resume render: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PathtracerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::ObjLoad(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PathtracerError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<tobj::LoadError> for PathtracerError {
+    fn from(error: tobj::LoadError) -> Self {
+        Self::ObjLoad(error)
+    }
+}
+
+impl From<CameraError> for PathtracerError {
+    fn from(error: CameraError) -> Self {
+        Self::DegenerateCamera(error)
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for PathtracerError {
+    fn from(error: rayon::ThreadPoolBuildError) -> Self {
+        Self::ThreadPool(error.to_string())
+    }
+}
+
+impl From<png::EncodingError> for PathtracerError {
+    fn from(error: png::EncodingError) -> Self {
+        match error {
+            png::EncodingError::IoError(error) => Self::Io(error),
+            error => Self::Io(io::Error::other(error)),
+        }
+    }
+}
+
+impl From<png::DecodingError> for PathtracerError {
+    fn from(error: png::DecodingError) -> Self {
+        match error {
+            png::DecodingError::IoError(error) => Self::Io(error),
+            error => Self::Parse(error.to_string()),
+        }
+    }
+}
+
+impl From<gif::EncodingError> for PathtracerError {
+    fn from(error: gif::EncodingError) -> Self {
+        match error {
+            gif::EncodingError::Io(error) => Self::Io(error),
+            error => Self::Io(io::Error::other(error)),
+        }
+    }
+}
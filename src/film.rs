@@ -0,0 +1,418 @@
+use std::io::{Read, Write};
+
+use crate::{error::PathtracerError, vec3::Color};
+
+/// First bytes of a checkpoint file, see `Film::write_checkpoint`.
+const CHECKPOINT_MAGIC: &[u8; 8] = b"PTFILM3\n";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ReconstructionFilter {
+    /// Every sample only counts towards the pixel it was taken in.
+    #[default]
+    Box,
+    /// Samples are splatted into all pixels whose center lies within
+    /// `radius`, weighted linearly by their distance on each axis.
+    Tent { radius: f64 },
+    /// Samples are splatted into all pixels whose center lies within
+    /// `radius`, weighted by a Gaussian `exp(-alpha d²)` on each axis which
+    /// is shifted down to reach zero at `radius`. Smoother than `Tent`, at
+    /// the cost of a little sharpness.
+    Gaussian { radius: f64, alpha: f64 },
+}
+
+impl ReconstructionFilter {
+    pub fn radius(&self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent { radius } | Self::Gaussian { radius, .. } => *radius,
+        }
+    }
+
+    /// Number of pixels a sample can reach beyond the pixel it was taken in.
+    pub fn margin(&self) -> i64 {
+        (self.radius() - 0.5).ceil().max(0.0) as i64
+    }
+
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        match self {
+            Self::Box => 1.0,
+            Self::Tent { radius } => {
+                (1.0 - dx.abs() / radius).max(0.0) * (1.0 - dy.abs() / radius).max(0.0)
+            }
+            Self::Gaussian { radius, alpha } => {
+                let gaussian =
+                    |d: f64| ((-alpha * d * d).exp() - (-alpha * radius * radius).exp()).max(0.0);
+                gaussian(dx) * gaussian(dy)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FilmPixel {
+    color_sum: Color,
+    /// Weights of the samples whose camera ray hit the world.
+    coverage_sum: f64,
+    weight_sum: f64,
+}
+
+/// Accumulates samples for a rectangular region of the image. Coordinates are
+/// in raster space of the full image (x to the right, y downwards), so that
+/// partial films rendered in parallel can be merged into the full one.
+#[derive(Debug, Clone)]
+pub struct Film {
+    x0: i64,
+    y0: i64,
+    width: usize,
+    height: usize,
+    filter: ReconstructionFilter,
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: ReconstructionFilter) -> Self {
+        Self::new_region(0, 0, width, height, filter)
+    }
+
+    pub fn new_region(
+        x0: i64,
+        y0: i64,
+        width: usize,
+        height: usize,
+        filter: ReconstructionFilter,
+    ) -> Self {
+        Self {
+            x0,
+            y0,
+            width,
+            height,
+            filter,
+            pixels: vec![FilmPixel::default(); width * height],
+        }
+    }
+
+    /// Number of pixels a sample can reach beyond the pixel it was taken in.
+    pub fn margin(&self) -> i64 {
+        self.filter.margin()
+    }
+
+    fn pixel_mut(&mut self, x: i64, y: i64) -> Option<&mut FilmPixel> {
+        let (local_x, local_y) = (x - self.x0, y - self.y0);
+        if local_x < 0
+            || local_y < 0
+            || local_x >= self.width as i64
+            || local_y >= self.height as i64
+        {
+            return None;
+        }
+        Some(&mut self.pixels[local_y as usize * self.width + local_x as usize])
+    }
+
+    /// Adds a sample taken at the continuous raster position `(x, y)`.
+    /// `covered` tells whether its camera ray hit the world, see
+    /// `resolve_coverage`.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, covered: bool) {
+        let coverage = if covered { 1.0 } else { 0.0 };
+        if let ReconstructionFilter::Box = self.filter {
+            if let Some(pixel) = self.pixel_mut(x.floor() as i64, y.floor() as i64) {
+                pixel.color_sum += color;
+                pixel.coverage_sum += coverage;
+                pixel.weight_sum += 1.0;
+            }
+            return;
+        }
+
+        let radius = self.filter.radius();
+        let (min_x, max_x) = (
+            (x - radius - 0.5).ceil() as i64,
+            (x + radius - 0.5).floor() as i64,
+        );
+        let (min_y, max_y) = (
+            (y - radius - 0.5).ceil() as i64,
+            (y + radius - 0.5).floor() as i64,
+        );
+
+        for pixel_y in min_y..=max_y {
+            for pixel_x in min_x..=max_x {
+                let weight = self
+                    .filter
+                    .weight(pixel_x as f64 + 0.5 - x, pixel_y as f64 + 0.5 - y);
+                if weight <= 0.0 {
+                    continue;
+                }
+                if let Some(pixel) = self.pixel_mut(pixel_x, pixel_y) {
+                    pixel.color_sum += weight * color;
+                    pixel.coverage_sum += weight * coverage;
+                    pixel.weight_sum += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds the accumulated samples of `other` to the overlapping pixels.
+    pub fn merge(&mut self, other: &Film) {
+        for local_y in 0..other.height {
+            for local_x in 0..other.width {
+                let source = other.pixels[local_y * other.width + local_x];
+                if let Some(pixel) =
+                    self.pixel_mut(other.x0 + local_x as i64, other.y0 + local_y as i64)
+                {
+                    pixel.color_sum += source.color_sum;
+                    pixel.coverage_sum += source.coverage_sum;
+                    pixel.weight_sum += source.weight_sum;
+                }
+            }
+        }
+    }
+
+    /// Discards the samples of all pixels outside the given region, which
+    /// then resolve to black.
+    pub fn clear_outside(&mut self, x0: i64, y0: i64, width: usize, height: usize) {
+        for local_y in 0..self.height {
+            for local_x in 0..self.width {
+                let (x, y) = (self.x0 + local_x as i64, self.y0 + local_y as i64);
+                if x < x0 || y < y0 || x >= x0 + width as i64 || y >= y0 + height as i64 {
+                    self.pixels[local_y * self.width + local_x] = FilmPixel::default();
+                }
+            }
+        }
+    }
+
+    /// Saves the accumulated samples together with the number of samples per
+    /// pixel taken so far, so the render can be continued later.
+    /// `fingerprint` identifies what was rendered, so that a checkpoint is not
+    /// resumed with a different scene. The format is the magic bytes, the
+    /// region as four little endian 64 bit integers, the sample count, the
+    /// fingerprint and then the color, coverage and weight sums of every
+    /// pixel as little endian 64 bit floats. The filter is not stored.
+    pub fn write_checkpoint<W: Write>(
+        &self,
+        mut writer: W,
+        samples_taken: usize,
+        fingerprint: u64,
+    ) -> Result<(), PathtracerError> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        for value in [
+            self.x0,
+            self.y0,
+            self.width as i64,
+            self.height as i64,
+            samples_taken as i64,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&fingerprint.to_le_bytes())?;
+        for pixel in &self.pixels {
+            for value in pixel.color_sum.e {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&pixel.coverage_sum.to_le_bytes())?;
+            writer.write_all(&pixel.weight_sum.to_le_bytes())?;
+        }
+        Ok(writer.flush()?)
+    }
+
+    /// Loads a film saved by `write_checkpoint` and returns it together with
+    /// the number of samples per pixel it holds and its fingerprint. The film
+    /// must cover the given region, which is checked before any pixels are
+    /// allocated.
+    pub fn read_checkpoint<R: Read>(
+        mut reader: R,
+        x0: i64,
+        y0: i64,
+        width: usize,
+        height: usize,
+        filter: ReconstructionFilter,
+    ) -> Result<(Self, usize, u64), PathtracerError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(PathtracerError::Parse(
+                "not a render checkpoint".to_string(),
+            ));
+        }
+
+        let mut buffer = [0; 8];
+        let mut next = || -> Result<[u8; 8], PathtracerError> {
+            reader.read_exact(&mut buffer)?;
+            Ok(buffer)
+        };
+        let mut header = [0; 5];
+        for value in &mut header {
+            *value = i64::from_le_bytes(next()?);
+        }
+        let [saved_x0, saved_y0, saved_width, saved_height, samples_taken] = header;
+        if samples_taken < 0 {
+            return Err(PathtracerError::Parse(
+                "corrupt render checkpoint".to_string(),
+            ));
+        }
+        if [saved_x0, saved_y0, saved_width, saved_height] != [x0, y0, width as i64, height as i64]
+        {
+            return Err(PathtracerError::CheckpointMismatch(format!(
+                "checkpoint covers {}x{} pixels at ({}, {}), expected {}x{} at ({}, {})",
+                saved_width, saved_height, saved_x0, saved_y0, width, height, x0, y0
+            )));
+        }
+        let fingerprint = u64::from_le_bytes(next()?);
+
+        let mut film = Self::new_region(x0, y0, width, height, filter);
+        for pixel in &mut film.pixels {
+            for value in &mut pixel.color_sum.e {
+                *value = f64::from_le_bytes(next()?);
+            }
+            pixel.coverage_sum = f64::from_le_bytes(next()?);
+            pixel.weight_sum = f64::from_le_bytes(next()?);
+        }
+        Ok((film, samples_taken as usize, fingerprint))
+    }
+
+    /// Returns the weighted average per pixel, row by row from the top.
+    pub fn resolve(&self) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                if pixel.weight_sum > 0.0 {
+                    pixel.color_sum / pixel.weight_sum
+                } else {
+                    Color::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the weighted fraction of samples per pixel whose camera ray
+    /// hit the world, row by row from the top. Used as the alpha channel for
+    /// compositing, see `renderer::to_rgba`.
+    pub fn resolve_coverage(&self) -> Vec<f64> {
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                if pixel.weight_sum > 0.0 {
+                    pixel.coverage_sum / pixel.weight_sum
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tent_splats_into_neighbors() {
+        let mut film = Film::new(3, 3, ReconstructionFilter::Tent { radius: 1.0 });
+        film.add_sample(1.5, 1.5, Color::new(1.0, 1.0, 1.0), true);
+        film.add_sample(0.75, 1.5, Color::new(0.0, 0.0, 0.0), false);
+
+        let pixels = film.resolve();
+        assert_eq!(0.0, pixels[1].x());
+        assert_eq!(0.0, pixels[3].x());
+        // center pixel: weight 1.0 for the white sample and 0.25 for the black
+        assert!((pixels[4].x() - 0.8).abs() < 1e-9);
+        // coverage is weighted the same way
+        let coverage = film.resolve_coverage();
+        assert_eq!(0.0, coverage[3]);
+        assert!((coverage[4] - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn filters_favor_samples_near_the_center() {
+        for filter in [
+            ReconstructionFilter::Tent { radius: 1.0 },
+            ReconstructionFilter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            },
+        ] {
+            let center = filter.weight(0.0, 0.0);
+            let corner = filter.weight(0.5, 0.5);
+            assert!(center > corner && corner > 0.0, "{:?}", filter);
+            assert_eq!(0.0, filter.weight(filter.radius(), 0.0));
+        }
+        let tent = ReconstructionFilter::Tent { radius: 1.0 };
+        assert_eq!(0.25, tent.weight(0.5, 0.5) / tent.weight(0.0, 0.0));
+
+        // a corner sample counts for less than a center one in the average
+        let mut film = Film::new(1, 1, tent);
+        film.add_sample(0.5, 0.5, Color::new(1.0, 1.0, 1.0), true);
+        film.add_sample(0.0, 0.0, Color::new(0.0, 0.0, 0.0), true);
+        assert!((film.resolve()[0].x() - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merged_regions_match_single_film() {
+        let filter = ReconstructionFilter::Tent { radius: 1.5 };
+        let samples = [(0.2, 0.3), (2.7, 1.1), (1.5, 3.9), (3.3, 2.2)];
+
+        let mut single = Film::new(4, 4, filter);
+        let mut merged = Film::new(4, 4, filter);
+        for (index, &(x, y)) in samples.iter().enumerate() {
+            let color = Color::new(index as f64, 1.0, 0.5);
+            single.add_sample(x, y, color, index % 2 == 0);
+
+            let margin = merged.margin();
+            let row = y as i64;
+            let mut strip = Film::new_region(0, row - margin, 4, 1 + 2 * margin as usize, filter);
+            strip.add_sample(x, y, color, index % 2 == 0);
+            merged.merge(&strip);
+        }
+
+        for (a, b) in single.resolve().iter().zip(merged.resolve()) {
+            assert!((*a - b).near_zero());
+        }
+        for (a, b) in single
+            .resolve_coverage()
+            .iter()
+            .zip(merged.resolve_coverage())
+        {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let filter = ReconstructionFilter::Tent { radius: 1.0 };
+        let mut film = Film::new_region(1, 2, 3, 2, filter);
+        film.add_sample(2.5, 2.5, Color::new(0.25, 0.5, 4.0), true);
+        film.add_sample(1.2, 3.7, Color::new(1.0, 0.0, 0.5), false);
+
+        let mut bytes = vec![];
+        film.write_checkpoint(&mut bytes, 7, u64::MAX - 1).unwrap();
+        let (loaded, samples_taken, fingerprint) =
+            Film::read_checkpoint(&bytes[..], 1, 2, 3, 2, filter).unwrap();
+
+        assert_eq!(7, samples_taken);
+        assert_eq!(u64::MAX - 1, fingerprint);
+        assert_eq!(
+            (1, 2, 3, 2),
+            (loaded.x0, loaded.y0, loaded.width, loaded.height)
+        );
+        for (a, b) in film.resolve().iter().zip(loaded.resolve()) {
+            assert_eq!(a.e, b.e);
+        }
+        assert_eq!(film.resolve_coverage(), loaded.resolve_coverage());
+        assert!(Film::read_checkpoint(&bytes[..20], 1, 2, 3, 2, filter).is_err());
+        assert!(Film::read_checkpoint(&b"PTFILM2\n"[..], 1, 2, 3, 2, filter).is_err());
+        assert!(matches!(
+            Film::read_checkpoint(&bytes[..], 0, 2, 3, 2, filter),
+            Err(PathtracerError::CheckpointMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn checkpoints_with_absurd_sizes_are_rejected() {
+        let mut bytes = CHECKPOINT_MAGIC.to_vec();
+        for value in [0, 0, i64::MAX, i64::MAX, 1, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let result = Film::read_checkpoint(&bytes[..], 0, 0, 4, 4, ReconstructionFilter::Box);
+        assert!(matches!(
+            result,
+            Err(PathtracerError::CheckpointMismatch(_))
+        ));
+    }
+}
@@ -4,7 +4,16 @@ use std::{
     sync::Arc,
 };
 
-use crate::{bvh::Aabb, material::Material, ray::Ray, vec3::Vec3};
+use rand::{Rng, RngCore};
+
+use crate::{
+    bvh::Aabb,
+    material::{IsotropicMaterial, Material},
+    ray::Ray,
+    sampler::splitmix64,
+    transformation::Matrix4x4,
+    vec3::{Color, OrthonormalBasis, Vec3},
+};
 
 pub struct HitRecord<'a> {
     pub t: f64,
@@ -14,6 +23,9 @@ pub struct HitRecord<'a> {
     pub v: f64,
     pub front_face: bool,
     pub material: &'a dyn Material,
+    /// Direction in which `u` increases along the surface, if the primitive
+    /// knows it. Used to orient normal maps.
+    pub tangent: Option<Vec3>,
 }
 
 impl<'a> HitRecord<'a> {
@@ -39,41 +51,162 @@ impl<'a> HitRecord<'a> {
             v,
             front_face,
             material,
+            tangent: None,
+        }
+    }
+
+    pub fn with_tangent(mut self, tangent: Vec3) -> Self {
+        if !tangent.near_zero() {
+            self.tangent = Some(tangent.unit_vector());
         }
+        self
     }
 }
 
-pub trait Hittable: Sync + Send + CloneHittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+pub trait Hittable: Sync + Send {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+    /// Like `hit`, for objects which need random numbers to decide whether
+    /// they are hit, like `ConstantMedium`. They take them from `rng`, so that
+    /// seeded renders stay reproducible. Containers pass `rng` on to their
+    /// children.
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        _rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
     fn bounding_box(&self) -> Aabb;
+
+    /// Surface area, used together with `emitted_power` to weigh lights.
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    /// Total power emitted by the surface (radiance × area × π).
+    fn emitted_power(&self) -> Color {
+        Color::default()
+    }
+
+    /// Probability density, per solid angle, of `random` returning
+    /// `direction` when called from `origin`. Zero for objects which cannot
+    /// be sampled as lights. `origin` lies on another surface than the
+    /// object, so it is searched for from `t = 0` on, without an epsilon that
+    /// would only suit some scales.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// A random direction from `origin` towards a point on the object.
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    /// Whether `pdf_value` and `random` are implemented, so that the object
+    /// can be sampled as a light.
+    fn is_sampleable(&self) -> bool {
+        false
+    }
+
+    /// Adds the emitting objects inside of this one to `lights`. Containers
+    /// hold their children in `Arc`s, so they are the ones adding them.
+    fn collect_lights(&self, _lights: &mut Vec<Arc<dyn Hittable>>) {}
+}
+
+/// Adds every child emitting light to `lights`, unless it is a container
+/// which already added the emitters inside of it. Emitters which can not be
+/// sampled are left out, they are only found by scattered rays.
+pub(crate) fn collect_lights_of(
+    children: &[Arc<dyn Hittable>],
+    lights: &mut Vec<Arc<dyn Hittable>>,
+) {
+    for child in children {
+        let count = lights.len();
+        child.collect_lights(lights);
+        let power = child.emitted_power();
+        if lights.len() == count && power.x() + power.y() + power.z() > 0.0 && child.is_sampleable()
+        {
+            lights.push(child.clone());
+        }
+    }
 }
 
-pub trait CloneHittable {
-    fn clone_hittable(&self) -> Box<dyn Hittable>;
+/// Total power emitted by a surface of `area` which emits the average
+/// radiance of `material` in every direction (radiance × area × π).
+fn lambertian_power(area: f64, material: &dyn Material) -> Color {
+    std::f64::consts::PI * area * material.average_emission()
 }
 
-impl<T> CloneHittable for T
-where
-    T: Hittable + Clone + 'static,
-{
-    fn clone_hittable(&self) -> Box<dyn Hittable> {
-        Box::new(self.clone())
+/// Density per solid angle of sampling `direction` from `origin` by picking
+/// a point uniformly on the flat `light` with the given geometric `normal`
+/// and `area`. Zero if `direction` misses the light.
+fn area_light_pdf(
+    light: &impl Hittable,
+    origin: Vec3,
+    direction: Vec3,
+    normal: Vec3,
+    area: f64,
+) -> f64 {
+    match light.hit(&Ray::new(origin, direction), 0.0, f64::INFINITY) {
+        Some(hit_record) => {
+            // convert the uniform density over the area to solid angle
+            let distance_squared = hit_record.t * hit_record.t * direction.len_squared();
+            let cosine = direction.unit_vector().dot(normal).abs();
+            distance_squared / (cosine * area)
+        }
+        None => 0.0,
     }
 }
 
-impl Clone for Box<dyn Hittable> {
-    fn clone(&self) -> Self {
-        self.clone_hittable()
+/// Objects which are hit by testing every one of them, without a BVH. Good
+/// for a handful of objects, like the sides of an `AABox`.
+#[derive(Clone, Default)]
+pub struct HittableList {
+    objects: Vec<Arc<dyn Hittable>>,
+}
+
+impl HittableList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.objects.push(object);
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Box around all objects, or `None` for an empty list. As a `Hittable`
+    /// an empty list has an empty box at the origin.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .map(|object| object.bounding_box())
+            .reduce(|output_box, object_box| output_box.surrounding_box(&object_box))
     }
 }
 
-impl Hittable for Vec<Box<dyn Hittable>> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl HittableList {
+    /// The closest of the hits `hit` finds on the objects, each called with
+    /// the distance to the closest hit so far.
+    fn closest_hit<'a>(
+        &'a self,
+        t_max: f64,
+        mut hit: impl FnMut(&'a dyn Hittable, f64) -> Option<HitRecord<'a>>,
+    ) -> Option<HitRecord<'a>> {
         let mut closest_so_far = t_max;
         let mut result_record = None;
 
-        for object in self {
-            if let Some(hit_record) = object.hit(ray, t_min, closest_so_far) {
+        for object in &self.objects {
+            if let Some(hit_record) = hit(&**object, closest_so_far) {
                 closest_so_far = hit_record.t;
                 result_record = Some(hit_record);
             }
@@ -81,23 +214,48 @@ impl Hittable for Vec<Box<dyn Hittable>> {
 
         result_record
     }
+}
+
+impl From<Vec<Arc<dyn Hittable>>> for HittableList {
+    fn from(objects: Vec<Arc<dyn Hittable>>) -> Self {
+        Self { objects }
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.closest_hit(t_max, |object, t_max| object.hit(ray, t_min, t_max))
+    }
+
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        self.closest_hit(t_max, |object, t_max| {
+            object.hit_with_rng(ray, t_min, t_max, rng)
+        })
+    }
 
     fn bounding_box(&self) -> Aabb {
-        if self.is_empty() {
-            panic!("called bounding box on empty hittable list");
-        }
+        HittableList::bounding_box(self)
+            .unwrap_or_else(|| Aabb::new(Vec3::default(), Vec3::default()))
+    }
 
-        let mut output_box: Option<Aabb> = None;
+    fn area(&self) -> f64 {
+        self.objects.iter().map(|object| object.area()).sum()
+    }
 
-        for object in self {
-            if let Some(existing_box) = output_box {
-                output_box = Some(existing_box.surrounding_box(&object.bounding_box()));
-            } else {
-                output_box = Some(object.bounding_box());
-            }
-        }
+    fn emitted_power(&self) -> Color {
+        self.objects.iter().fold(Color::default(), |power, object| {
+            power + object.emitted_power()
+        })
+    }
 
-        output_box.expect("could not construct bounding box for hittable list")
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        collect_lights_of(&self.objects, lights);
     }
 }
 
@@ -117,8 +275,14 @@ impl Sphere {
         }
     }
 
+    /// Equirectangular coordinates of a point on the unit sphere: `u` goes
+    /// around the y axis starting at -x, towards +z, and `v` from the bottom
+    /// pole (0) to the top one (1), so images map right side up and are not
+    /// mirrored when seen from outside.
     fn get_sphere_uv(point: Vec3) -> (f64, f64) {
-        let theta = point.y().neg().acos();
+        // rounding can push normals at the poles slightly past 1, where acos
+        // would return NaN
+        let theta = point.y().neg().clamp(-1.0, 1.0).acos();
         let phi = point.z().neg().atan2(point.x()) + std::f64::consts::PI;
 
         (
@@ -129,7 +293,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direction.len_squared();
         let half_b = ray.direction.dot(oc);
@@ -151,6 +315,138 @@ impl Hittable for Sphere {
         let outward_normal = (point - self.center) / self.radius;
         let (u, v) = Self::get_sphere_uv(outward_normal);
 
+        Some(
+            HitRecord::new(root, point, ray, outward_normal, u, v, &*self.material)
+                // u runs around the y axis
+                .with_tangent(Vec3::new(outward_normal.z(), 0.0, -outward_normal.x())),
+        )
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            self.center - Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+            self.center + Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+        )
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * std::f64::consts::PI * self.radius * self.radius
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        if self
+            .hit(&Ray::new(origin, direction), 0.0, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        // uniform over the cone of directions covered by the sphere
+        let distance_squared = (self.center - origin).len_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.len_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Vec3::random_on_unitsphere(rng);
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let cos_theta = 1.0 + rng.gen::<f64>() * (cos_theta_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+
+        let w = direction.unit_vector();
+        let OrthonormalBasis {
+            u: u_axis,
+            v: v_axis,
+            ..
+        } = OrthonormalBasis::from_normal(w);
+        phi.cos() * sin_theta * u_axis + phi.sin() * sin_theta * v_axis + cos_theta * w
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+}
+
+/// A sphere moving linearly from `center0` at `time0` to `center1` at
+/// `time1`, rendered with motion blur if the camera shutter is open for a
+/// while. Outside of the interval it keeps moving along the same line.
+#[derive(Clone)]
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let fraction = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + fraction * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
+        let a = ray.direction.len_squared();
+        let half_b = ray.direction.dot(oc);
+        let c = oc.len_squared() - self.radius * self.radius;
+        let dis = half_b * half_b - a * c;
+        if dis < 0.0 {
+            return None;
+        }
+
+        let mut root = (-half_b - dis.sqrt()) / a;
+        if root < t_min || root > t_max {
+            root = (-half_b + dis.sqrt()) / a;
+            if root < t_min || root > t_max {
+                return None;
+            }
+        }
+
+        let point = ray.at(root);
+        let outward_normal = (point - center) / self.radius;
+        let (u, v) = Sphere::get_sphere_uv(outward_normal);
+
         Some(HitRecord::new(
             root,
             point,
@@ -162,12 +458,102 @@ impl Hittable for Sphere {
         ))
     }
 
+    /// Covers the sphere during the shutter interval `[time0, time1]`.
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Aabb::new(self.center0 - extent, self.center0 + extent)
+            .surrounding_box(&Aabb::new(self.center1 - extent, self.center1 + extent))
+    }
+}
+
+/// A sphere cut off below `z_min` and above `z_max`, measured along z from its
+/// center, like PBRT's `zmin`/`zmax` sphere parameters. Through the cuts the
+/// inside of the sphere is visible.
+#[derive(Clone)]
+pub struct PartialSphere {
+    center: Vec3,
+    radius: f64,
+    z_min: f64,
+    z_max: f64,
+    material: Arc<dyn Material>,
+}
+
+impl PartialSphere {
+    pub fn new(
+        center: Vec3,
+        radius: f64,
+        z_min: f64,
+        z_max: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let radius = radius.abs();
+        let (z_min, z_max) = (
+            z_min.min(z_max).clamp(-radius, radius),
+            z_min.max(z_max).clamp(-radius, radius),
+        );
+
+        Self {
+            center,
+            radius,
+            z_min,
+            z_max,
+            material,
+        }
+    }
+}
+
+impl Hittable for PartialSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let oc = ray.origin - self.center;
+        let a = ray.direction.len_squared();
+        let half_b = ray.direction.dot(oc);
+        let c = oc.len_squared() - self.radius * self.radius;
+        let dis = half_b * half_b - a * c;
+        if dis < 0.0 {
+            return None;
+        }
+
+        // The nearer intersection may be cut away, in which case the ray can
+        // still hit the inside of the far wall.
+        [(-half_b - dis.sqrt()) / a, (-half_b + dis.sqrt()) / a]
+            .into_iter()
+            .filter(|root| (t_min..=t_max).contains(root))
+            .find_map(|root| {
+                let point = ray.at(root);
+                let z = point.z() - self.center.z();
+                if z < self.z_min || z > self.z_max {
+                    return None;
+                }
+
+                let outward_normal = (point - self.center) / self.radius;
+                let (u, v) = Sphere::get_sphere_uv(outward_normal);
+                Some(HitRecord::new(
+                    root,
+                    point,
+                    ray,
+                    outward_normal,
+                    u,
+                    v,
+                    &*self.material,
+                ))
+            })
+    }
+
     fn bounding_box(&self) -> Aabb {
         Aabb::new(
-            self.center - Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
-            self.center + Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+            self.center + Vec3::new(-self.radius, -self.radius, self.z_min),
+            self.center + Vec3::new(self.radius, self.radius, self.z_max),
         )
     }
+
+    fn area(&self) -> f64 {
+        // area of a spherical zone only depends on its height
+        2.0 * std::f64::consts::PI * self.radius * (self.z_max - self.z_min)
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
 }
 
 #[derive(Debug)]
@@ -207,7 +593,7 @@ impl RectangleXY {
 }
 
 impl Hittable for RectangleXY {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let t = (self.start.z() - ray.origin.z()) / ray.direction.z();
         if t < t_min || t > t_max {
             return None;
@@ -235,11 +621,43 @@ impl Hittable for RectangleXY {
         // In theory this bounding box is infinitly thin, but we pad it a bit.
         // Also, using start in both arguments is correct, since we want to
         // force the axis alignment.
-        Aabb::new(
-            Vec3::new(self.start.x(), self.start.y(), self.start.z() - 0.0001),
-            Vec3::new(self.end.x(), self.end.y(), self.start.z() + 0.0001),
+        let bbox = Aabb::new(
+            self.start,
+            Vec3::new(self.end.x(), self.end.y(), self.start.z()),
+        );
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        (self.end.x() - self.start.x()) * (self.end.y() - self.start.y())
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_light_pdf(
+            self,
+            origin,
+            direction,
+            Vec3::new(0.0, 0.0, self.direction),
+            self.area(),
         )
     }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = Vec3::new(
+            self.start.x() + rng.gen::<f64>() * (self.end.x() - self.start.x()),
+            self.start.y() + rng.gen::<f64>() * (self.end.y() - self.start.y()),
+            self.start.z(),
+        );
+        point - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
@@ -270,7 +688,7 @@ impl RectangleXZ {
 }
 
 impl Hittable for RectangleXZ {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let t = (self.start.y() - ray.origin.y()) / ray.direction.y();
         if t < t_min || t > t_max {
             return None;
@@ -298,11 +716,43 @@ impl Hittable for RectangleXZ {
         // In theory this bounding box is infinitly thin, but we pad it a bit.
         // Also, using start in both arguments is correct, since we want to
         // force the axis alignment.
-        Aabb::new(
-            Vec3::new(self.start.x(), self.start.y() - 0.0001, self.start.z()),
-            Vec3::new(self.end.x(), self.start.y() + 0.0001, self.end.z()),
+        let bbox = Aabb::new(
+            self.start,
+            Vec3::new(self.end.x(), self.start.y(), self.end.z()),
+        );
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        (self.end.x() - self.start.x()) * (self.end.z() - self.start.z())
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_light_pdf(
+            self,
+            origin,
+            direction,
+            Vec3::new(0.0, self.direction, 0.0),
+            self.area(),
         )
     }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = Vec3::new(
+            self.start.x() + rng.gen::<f64>() * (self.end.x() - self.start.x()),
+            self.start.y(),
+            self.start.z() + rng.gen::<f64>() * (self.end.z() - self.start.z()),
+        );
+        point - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
@@ -333,7 +783,7 @@ impl RectangleYZ {
 }
 
 impl Hittable for RectangleYZ {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let t = (self.start.x() - ray.origin.x()) / ray.direction.x();
         if t < t_min || t > t_max {
             return None;
@@ -361,18 +811,50 @@ impl Hittable for RectangleYZ {
         // In theory this bounding box is infinitly thin, but we pad it a bit.
         // Also, using start in both arguments is correct, since we want to
         // force the axis alignment.
-        Aabb::new(
-            Vec3::new(self.start.x() - 0.0001, self.start.y(), self.start.z()),
-            Vec3::new(self.start.x() + 0.0001, self.end.y(), self.end.z()),
+        let bbox = Aabb::new(
+            self.start,
+            Vec3::new(self.start.x(), self.end.y(), self.end.z()),
+        );
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        (self.end.y() - self.start.y()) * (self.end.z() - self.start.z())
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_light_pdf(
+            self,
+            origin,
+            direction,
+            Vec3::new(self.direction, 0.0, 0.0),
+            self.area(),
         )
     }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = Vec3::new(
+            self.start.x(),
+            self.start.y() + rng.gen::<f64>() * (self.end.y() - self.start.y()),
+            self.start.z() + rng.gen::<f64>() * (self.end.z() - self.start.z()),
+        );
+        point - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct AABox {
     minimum: Vec3,
     maximum: Vec3,
-    sides: Vec<Box<dyn Hittable>>,
+    sides: HittableList,
 }
 
 impl AABox {
@@ -388,8 +870,8 @@ impl AABox {
             start.z().max(end.z()),
         );
 
-        let sides: Vec<Box<dyn Hittable>> = vec![
-            Box::new(
+        let sides: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(
                 RectangleXY::new(
                     Vec3::new(minimum.x(), minimum.y(), minimum.z()),
                     Vec3::new(maximum.x(), maximum.y(), minimum.z()),
@@ -398,7 +880,7 @@ impl AABox {
                 )
                 .expect("rectangle definition is not axis aligned"),
             ),
-            Box::new(
+            Arc::new(
                 RectangleXY::new(
                     Vec3::new(minimum.x(), minimum.y(), maximum.z()),
                     Vec3::new(maximum.x(), maximum.y(), maximum.z()),
@@ -407,7 +889,7 @@ impl AABox {
                 )
                 .expect("rectangle definition is not axis aligned"),
             ),
-            Box::new(
+            Arc::new(
                 RectangleXZ::new(
                     Vec3::new(minimum.x(), minimum.y(), minimum.z()),
                     Vec3::new(maximum.x(), minimum.y(), maximum.z()),
@@ -416,7 +898,7 @@ impl AABox {
                 )
                 .expect("rectangle definition is not axis aligned"),
             ),
-            Box::new(
+            Arc::new(
                 RectangleXZ::new(
                     Vec3::new(minimum.x(), maximum.y(), minimum.z()),
                     Vec3::new(maximum.x(), maximum.y(), maximum.z()),
@@ -425,7 +907,7 @@ impl AABox {
                 )
                 .expect("rectangle definition is not axis aligned"),
             ),
-            Box::new(
+            Arc::new(
                 RectangleYZ::new(
                     Vec3::new(minimum.x(), minimum.y(), minimum.z()),
                     Vec3::new(minimum.x(), maximum.y(), maximum.z()),
@@ -434,7 +916,7 @@ impl AABox {
                 )
                 .expect("rectangle definition is not axis aligned"),
             ),
-            Box::new(
+            Arc::new(
                 RectangleYZ::new(
                     Vec3::new(maximum.x(), minimum.y(), minimum.z()),
                     Vec3::new(maximum.x(), maximum.y(), maximum.z()),
@@ -448,19 +930,31 @@ impl AABox {
         Self {
             minimum,
             maximum,
-            sides,
+            sides: sides.into(),
         }
     }
 }
 
 impl Hittable for AABox {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         self.sides.hit(ray, t_min, t_max)
     }
 
     fn bounding_box(&self) -> Aabb {
         Aabb::new(self.minimum, self.maximum)
     }
+
+    fn area(&self) -> f64 {
+        self.sides.area()
+    }
+
+    fn emitted_power(&self) -> Color {
+        self.sides.emitted_power()
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        self.sides.collect_lights(lights);
+    }
 }
 
 #[derive(Clone)]
@@ -469,6 +963,11 @@ pub struct Triangle {
     point2: Vec3,
     point3: Vec3,
     normal: Vec3,
+    /// Normals at the three points, interpolated across the face for smooth
+    /// shading.
+    vertex_normals: Option<[Vec3; 3]>,
+    /// Texture coordinates at the three points.
+    texture_coordinates: Option<[(f64, f64); 3]>,
     material: Arc<dyn Material>,
 }
 
@@ -495,6 +994,8 @@ impl Triangle {
             point2,
             point3,
             normal,
+            vertex_normals: None,
+            texture_coordinates: None,
             material,
         }
     }
@@ -510,13 +1011,25 @@ impl Triangle {
             point2,
             point3,
             normal: (point2 - point1).cross(point3 - point1).unit_vector(),
+            vertex_normals: None,
+            texture_coordinates: None,
             material,
         }
     }
+
+    pub fn with_vertex_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.vertex_normals = Some(normals.map(|normal| normal.unit_vector()));
+        self
+    }
+
+    pub fn with_texture_coordinates(mut self, coordinates: [(f64, f64); 3]) -> Self {
+        self.texture_coordinates = Some(coordinates);
+        self
+    }
 }
 
 impl Hittable for Triangle {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         // Adapted from https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection.html
         let v0v1 = self.point2 - self.point1;
         let v0v2 = self.point3 - self.point1;
@@ -547,43 +1060,1508 @@ impl Hittable for Triangle {
 
         let p = ray.at(t);
 
-        Some(HitRecord::new(
-            t,
-            p,
-            ray,
-            self.normal,
-            0.0,
-            0.0,
-            &*self.material,
-        ))
+        // u and v weigh point2 and point3, the rest goes to point1
+        let w = 1.0 - u - v;
+        let normal = match self.vertex_normals {
+            Some([n1, n2, n3]) => (w * n1 + u * n2 + v * n3).unit_vector(),
+            None => self.normal,
+        };
+        let (texture_u, texture_v) = match self.texture_coordinates {
+            Some([(u1, v1), (u2, v2), (u3, v3)]) => {
+                (w * u1 + u * u2 + v * u3, w * v1 + u * v2 + v * v3)
+            }
+            None => (0.0, 0.0),
+        };
+
+        let hit_record = HitRecord::new(t, p, ray, normal, texture_u, texture_v, &*self.material);
+        Some(match self.texture_coordinates {
+            Some([(u1, v1), (u2, v2), (u3, v3)]) => {
+                // solve for the derivative of the position along u
+                let (du1, dv1, du2, dv2) = (u2 - u1, v2 - v1, u3 - u1, v3 - v1);
+                let determinant = du1 * dv2 - du2 * dv1;
+                if determinant.abs() < 1e-12 {
+                    hit_record
+                } else {
+                    hit_record.with_tangent((dv2 * v0v1 - dv1 * v0v2) / determinant)
+                }
+            }
+            None => hit_record,
+        })
     }
 
     fn bounding_box(&self) -> Aabb {
-        let mut minimum = Vec3::new(
-            self.point1.x().min(self.point2.x()).min(self.point3.x()),
-            self.point1.y().min(self.point2.y()).min(self.point3.y()),
-            self.point1.z().min(self.point2.z()).min(self.point3.z()),
-        );
-        let mut maximum = Vec3::new(
-            self.point1.x().max(self.point2.x()).max(self.point3.x()),
-            self.point1.y().max(self.point2.y()).max(self.point3.y()),
-            self.point1.z().max(self.point2.z()).max(self.point3.z()),
+        let bbox = Aabb::new(
+            self.point1.min(self.point2).min(self.point3),
+            self.point1.max(self.point2).max(self.point3),
         );
 
-        // In theory this bounding box is infinitly thin, but we pad it a bit.
-        if minimum.x() == maximum.x() {
-            minimum += Vec3::new(-0.001, 0.0, 0.0);
-            maximum += Vec3::new(0.001, 0.0, 0.0);
+        // In theory this bounding box can be infinitly thin, but we pad it a
+        // bit.
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        0.5 * (self.point2 - self.point1)
+            .cross(self.point3 - self.point1)
+            .len()
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        // the normal of the hit may be interpolated from the vertices
+        area_light_pdf(self, origin, direction, self.normal, self.area())
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        // points beyond the diagonal are folded back into the triangle
+        let (mut a, mut b) = (rng.gen::<f64>(), rng.gen::<f64>());
+        if a + b > 1.0 {
+            (a, b) = (1.0 - a, 1.0 - b);
+        }
+        self.point1 + a * (self.point2 - self.point1) + b * (self.point3 - self.point1) - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+}
+
+/// Half the extent of the bounding box of a `Plane`. Large enough to cover any
+/// reasonable scene, but finite so the BVH can still work with it.
+const PLANE_EXTENT: f64 = 1.0e6;
+/// Padding of the bounding box of a `Plane`. `Aabb::padding` grows with the
+/// box diagonal, which for `PLANE_EXTENT` would make the box several units
+/// thick, so planes use this fixed padding instead.
+const PLANE_PADDING: f64 = 1.0e-4;
+
+/// An infinite plane through `point`, e.g. as a ground floor. The texture
+/// coordinates are the projected world coordinates, repeating once per unit.
+#[derive(Clone)]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    u_axis: Vec3,
+    v_axis: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        let normal = normal.unit_vector();
+        let OrthonormalBasis {
+            u: u_axis,
+            v: v_axis,
+            ..
+        } = OrthonormalBasis::from_normal(normal);
+
+        Self {
+            point,
+            normal,
+            u_axis,
+            v_axis,
+            material,
         }
-        if minimum.y() == maximum.y() {
-            minimum += Vec3::new(0.0, -0.001, 0.0);
-            maximum += Vec3::new(0.0, 0.001, 0.0);
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
         }
-        if minimum.z() == maximum.z() {
-            minimum += Vec3::new(0.0, 0.0, -0.001);
-            maximum += Vec3::new(0.0, 0.0, 0.001);
+
+        let t = (self.point - ray.origin).dot(self.normal) / denominator;
+        if t < t_min || t > t_max {
+            return None;
         }
 
-        Aabb::new(minimum, maximum)
+        let point = ray.at(t);
+        let offset = point - self.point;
+
+        Some(HitRecord::new(
+            t,
+            point,
+            ray,
+            self.normal,
+            offset.dot(self.u_axis).rem_euclid(1.0),
+            offset.dot(self.v_axis).rem_euclid(1.0),
+            &*self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Along an axis the plane is perpendicular to, the box is only as
+        // thick as its padding.
+        let mut minimum = Vec3::default();
+        let mut maximum = Vec3::default();
+        for axis in 0..3 {
+            if (self.normal[axis].abs() - 1.0).abs() < 1e-12 {
+                minimum[axis] = self.point[axis];
+                maximum[axis] = self.point[axis];
+            } else {
+                minimum[axis] = -PLANE_EXTENT;
+                maximum[axis] = PLANE_EXTENT;
+            }
+        }
+
+        Aabb::new(minimum, maximum).pad(PLANE_PADDING)
+    }
+}
+
+/// A flat circular disk. `u` is the angle around the center, `v` the distance
+/// from it, both scaled to [0, 1].
+#[derive(Clone)]
+pub struct Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f64,
+    u_axis: Vec3,
+    v_axis: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        let normal = normal.unit_vector();
+        let OrthonormalBasis {
+            u: u_axis,
+            v: v_axis,
+            ..
+        } = OrthonormalBasis::from_normal(normal);
+
+        Self {
+            center,
+            normal,
+            radius: radius.abs(),
+            u_axis,
+            v_axis,
+            material,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.center - ray.origin).dot(self.normal) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let distance = offset.len();
+        if distance > self.radius {
+            return None;
+        }
+
+        let angle = offset.dot(self.v_axis).atan2(offset.dot(self.u_axis));
+        Some(HitRecord::new(
+            t,
+            point,
+            ray,
+            self.normal,
+            angle.rem_euclid(2.0 * std::f64::consts::PI) / (2.0 * std::f64::consts::PI),
+            distance / self.radius,
+            &*self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // extent of the rim along each axis, padded so the box is never flat
+        let mut extent = Vec3::default();
+        for axis in 0..3 {
+            extent[axis] = self.radius * (1.0 - self.normal[axis].powi(2)).max(0.0).sqrt();
+        }
+
+        let bbox = Aabb::new(self.center - extent, self.center + extent);
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.radius * self.radius
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_light_pdf(self, origin, direction, self.normal, self.area())
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let radius = self.radius * rng.gen::<f64>().sqrt();
+        let angle = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        self.center + radius * (angle.cos() * self.u_axis + angle.sin() * self.v_axis) - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+}
+
+/// A parallelogram spanned by the edges `u` and `v` from the corner `q`. The
+/// texture coordinates run from 0 to 1 along both edges, and the normal
+/// points along `u × v`.
+#[derive(Clone)]
+pub struct Quad {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    /// `n / (n · n)` with `n = u × v`, turns a point in the plane into its
+    /// coordinates along the edges.
+    w: Vec3,
+    normal: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        Self {
+            q,
+            u,
+            v,
+            w: n / n.len_squared(),
+            normal: n.unit_vector(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.q - ray.origin).dot(self.normal) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let planar = point - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(
+            HitRecord::new(t, point, ray, self.normal, alpha, beta, &*self.material)
+                .with_tangent(self.u),
+        )
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let minimum = corners.iter().fold(self.q, |minimum, &c| minimum.min(c));
+        let maximum = corners.iter().fold(self.q, |maximum, &c| maximum.max(c));
+        // padded so the box is never flat
+        let bbox = Aabb::new(minimum, maximum);
+        bbox.pad(bbox.padding())
+    }
+
+    fn area(&self) -> f64 {
+        self.u.cross(self.v).len()
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_light_pdf(self, origin, direction, self.normal, self.area())
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        self.q + rng.gen::<f64>() * self.u + rng.gen::<f64>() * self.v - origin
+    }
+
+    fn is_sampleable(&self) -> bool {
+        true
+    }
+}
+
+/// Angle around the y axis, scaled to [0, 1].
+fn angle_around_y(point: Vec3) -> f64 {
+    (point.z().neg().atan2(point.x()) + std::f64::consts::PI) / (2.0 * std::f64::consts::PI)
+}
+
+/// Hit of a ray with the cap at height `y` of a shape around the y axis.
+fn cap_hit(ray: &Ray, y: f64, radius: f64) -> Option<f64> {
+    if ray.direction.y().abs() < 1e-12 {
+        return None;
+    }
+
+    let t = (y - ray.origin.y()) / ray.direction.y();
+    let point = ray.at(t);
+    if point.x() * point.x() + point.z() * point.z() > radius * radius {
+        return None;
+    }
+    Some(t)
+}
+
+/// Roots of `a t² + 2 half_b t + c`, smaller first.
+fn quadratic_roots(a: f64, half_b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < 1e-12 {
+        if half_b.abs() < 1e-12 {
+            return vec![];
+        }
+        return vec![-c / (2.0 * half_b)];
+    }
+
+    let dis = half_b * half_b - a * c;
+    if dis < 0.0 {
+        return vec![];
+    }
+    let mut roots = vec![(-half_b - dis.sqrt()) / a, (-half_b + dis.sqrt()) / a];
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// A cylinder around the y axis between `y_min` and `y_max`, optionally closed
+/// with caps. `u` is the angle around the axis, `v` the relative height.
+#[derive(Clone)]
+pub struct Cylinder {
+    radius: f64,
+    y_min: f64,
+    y_max: f64,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        radius: f64,
+        y_min: f64,
+        y_max: f64,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            radius: radius.abs(),
+            y_min: y_min.min(y_max),
+            y_max: y_min.max(y_max),
+            capped,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (ray.origin, ray.direction);
+        let mut candidates: Vec<(f64, Vec3)> = quadratic_roots(
+            d.x() * d.x() + d.z() * d.z(),
+            o.x() * d.x() + o.z() * d.z(),
+            o.x() * o.x() + o.z() * o.z() - self.radius * self.radius,
+        )
+        .into_iter()
+        .filter_map(|t| {
+            let point = ray.at(t);
+            (self.y_min..=self.y_max)
+                .contains(&point.y())
+                .then(|| (t, Vec3::new(point.x(), 0.0, point.z()) / self.radius))
+        })
+        .collect();
+
+        if self.capped {
+            candidates.extend(
+                cap_hit(ray, self.y_min, self.radius).map(|t| (t, Vec3::new(0.0, -1.0, 0.0))),
+            );
+            candidates.extend(
+                cap_hit(ray, self.y_max, self.radius).map(|t| (t, Vec3::new(0.0, 1.0, 0.0))),
+            );
+        }
+
+        let (t, outward_normal) = candidates
+            .into_iter()
+            .filter(|(t, _)| (t_min..=t_max).contains(t))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        let point = ray.at(t);
+
+        Some(HitRecord::new(
+            t,
+            point,
+            ray,
+            outward_normal,
+            angle_around_y(point),
+            (point.y() - self.y_min) / (self.y_max - self.y_min),
+            &*self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Vec3::new(-self.radius, self.y_min, -self.radius),
+            Vec3::new(self.radius, self.y_max, self.radius),
+        )
+    }
+
+    fn area(&self) -> f64 {
+        let side = 2.0 * std::f64::consts::PI * self.radius * (self.y_max - self.y_min);
+        match self.capped {
+            true => side + 2.0 * std::f64::consts::PI * self.radius * self.radius,
+            false => side,
+        }
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+}
+
+/// A cone around the y axis with its base of `radius` at `y_min` and its apex
+/// at `y_max`, optionally closed at the base. Texture coordinates work like
+/// for `Cylinder`.
+#[derive(Clone)]
+pub struct Cone {
+    radius: f64,
+    y_min: f64,
+    y_max: f64,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(
+        radius: f64,
+        y_min: f64,
+        y_max: f64,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            radius: radius.abs(),
+            y_min: y_min.min(y_max),
+            y_max: y_min.max(y_max),
+            capped,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // radius shrinks by `slope` per unit of height towards the apex
+        let slope = self.radius / (self.y_max - self.y_min);
+        let slope2 = slope * slope;
+        let (o, d) = (ray.origin, ray.direction);
+        let to_apex = self.y_max - o.y();
+
+        let mut candidates: Vec<(f64, Vec3)> = quadratic_roots(
+            d.x() * d.x() + d.z() * d.z() - slope2 * d.y() * d.y(),
+            o.x() * d.x() + o.z() * d.z() + slope2 * to_apex * d.y(),
+            o.x() * o.x() + o.z() * o.z() - slope2 * to_apex * to_apex,
+        )
+        .into_iter()
+        .filter_map(|t| {
+            let point = ray.at(t);
+            let distance = (point.x() * point.x() + point.z() * point.z()).sqrt();
+            let normal = Vec3::new(point.x(), slope * distance, point.z());
+            let normal = match normal.near_zero() {
+                true => Vec3::new(0.0, 1.0, 0.0),
+                false => normal.unit_vector(),
+            };
+            (self.y_min..=self.y_max)
+                .contains(&point.y())
+                .then_some((t, normal))
+        })
+        .collect();
+
+        if self.capped {
+            candidates.extend(
+                cap_hit(ray, self.y_min, self.radius).map(|t| (t, Vec3::new(0.0, -1.0, 0.0))),
+            );
+        }
+
+        let (t, outward_normal) = candidates
+            .into_iter()
+            .filter(|(t, _)| (t_min..=t_max).contains(t))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        let point = ray.at(t);
+
+        Some(HitRecord::new(
+            t,
+            point,
+            ray,
+            outward_normal,
+            angle_around_y(point),
+            (point.y() - self.y_min) / (self.y_max - self.y_min),
+            &*self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Vec3::new(-self.radius, self.y_min, -self.radius),
+            Vec3::new(self.radius, self.y_max, self.radius),
+        )
+    }
+
+    fn area(&self) -> f64 {
+        let height = self.y_max - self.y_min;
+        let side = std::f64::consts::PI
+            * self.radius
+            * (self.radius * self.radius + height * height).sqrt();
+        match self.capped {
+            true => side + std::f64::consts::PI * self.radius * self.radius,
+            false => side,
+        }
+    }
+
+    fn emitted_power(&self) -> Color {
+        lambertian_power(self.area(), &*self.material)
+    }
+}
+
+/// Turns `object` inside out: the outside of its surface becomes the back
+/// face and the other way around, e.g. for lights which only emit from their
+/// front face. The normal of a hit still points against the ray.
+#[derive(Clone)]
+pub struct FlipFace {
+    object: Arc<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Self { object }
+    }
+}
+
+impl Hittable for FlipFace {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut hit_record = self.object.hit(ray, t_min, t_max)?;
+        hit_record.front_face = !hit_record.front_face;
+        Some(hit_record)
+    }
+
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        let mut hit_record = self.object.hit_with_rng(ray, t_min, t_max, rng)?;
+        hit_record.front_face = !hit_record.front_face;
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn area(&self) -> f64 {
+        self.object.area()
+    }
+
+    fn emitted_power(&self) -> Color {
+        self.object.emitted_power()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(origin, rng)
+    }
+
+    fn is_sampleable(&self) -> bool {
+        self.object.is_sampleable()
+    }
+
+    /// Adds the emitters inside of a flipped container, each flipped as well.
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = vec![];
+        self.object.collect_lights(&mut inner);
+        for light in inner {
+            lights.push(Arc::new(Self::new(light)));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SingularTransformError;
+
+impl fmt::Display for SingularTransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transformation matrix is not invertible")
+    }
+}
+
+/// Places another object in the world with an arbitrary transformation, e.g.
+/// to rotate an `AABox`.
+#[derive(Clone)]
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    transform: Matrix4x4,
+    inverse: Matrix4x4,
+    bbox: Aabb,
+}
+
+impl Transformed {
+    pub fn new(
+        object: Arc<dyn Hittable>,
+        transform: Matrix4x4,
+    ) -> Result<Self, SingularTransformError> {
+        let inverse = transform.inverse().ok_or(SingularTransformError)?;
+        Ok(Self::with_inverse(object, transform, inverse))
+    }
+
+    fn with_inverse(object: Arc<dyn Hittable>, transform: Matrix4x4, inverse: Matrix4x4) -> Self {
+        let inner_box = object.bounding_box();
+        let mut minimum = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut maximum = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for corner in 0..8 {
+            let mut point = inner_box.minimum;
+            for axis in 0..3 {
+                if corner & (1 << axis) != 0 {
+                    point[axis] = inner_box.maximum[axis];
+                }
+            }
+            let point = transform.transform_point(point);
+            for axis in 0..3 {
+                minimum[axis] = minimum[axis].min(point[axis]);
+                maximum[axis] = maximum[axis].max(point[axis]);
+            }
+        }
+
+        Self {
+            object,
+            transform,
+            inverse,
+            bbox: Aabb::new(minimum, maximum),
+        }
+    }
+}
+
+impl Transformed {
+    /// `ray` in the space of the object. The direction is not normalized, so
+    /// t is the same in both spaces.
+    fn object_ray(&self, ray: &Ray) -> Ray {
+        Ray::new_at_time(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        )
+    }
+
+    /// Moves a hit on the object back into world space.
+    fn world_hit<'a>(&self, mut hit_record: HitRecord<'a>) -> HitRecord<'a> {
+        hit_record.point = self.transform.transform_point(hit_record.point);
+        // Transforming with the inverse transpose keeps the normal on the
+        // side of the ray, so front_face stays valid.
+        hit_record.normal =
+            Matrix4x4::transform_normal(&self.inverse, hit_record.normal).unit_vector();
+        hit_record.tangent = hit_record
+            .tangent
+            .map(|tangent| self.transform.transform_vector(tangent).unit_vector());
+        hit_record
+    }
+
+    /// Factor by which areas grow from the space of the object to world
+    /// space. Exact for rotations, translations and uniform scaling, an
+    /// average over all orientations for other transformations.
+    fn area_scale(&self) -> f64 {
+        self.transform.determinant().abs().powf(2.0 / 3.0)
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let hit_record = self.object.hit(&self.object_ray(ray), t_min, t_max)?;
+        Some(self.world_hit(hit_record))
+    }
+
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        let object_ray = self.object_ray(ray);
+        let hit_record = self.object.hit_with_rng(&object_ray, t_min, t_max, rng)?;
+        Some(self.world_hit(hit_record))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn area(&self) -> f64 {
+        self.object.area() * self.area_scale()
+    }
+
+    fn emitted_power(&self) -> Color {
+        self.object.emitted_power() * self.area_scale()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let object_direction = self.inverse.transform_vector(direction);
+        let pdf = self
+            .object
+            .pdf_value(self.inverse.transform_point(origin), object_direction);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        // the transformation stretches a solid angle around the unit
+        // direction d by |det| / |M d|³
+        let stretch = self
+            .transform
+            .transform_vector(object_direction.unit_vector());
+        pdf * stretch.len().powi(3) / self.transform.determinant().abs()
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let object_origin = self.inverse.transform_point(origin);
+        self.transform
+            .transform_vector(self.object.random(object_origin, rng))
+    }
+
+    fn is_sampleable(&self) -> bool {
+        self.object.is_sampleable()
+    }
+
+    /// Adds the emitters inside of a transformed container, each under the
+    /// same transformation.
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = vec![];
+        self.object.collect_lights(&mut inner);
+        for light in inner {
+            lights.push(Arc::new(Self::with_inverse(
+                light,
+                self.transform,
+                self.inverse,
+            )));
+        }
+    }
+}
+
+/// Fog or smoke of constant density filling the inside of `boundary`, which
+/// has to be convex. Rays scatter after an exponentially distributed distance
+/// inside the medium, or pass through it.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    negative_inverse_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            negative_inverse_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    pub fn new_from_color(boundary: Arc<dyn Hittable>, density: f64, color: Color) -> Self {
+        Self::new(
+            boundary,
+            density,
+            Arc::new(IsotropicMaterial::new_from_color(color)),
+        )
+    }
+}
+
+impl ConstantMedium {
+    /// Hit inside the medium after an exponentially distributed distance,
+    /// with `uniform` giving the random number in `[0, 1)` it is drawn from.
+    fn scatter(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        uniform: impl FnOnce() -> f64,
+    ) -> Option<HitRecord<'_>> {
+        let entry = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?.t;
+        // skip past the entry by a distance which scales with the boundary,
+        // so that tiny media are not missed
+        let bbox = self.boundary.bounding_box();
+        let skip = 1e-6 * (bbox.maximum - bbox.minimum).len() / ray.direction.len();
+        let exit = self.boundary.hit(ray, entry + skip, f64::INFINITY)?.t;
+
+        let entry = entry.max(t_min).max(0.0);
+        let exit = exit.min(t_max);
+        if entry >= exit {
+            return None;
+        }
+
+        let ray_length = ray.direction.len();
+        let distance_inside = (exit - entry) * ray_length;
+        let hit_distance = self.negative_inverse_density * (1.0 - uniform()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = entry + hit_distance / ray_length;
+        // normal and side do not matter for an isotropic phase function
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: &*self.phase_function,
+            tangent: None,
+        })
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // without a generator the random distance is hashed from the ray, so
+        // that the same ray always scatters at the same point
+        self.scatter(ray, t_min, t_max, || {
+            let hash = ray
+                .origin
+                .e
+                .iter()
+                .chain(&ray.direction.e)
+                .fold(0, |hash, value| splitmix64(hash ^ value.to_bits()));
+            (hash >> 11) as f64 / (1u64 << 53) as f64
+        })
+    }
+
+    fn hit_with_rng(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord<'_>> {
+        self.scatter(ray, t_min, t_max, || rng.gen())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        bvh::BvhNode,
+        material::{DiffuseLightMaterial, LambertianMaterial, MetalMaterial, NormalMapped},
+        texture::SolidColorTexture,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.5, 0.5, 0.5,
+        )))
+    }
+
+    #[test]
+    fn hittable_lists() {
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        let mut list = HittableList::new();
+        assert!(list.is_empty());
+        assert!(list.bounding_box().is_none());
+        assert!(list.hit(&down, 0.001, f64::INFINITY).is_none());
+        let bbox = Hittable::bounding_box(&list);
+        assert_eq!(0.0, bbox.surface_area());
+
+        list.add(Arc::new(Sphere::new(Vec3::default(), 1.0, material())));
+        assert_eq!(1, list.len());
+        let bbox = list.bounding_box().unwrap();
+        assert_eq!([-1.0, -1.0, -1.0], bbox.minimum.e);
+        assert_eq!([1.0, 1.0, 1.0], bbox.maximum.e);
+        assert_eq!(4.0, list.hit(&down, 0.001, f64::INFINITY).unwrap().t);
+
+        // the closest of several objects is hit
+        list.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 2.0, 0.0),
+            0.5,
+            material(),
+        )));
+        list.add(Arc::new(Sphere::new(
+            Vec3::new(4.0, 0.0, 0.0),
+            1.0,
+            material(),
+        )));
+        assert_eq!(3, list.len());
+        let bbox = list.bounding_box().unwrap();
+        assert_eq!([-1.0, -1.0, -1.0], bbox.minimum.e);
+        assert_eq!([5.0, 2.5, 1.0], bbox.maximum.e);
+        assert_eq!(2.5, list.hit(&down, 0.001, f64::INFINITY).unwrap().t);
+    }
+
+    #[test]
+    fn flat_boxes_are_padded_relative_to_their_size() {
+        let thickness = |width: f64| {
+            let offset = Vec3::new(0.0, 0.0, width / 2.0);
+            let rectangle = RectangleXY::new(
+                offset - Vec3::new(width / 2.0, width / 2.0, 0.0),
+                offset + Vec3::new(width / 2.0, width / 2.0, 0.0),
+                1.0,
+                material(),
+            )
+            .unwrap();
+            let bbox = rectangle.bounding_box();
+            assert!(bbox.minimum.z() < offset.z() && offset.z() < bbox.maximum.z());
+            bbox.maximum.z() - bbox.minimum.z()
+        };
+
+        let huge = thickness(10000.0);
+        assert!(huge > 0.0 && huge < 1.0, "{}", huge);
+        let tiny = thickness(1e-4);
+        assert!(tiny > 0.0 && tiny < 1e-6, "{}", tiny);
+
+        // the box of the huge rectangle still leads rays to it
+        let objects: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(
+                RectangleXY::new(
+                    Vec3::new(-5000.0, -5000.0, 5000.0),
+                    Vec3::new(5000.0, 5000.0, 5000.0),
+                    1.0,
+                    material(),
+                )
+                .unwrap(),
+            ),
+            Arc::new(Sphere::new(Vec3::default(), 1.0, material())),
+        ];
+        let bvh = BvhNode::new(objects).unwrap();
+        let ray = Ray::new(Vec3::new(4999.5, -4999.5, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(5000.0, bvh.hit(&ray, 0.001, f64::INFINITY).unwrap().t);
+
+        // shrinking stops at the center
+        let bbox = Aabb::new(Vec3::default(), Vec3::new(2.0, 2.0, 4.0)).pad(-1.5);
+        assert_eq!([1.0, 1.0, 1.5], bbox.minimum.e);
+        assert_eq!([1.0, 1.0, 2.5], bbox.maximum.e);
+    }
+
+    #[test]
+    fn plane_hits() {
+        let plane = Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material(),
+        );
+
+        let down = Ray::new(Vec3::new(3.5, 1.0, -2.25), Vec3::new(0.0, -1.0, 0.0));
+        let hit_record = plane.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(2.0, hit_record.t);
+        assert!(hit_record.front_face);
+        assert!((0.0..1.0).contains(&hit_record.u) && (0.0..1.0).contains(&hit_record.v));
+
+        let parallel = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&parallel, 0.001, f64::INFINITY).is_none());
+
+        let bbox = plane.bounding_box();
+        assert_eq!(-1.0 - PLANE_PADDING, bbox.minimum.y());
+        assert_eq!(-1.0 + PLANE_PADDING, bbox.maximum.y());
+        assert!(bbox.hit(&down, 0.001, f64::INFINITY));
+    }
+
+    #[test]
+    fn sphere_uvs_are_upright_and_continuous() {
+        let uv = |x: f64, y: f64, z: f64| Sphere::get_sphere_uv(Vec3::new(x, y, z));
+        let close = |(u0, v0): (f64, f64), (u1, v1): (f64, f64)| {
+            // u wraps around at the seam
+            let du = (u0 - u1).abs();
+            du.min(1.0 - du) < 1e-3 && (v0 - v1).abs() < 1e-3
+        };
+
+        assert!(close((0.25, 0.5), uv(0.0, 0.0, 1.0)));
+        assert!(close((0.75, 0.5), uv(0.0, 0.0, -1.0)));
+        assert!(close((0.5, 0.5), uv(1.0, 0.0, 0.0)));
+        // u grows to the right when looking at the sphere from +z
+        assert!(uv(0.1, 0.0, 1.0).0 > uv(-0.1, 0.0, 1.0).0);
+        // the top of an image lands on the top of the sphere
+        assert!(close((0.5, 1.0), uv(0.0, 1.0, 0.0)));
+        assert!(close((0.5, 0.0), uv(0.0, -1.0, 0.0)));
+
+        // both sides of the seam at -x and of the -z meridian line up
+        assert!(close(uv(-1.0, 0.3, 1e-6), uv(-1.0, 0.3, -1e-6)));
+        assert!(close(uv(-1.0, 0.3, 0.0), uv(-1.0, 0.3, -0.0)));
+        assert!(close(uv(1e-6, -0.2, -1.0), uv(-1e-6, -0.2, -1.0)));
+
+        // normals slightly off the unit sphere at the poles stay finite
+        let (u, v) = uv(0.0, 1.0 + 1e-12, 0.0);
+        assert!(u.is_finite() && v.is_finite());
+        let (u, v) = uv(0.0, -1.0 - 1e-12, 0.0);
+        assert!(u.is_finite() && v.is_finite());
+    }
+
+    #[test]
+    fn tilted_quad_hits() {
+        let quad = Quad::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, -2.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = quad.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.point - Vec3::new(0.5, 0.5, -0.5)).near_zero());
+        assert!((hit_record.u - 0.25).abs() < 1e-9 && (hit_record.v - 0.5).abs() < 1e-9);
+        assert!(hit_record.front_face);
+
+        let beside = Ray::new(Vec3::new(-0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&beside, 0.001, f64::INFINITY).is_none());
+        let above = Ray::new(Vec3::new(0.5, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&above, 0.001, f64::INFINITY).is_none());
+
+        assert!((quad.area() - 8f64.sqrt()).abs() < 1e-9);
+        let bbox = quad.bounding_box();
+        assert!(bbox.hit(&ray, 0.001, f64::INFINITY));
+
+        // flat along y, but the box still has some thickness
+        let floor = Quad::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material(),
+        );
+        let bbox = floor.bounding_box();
+        assert!(bbox.maximum.y() > bbox.minimum.y());
+    }
+
+    #[test]
+    fn flipped_lights_emit_from_the_back() {
+        let light = || -> Arc<dyn Hittable> {
+            Arc::new(
+                RectangleXY::new(
+                    Vec3::new(-1.0, -1.0, 0.0),
+                    Vec3::new(1.0, 1.0, 0.0),
+                    1.0,
+                    Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                        1.0, 1.0, 1.0,
+                    ))),
+                )
+                .unwrap(),
+            )
+        };
+        let flipped = FlipFace::new(light());
+        let emission = |object: &dyn Hittable, ray: &Ray| {
+            let hit_record = object.hit(ray, 0.001, f64::INFINITY).unwrap();
+            hit_record.material.emits(ray, &hit_record).x()
+        };
+
+        let from_front = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let from_back = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(1.0, emission(&*light(), &from_front));
+        assert_eq!(0.0, emission(&*light(), &from_back));
+        assert_eq!(0.0, emission(&flipped, &from_front));
+        assert_eq!(1.0, emission(&flipped, &from_back));
+
+        let hit_record = flipped.hit(&from_back, 0.001, f64::INFINITY).unwrap();
+        assert!(hit_record.normal.dot(from_back.direction) < 0.0);
+        assert_eq!(light().area(), flipped.area());
+    }
+
+    #[test]
+    fn partial_sphere_is_clipped_in_z() {
+        // upper hemisphere, open towards -z
+        let sphere = PartialSphere::new(Vec3::default(), 1.0, 0.0, 2.0, material());
+
+        // looking into the opening hits the inside of the far wall
+        let into_opening = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit_record = sphere.hit(&into_opening, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(6.0, hit_record.t);
+        assert!(!hit_record.front_face);
+
+        // the cut away half is not hit at all
+        let below = Ray::new(Vec3::new(-5.0, 0.0, -0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(sphere.hit(&below, 0.001, f64::INFINITY).is_none());
+
+        let bbox = sphere.bounding_box();
+        assert_eq!(0.0, bbox.minimum.z());
+        assert_eq!(1.0, bbox.maximum.z());
+        assert!((sphere.area() - 2.0 * std::f64::consts::PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transformed_box_is_rotated() {
+        let unit_box = AABox::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            material(),
+        );
+        let rotated = Transformed::new(
+            Arc::new(unit_box),
+            Matrix4x4::translation(Vec3::new(0.0, 0.0, -5.0))
+                * Matrix4x4::rotation(Vec3::new(0.0, 1.0, 0.0), 45.0),
+        )
+        .unwrap();
+
+        // an edge of the box now points at the camera
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = rotated.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.t - (5.0 - 2.0_f64.sqrt())).abs() < 1e-9);
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal.len() - 1.0).abs() < 1e-9);
+        assert!(hit_record.normal.z() > 0.0);
+
+        let bbox = rotated.bounding_box();
+        assert!((bbox.maximum.x() - 2.0_f64.sqrt()).abs() < 1e-9);
+        assert!((bbox.minimum.z() + 5.0 + 2.0_f64.sqrt()).abs() < 1e-9);
+
+        assert!(Transformed::new(
+            Arc::new(Sphere::new(Vec3::default(), 1.0, material())),
+            Matrix4x4::scaling(Vec3::new(0.0, 1.0, 1.0)),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn transformed_normal_maps_follow_the_rotation() {
+        // tilts the shading normal towards the tangent, (0.6, 0, 0.8) in
+        // tangent space
+        let mirror: Arc<dyn Material> = Arc::new(NormalMapped::new(
+            Arc::new(MetalMaterial::new_from_color(
+                Color::new(1.0, 1.0, 1.0),
+                0.0,
+            )),
+            Box::new(SolidColorTexture::new(Color::new(0.8, 0.5, 0.9))),
+        ));
+        let quad = Arc::new(Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            mirror,
+        ));
+        let rotation = Matrix4x4::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let rotated = Transformed::new(quad.clone(), rotation).unwrap();
+
+        let reflect = |object: &dyn Hittable, ray: &Ray| {
+            let hit_record = object.hit(ray, 0.001, f64::INFINITY).unwrap();
+            let mut rng = StdRng::seed_from_u64(1284);
+            let scatter = hit_record.material.scatter(ray, &hit_record, &mut rng);
+            scatter.unwrap().scattered_ray.direction
+        };
+        let ray = Ray::new(Vec3::new(0.2, 0.1, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rotated_ray = Ray::new(
+            rotation.transform_point(ray.origin),
+            rotation.transform_vector(ray.direction),
+        );
+        let expected = rotation.transform_vector(reflect(&*quad, &ray));
+        assert!((expected - reflect(&rotated, &rotated_ray)).near_zero());
+        assert!((expected - Vec3::new(0.0, 0.96, 0.28)).near_zero());
+    }
+
+    #[test]
+    fn constant_medium_scatters_inside_boundary() {
+        let boundary = || Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, material()));
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, -2.0));
+
+        let dense = ConstantMedium::new_from_color(boundary(), 1e9, Color::new(1.0, 1.0, 1.0));
+        let hit_record = dense.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.t - 2.0).abs() < 1e-6);
+
+        let thin = ConstantMedium::new_from_color(boundary(), 1e-9, Color::new(1.0, 1.0, 1.0));
+        assert!(thin.hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        // from inside, scattering starts right at the ray origin
+        let inside = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit_record = dense.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!(hit_record.t < 0.01);
+
+        // media much smaller than the usual epsilon are still crossed
+        let tiny = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -5e-6), 1e-6, material()));
+        let tiny = ConstantMedium::new_from_color(tiny, 1e15, Color::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = tiny.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit_record.t - 4e-6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn disk_hits() {
+        let disk = Disk::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+            material(),
+        );
+
+        let center = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = disk.hit(&center, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(2.0, hit_record.t);
+        assert!(hit_record.front_face);
+        assert_eq!(0.0, hit_record.v);
+
+        let rim = Ray::new(Vec3::new(0.0, 0.9, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = disk.hit(&rim, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.v - 0.9).abs() < 1e-9);
+        assert!((0.0..1.0).contains(&hit_record.u));
+
+        let outside = Ray::new(Vec3::new(0.8, 0.8, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(&outside, 0.001, f64::INFINITY).is_none());
+        let parallel = Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(disk.hit(&parallel, 0.001, f64::INFINITY).is_none());
+
+        let bbox = disk.bounding_box();
+        assert!((bbox.maximum.x() - 1.0).abs() < 0.001);
+        assert!(bbox.maximum.z() - bbox.minimum.z() < 0.001);
+    }
+
+    #[test]
+    fn cylinder_hits() {
+        let capped = Cylinder::new(1.0, -1.0, 1.0, true, material());
+        let open = Cylinder::new(1.0, -1.0, 1.0, false, material());
+
+        // down the axis only the caps can be hit
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit_record = capped.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(4.0, hit_record.t);
+        assert!(hit_record.front_face);
+        assert_eq!(1.0, hit_record.v);
+        assert!(open.hit(&down, 0.001, f64::INFINITY).is_none());
+
+        // grazing the side
+        let side = Ray::new(Vec3::new(-5.0, 0.5, 0.999), Vec3::new(1.0, 0.0, 0.0));
+        let hit_record = open.hit(&side, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.point.x() + (1.0 - 0.999_f64 * 0.999).sqrt()).abs() < 1e-9);
+        assert!((hit_record.v - 0.75).abs() < 1e-9);
+        let miss = Ray::new(Vec3::new(-5.0, 0.5, 1.001), Vec3::new(1.0, 0.0, 0.0));
+        assert!(open.hit(&miss, 0.001, f64::INFINITY).is_none());
+
+        let bbox = capped.bounding_box();
+        assert_eq!(-1.0, bbox.minimum.y());
+        assert_eq!(1.0, bbox.maximum.x());
+    }
+
+    #[test]
+    fn cone_hits() {
+        let cone = Cone::new(1.0, 0.0, 2.0, true, material());
+
+        // down the axis the apex is hit first
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit_record = cone.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.t - 3.0).abs() < 1e-9);
+
+        // up the axis the base cap is hit
+        let up = Ray::new(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let hit_record = cone.hit(&up, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(5.0, hit_record.t);
+        assert_eq!(0.0, hit_record.v);
+
+        // halfway up the radius is 0.5, the normal leans upwards
+        let side = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit_record = cone.hit(&side, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.point.x() + 0.5).abs() < 1e-9);
+        assert!(hit_record.normal.y() > 0.0 && hit_record.normal.x() < 0.0);
+        let grazing = Ray::new(Vec3::new(-5.0, 1.0, 0.501), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cone.hit(&grazing, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn triangle_interpolates_vertex_attributes() {
+        let triangle = Triangle::new_without_normal(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            material(),
+        )
+        .with_vertex_normals([
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ])
+        .with_texture_coordinates([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+
+        let near_vertex1 = Ray::new(Vec3::new(0.01, 0.01, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = triangle.hit(&near_vertex1, 0.001, f64::INFINITY).unwrap();
+        let expected = Vec3::new(-1.0, -1.0, 1.0).unit_vector();
+        assert!((hit_record.normal - expected).len() < 0.05);
+        assert!((hit_record.u - 0.01).abs() < 1e-9);
+        assert!((hit_record.v - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transformed_lights_match_lights_in_world_space() {
+        let emitter = || -> Arc<dyn Material> {
+            Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                1.0, 1.0, 1.0,
+            )))
+        };
+        let unit_quad = Quad::new(
+            Vec3::default(),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            emitter(),
+        );
+        let transform = Matrix4x4::translation(Vec3::new(0.0, 3.0, 0.0))
+            * Matrix4x4::rotation(Vec3::new(1.0, 0.0, 0.0), 90.0)
+            * Matrix4x4::scaling(Vec3::new(2.0, 2.0, 2.0));
+        let in_world = Quad::new(
+            transform.transform_point(Vec3::default()),
+            transform.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            transform.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            emitter(),
+        );
+
+        // emitters inside of transformed containers are found as well
+        let container = HittableList::from(vec![Arc::new(unit_quad) as Arc<dyn Hittable>]);
+        let transformed = Transformed::new(Arc::new(container), transform).unwrap();
+        let mut lights = vec![];
+        collect_lights_of(&[Arc::new(transformed)], &mut lights);
+        assert_eq!(1, lights.len());
+        let light = &lights[0];
+        assert!(light.is_sampleable());
+        assert!((light.area() - in_world.area()).abs() < 1e-9);
+        assert!((light.emitted_power() - in_world.emitted_power()).near_zero());
+
+        let origin = Vec3::new(0.5, 0.0, 0.5);
+        let mut rng = StdRng::seed_from_u64(1257);
+        for _ in 0..100 {
+            let direction = light.random(origin, &mut rng);
+            let expected = in_world.pdf_value(origin, direction);
+            assert!(expected > 0.0);
+            assert!((light.pdf_value(origin, direction) - expected).abs() < 1e-9);
+        }
+        let away = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(0.0, light.pdf_value(origin, away));
+    }
+
+    #[test]
+    fn flipped_containers_add_their_lights() {
+        let lamp = AABox::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                1.0, 1.0, 1.0,
+            ))),
+        );
+        let mut lights = vec![];
+        collect_lights_of(&[Arc::new(FlipFace::new(Arc::new(lamp)))], &mut lights);
+        assert_eq!(6, lights.len());
+
+        // every face is sampled and lights up the inside of the box
+        let center = Vec3::default();
+        let mut rng = StdRng::seed_from_u64(1315);
+        for light in &lights {
+            let direction = light.random(center, &mut rng);
+            assert!(light.pdf_value(center, direction) > 0.0);
+            let ray = Ray::new(center, direction);
+            let hit_record = light.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert_eq!(1.0, hit_record.material.emits(&ray, &hit_record).x());
+        }
+    }
+
+    #[test]
+    fn light_pdfs_integrate_to_one() {
+        let lights: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Vec3::new(1.0, 2.0, -3.0), 1.5, material())),
+            Box::new(
+                RectangleXY::new(
+                    Vec3::new(-1.0, -1.0, 2.0),
+                    Vec3::new(1.0, 2.0, 2.0),
+                    -1.0,
+                    material(),
+                )
+                .unwrap(),
+            ),
+            Box::new(
+                RectangleXZ::new(
+                    Vec3::new(-1.0, 3.0, -1.0),
+                    Vec3::new(1.0, 3.0, 1.0),
+                    -1.0,
+                    material(),
+                )
+                .unwrap(),
+            ),
+            Box::new(
+                RectangleYZ::new(
+                    Vec3::new(-2.0, -1.0, -1.0),
+                    Vec3::new(-2.0, 1.0, 1.0),
+                    1.0,
+                    material(),
+                )
+                .unwrap(),
+            ),
+            // an ellipsoid and a sheared rectangle
+            Box::new(
+                Transformed::new(
+                    Arc::new(Sphere::new(Vec3::default(), 1.0, material())),
+                    Matrix4x4::translation(Vec3::new(-1.0, 1.0, 3.0))
+                        * Matrix4x4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+                        * Matrix4x4::scaling(Vec3::new(2.0, 0.5, 1.0)),
+                )
+                .unwrap(),
+            ),
+            Box::new(
+                Transformed::new(
+                    Arc::new(Quad::new(
+                        Vec3::new(0.0, 0.0, 0.0),
+                        Vec3::new(1.0, 0.0, 0.0),
+                        Vec3::new(0.0, 1.0, 0.0),
+                        material(),
+                    )),
+                    Matrix4x4::translation(Vec3::new(0.5, -2.0, -1.0))
+                        * Matrix4x4::rotation(Vec3::new(1.0, 0.0, 0.0), 60.0)
+                        * Matrix4x4::scaling(Vec3::new(3.0, 1.0, 2.0)),
+                )
+                .unwrap(),
+            ),
+        ];
+        let origin = Vec3::default();
+        let mut rng = StdRng::seed_from_u64(1269);
+
+        for light in lights {
+            // integrating the density over all directions gives one
+            let samples = 200_000;
+            let integral = (0..samples)
+                .map(|_| light.pdf_value(origin, Vec3::random_on_unitsphere(&mut rng)))
+                .sum::<f64>()
+                * 4.0
+                * std::f64::consts::PI
+                / samples as f64;
+            assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+
+            // and every sampled direction actually hits the light
+            for _ in 0..100 {
+                let direction = light.random(origin, &mut rng);
+                assert!(light.pdf_value(origin, direction) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn moving_sphere_follows_time() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 2.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            material(),
+        );
+        let at = |time: f64| Ray::new_at_time(Vec3::default(), Vec3::new(0.0, 0.0, -1.0), time);
+
+        assert_eq!(4.5, sphere.hit(&at(0.0), 0.001, f64::INFINITY).unwrap().t);
+        assert!(sphere.hit(&at(0.5), 0.001, f64::INFINITY).is_none());
+        assert!(sphere.hit(&at(1.0), 0.001, f64::INFINITY).is_none());
+
+        let bbox = sphere.bounding_box();
+        assert_eq!(-0.5, bbox.minimum.y());
+        assert_eq!(2.5, bbox.maximum.y());
     }
 }
@@ -0,0 +1,451 @@
+use rand::RngCore;
+
+use crate::{
+    bvh::TraversalStats,
+    environment::Environment,
+    geometry::Hittable,
+    light::LightSampler,
+    ray::{PathOptions, Ray, RayEpsilon},
+    renderer::{self, RenderMode},
+    scene::ImageSettings,
+    vec3::Color,
+};
+
+/// Computes the radiance arriving along a camera ray.
+pub trait Integrator: Sync + Send {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        environment: &Environment,
+        max_bounces: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color;
+}
+
+/// Path tracing which only follows the directions picked by the materials,
+/// so light is found by hitting emitters by chance. See `Ray::color_with`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NaivePathIntegrator {
+    /// See `PathOptions::min_roughness`.
+    pub min_roughness: Option<f64>,
+    /// See `PathOptions::roulette_depth`.
+    pub roulette_depth: Option<usize>,
+    /// See `PathOptions::epsilon`.
+    pub epsilon: RayEpsilon,
+}
+
+impl Integrator for NaivePathIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        environment: &Environment,
+        max_bounces: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let options = PathOptions {
+            min_roughness: self.min_roughness,
+            roulette_depth: self.roulette_depth,
+            light_sampler: None,
+            epsilon: self.epsilon,
+        };
+        ray.color_with(world, environment, max_bounces, &options, rng)
+    }
+}
+
+/// Path tracing which also samples the emitters directly at every diffuse
+/// bounce, combining both strategies with multiple importance sampling. See
+/// `PathOptions::light_sampler`.
+pub struct ImportanceSampledIntegrator {
+    pub min_roughness: Option<f64>,
+    pub roulette_depth: Option<usize>,
+    pub epsilon: RayEpsilon,
+    light_sampler: LightSampler,
+}
+
+impl ImportanceSampledIntegrator {
+    /// Samples the emitters of `world`.
+    pub fn new(world: &dyn Hittable) -> Self {
+        let mut lights = vec![];
+        world.collect_lights(&mut lights);
+        Self {
+            min_roughness: None,
+            roulette_depth: None,
+            epsilon: RayEpsilon::default(),
+            light_sampler: LightSampler::new(&lights),
+        }
+    }
+}
+
+impl Integrator for ImportanceSampledIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        environment: &Environment,
+        max_bounces: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let options = PathOptions {
+            min_roughness: self.min_roughness,
+            roulette_depth: self.roulette_depth,
+            light_sampler: Some(&self.light_sampler).filter(|sampler| !sampler.is_empty()),
+            epsilon: self.epsilon,
+        };
+        ray.color_with(world, environment, max_bounces, &options, rng)
+    }
+}
+
+/// Flat shading without secondary rays, see `Ray::preview_color`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreviewIntegrator {
+    /// See `PathOptions::epsilon`.
+    pub epsilon: RayEpsilon,
+}
+
+impl Integrator for PreviewIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        environment: &Environment,
+        _max_bounces: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        ray.preview_color(world, environment, self.epsilon, rng)
+    }
+}
+
+/// Shows the outward normal `n` of the first hit as the color
+/// `0.5 * (n + 1)`, and the environment where rays miss. Materials and lights
+/// are ignored, which makes flipped or missing normals easy to spot.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalsIntegrator {
+    /// See `PathOptions::epsilon`.
+    pub epsilon: RayEpsilon,
+}
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        environment: &Environment,
+        _max_bounces: usize,
+        _rng: &mut dyn RngCore,
+    ) -> Color {
+        match world.hit(ray, self.epsilon.t_min(ray), f64::INFINITY) {
+            Some(hit_record) => {
+                let normal = match hit_record.front_face {
+                    true => hit_record.normal,
+                    false => -hit_record.normal,
+                };
+                0.5 * (normal + Color::new(1.0, 1.0, 1.0))
+            }
+            None => environment.value(ray.direction),
+        }
+    }
+}
+
+/// Shows the distance to the first hit as gray levels, falling linearly
+/// from white at `near` to black at `far`. Misses are black, and like
+/// `NormalsIntegrator` materials and lights are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthIntegrator {
+    pub near: f64,
+    pub far: f64,
+    /// See `PathOptions::epsilon`.
+    pub epsilon: RayEpsilon,
+}
+
+impl Integrator for DepthIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        _environment: &Environment,
+        _max_bounces: usize,
+        _rng: &mut dyn RngCore,
+    ) -> Color {
+        let (depth, _) = renderer::first_hit(world, ray, self.epsilon);
+        if !depth.is_finite() {
+            return Color::default();
+        }
+        let range = (self.far - self.near).max(f64::EPSILON);
+        let brightness = 1.0 - ((depth - self.near) / range).clamp(0.0, 1.0);
+        Color::new(brightness, brightness, brightness)
+    }
+}
+
+/// Shows how many bounding box and object tests the BVHs of the world need
+/// to find the first hit, see `TraversalStats`. The cost is colored from blue
+/// for none over green to red for `max_cost` or more, which shows where the
+/// trees are inefficient.
+#[derive(Debug, Clone, Copy)]
+pub struct BvhCostIntegrator {
+    pub max_cost: usize,
+    /// See `PathOptions::epsilon`.
+    pub epsilon: RayEpsilon,
+}
+
+impl Integrator for BvhCostIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        _environment: &Environment,
+        _max_bounces: usize,
+        _rng: &mut dyn RngCore,
+    ) -> Color {
+        let (_, stats) =
+            TraversalStats::measure(|| world.hit(ray, self.epsilon.t_min(ray), f64::INFINITY));
+        let t = (stats.cost() as f64 / self.max_cost.max(1) as f64).min(1.0);
+        match t < 0.5 {
+            true => Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t),
+            false => Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0),
+        }
+    }
+}
+
+/// The integrator `settings` ask for through their render mode and
+/// `next_event_estimation`.
+pub fn for_settings(world: &dyn Hittable, settings: &ImageSettings) -> Box<dyn Integrator> {
+    let epsilon = settings.ray_epsilon;
+    match settings.render_mode {
+        RenderMode::Preview => Box::new(PreviewIntegrator { epsilon }),
+        RenderMode::Normals => Box::new(NormalsIntegrator { epsilon }),
+        RenderMode::Depth { near, far } => Box::new(DepthIntegrator { near, far, epsilon }),
+        RenderMode::BvhCost { max_cost } => Box::new(BvhCostIntegrator { max_cost, epsilon }),
+        RenderMode::PathTraced if settings.next_event_estimation => {
+            let mut integrator = ImportanceSampledIntegrator::new(world);
+            integrator.min_roughness = settings.path_regularization;
+            integrator.roulette_depth = settings.russian_roulette_depth;
+            integrator.epsilon = settings.ray_epsilon;
+            Box::new(integrator)
+        }
+        RenderMode::PathTraced => Box::new(NaivePathIntegrator {
+            min_roughness: settings.path_regularization,
+            roulette_depth: settings.russian_roulette_depth,
+            epsilon: settings.ray_epsilon,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        geometry::{HittableList, RectangleXY, Sphere},
+        material::{DiffuseLightMaterial, LambertianMaterial},
+        scene::{CornellBoxScene, Scene},
+        vec3::Vec3,
+    };
+
+    /// Mean and variance of the luminance of `count` samples.
+    fn luminance_statistics(
+        integrator: &dyn Integrator,
+        ray: &Ray,
+        world: &dyn Hittable,
+        count: usize,
+        seed: u64,
+    ) -> (f64, f64) {
+        let environment = Environment::Constant(Color::default());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let samples: Vec<f64> = (0..count)
+            .map(|_| {
+                integrator
+                    .radiance(ray, world, &environment, 5, &mut rng)
+                    .luminance()
+            })
+            .collect();
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        let variance = samples
+            .iter()
+            .map(|sample| (sample - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn naive_integrator_matches_analytic_radiance() {
+        // a convex diffuse sphere under a constant sky: every bounce leaves
+        // the sphere and sees the sky, never the light above it, so each
+        // sample is exactly albedo × sky
+        let sky = Color::new(0.2, 0.3, 0.4);
+        let albedo = Color::new(0.5, 0.6, 0.7);
+        let light = Color::new(4.0, 4.0, 4.0);
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, -3.0),
+                1.0,
+                Arc::new(LambertianMaterial::new_from_color(albedo)),
+            )),
+            Arc::new(Sphere::new(
+                Vec3::new(0.0, 5.0, -4.0),
+                1.0,
+                Arc::new(DiffuseLightMaterial::new_from_color(light)),
+            )),
+        ];
+        let world = HittableList::from(world);
+        let environment = Environment::Constant(sky);
+        let integrator = NaivePathIntegrator::default();
+        let mut rng = StdRng::seed_from_u64(1299);
+
+        let cases = [
+            (Vec3::new(0.0, 0.0, -1.0), albedo * sky),
+            (Vec3::new(0.0, 5.0, -4.0), light),
+            (Vec3::new(0.0, 0.0, 1.0), sky),
+        ];
+        for (direction, expected) in cases {
+            let ray = Ray::new(Vec3::default(), direction);
+            for _ in 0..100 {
+                let actual = integrator.radiance(&ray, &world, &environment, 10, &mut rng);
+                assert!((actual - expected).near_zero(), "{:?}", actual);
+            }
+        }
+    }
+
+    #[test]
+    fn light_sampling_agrees_with_naive_path_tracing() {
+        let world = CornellBoxScene.get_world().unwrap();
+        // looks at the back wall, lit by the ceiling light
+        let ray = Ray::new(
+            Vec3::new(278.0, 278.0, -800.0),
+            Vec3::new(-120.0, 150.0, 800.0),
+        );
+        let count = 20000;
+
+        let (naive_mean, naive_variance) =
+            luminance_statistics(&NaivePathIntegrator::default(), &ray, &world, count, 1299);
+        let sampled = ImportanceSampledIntegrator::new(&world);
+        let (sampled_mean, sampled_variance) =
+            luminance_statistics(&sampled, &ray, &world, count, 1299);
+
+        // four standard errors of the difference of the two means
+        let tolerance = 4.0 * ((naive_variance + sampled_variance) / count as f64).sqrt();
+        assert!(
+            (naive_mean - sampled_mean).abs() < tolerance,
+            "{} {} {}",
+            naive_mean,
+            sampled_mean,
+            tolerance
+        );
+        assert!(
+            sampled_variance < 0.5 * naive_variance,
+            "{} {}",
+            sampled_variance,
+            naive_variance
+        );
+    }
+
+    #[test]
+    fn normals_are_shown_as_colors() {
+        let world = RectangleXY::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                4.0, 0.0, 0.0,
+            ))),
+        )
+        .unwrap();
+        let environment = Environment::Constant(Color::new(0.2, 0.3, 0.4));
+        let mut rng = StdRng::seed_from_u64(1321);
+
+        let front = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.1, 0.0, -1.0));
+        let color =
+            NormalsIntegrator::default().radiance(&front, &world, &environment, 10, &mut rng);
+        assert_eq!([0.5, 0.5, 1.0], color.e);
+        // seen from behind the normal still points away from the camera
+        let back = Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let color =
+            NormalsIntegrator::default().radiance(&back, &world, &environment, 10, &mut rng);
+        assert_eq!([0.5, 0.5, 1.0], color.e);
+
+        let miss = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, 1.0));
+        let color =
+            NormalsIntegrator::default().radiance(&miss, &world, &environment, 10, &mut rng);
+        assert_eq!(environment.value(miss.direction).e, color.e);
+    }
+
+    #[test]
+    fn debug_integrators_skip_hits_closer_than_the_epsilon() {
+        let world = RectangleXY::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            1.0,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        )
+        .unwrap();
+        let environment = Environment::Constant(Color::new(0.2, 0.3, 0.4));
+        let mut rng = StdRng::seed_from_u64(1336);
+        // starts 0.05 in front of the rectangle
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.05), Vec3::new(0.0, 0.0, -1.0));
+
+        for (epsilon, hits) in [
+            (RayEpsilon::default(), true),
+            (RayEpsilon::Absolute(0.1), false),
+        ] {
+            let integrators: [Box<dyn Integrator>; 3] = [
+                Box::new(NormalsIntegrator { epsilon }),
+                Box::new(DepthIntegrator {
+                    near: 0.0,
+                    far: 1.0,
+                    epsilon,
+                }),
+                Box::new(PreviewIntegrator { epsilon }),
+            ];
+            for integrator in &integrators {
+                let color = integrator.radiance(&ray, &world, &environment, 10, &mut rng);
+                let missed = match color.e {
+                    [0.0, 0.0, 0.0] => true,
+                    color => color == environment.value(ray.direction).e,
+                };
+                assert_eq!(hits, !missed, "{:?} {:?}", epsilon, color.e);
+            }
+        }
+    }
+
+    #[test]
+    fn closer_hits_are_brighter() {
+        let material = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.5, 0.5, 0.5,
+        )));
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(
+                Vec3::new(-1.0, 0.0, -3.0),
+                0.5,
+                material.clone(),
+            )),
+            Arc::new(Sphere::new(Vec3::new(1.0, 0.0, -6.0), 0.5, material)),
+        ];
+        let world = HittableList::from(world);
+        let environment = Environment::Constant(Color::new(0.2, 0.3, 0.4));
+        let integrator = DepthIntegrator {
+            near: 1.0,
+            far: 10.0,
+            epsilon: RayEpsilon::default(),
+        };
+        let mut rng = StdRng::seed_from_u64(1322);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut brightness = |target: Vec3| {
+            let ray = Ray::new(origin, target - origin);
+            integrator
+                .radiance(&ray, &world, &environment, 10, &mut rng)
+                .x()
+        };
+
+        let near = brightness(Vec3::new(-1.0, 0.0, -3.0));
+        let far = brightness(Vec3::new(1.0, 0.0, -6.0));
+        assert!(near > far && far > 0.0, "{} {}", near, far);
+        assert_eq!(0.0, brightness(Vec3::new(0.0, 1.0, -3.0)));
+    }
+}
@@ -0,0 +1,735 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::error::PathtracerError;
+
+/// Position in the natural, row by row order of the coefficient at every
+/// position of the zigzag order used in the file.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Quantization tables for luminance and chrominance at quality 50, in
+/// natural order, from Annex K of the JPEG standard.
+const LUMINANCE_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMINANCE_QUANTIZATION: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Huffman tables from Annex K as the number of codes per length from 1 to
+/// 16 bits and the values in order of their codes.
+const LUMINANCE_DC_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const LUMINANCE_DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const CHROMINANCE_DC_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const CHROMINANCE_DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const LUMINANCE_AC_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const LUMINANCE_AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+const CHROMINANCE_AC_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const CHROMINANCE_AC_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Quality used when none is given, see `write_jpeg`.
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// Writes 8 bit RGB pixels, row by row from the top, as a baseline JPEG
+/// image without chroma subsampling. `quality` from 1 to 100 scales the
+/// quantization tables of the standard like the IJG encoder does: lower
+/// qualities give smaller files with more artifacts.
+pub fn write_jpeg<W: Write>(
+    mut writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    quality: u8,
+) -> Result<(), PathtracerError> {
+    if !(1..=100).contains(&quality) {
+        return Err(PathtracerError::InvalidJpegQuality(quality));
+    }
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(PathtracerError::InvalidImageSize { width, height });
+    }
+    if pixels.len() != 3 * width * height {
+        return Err(PathtracerError::InvalidImageSize { width, height });
+    }
+
+    let quantization = [
+        scaled_quantization(&LUMINANCE_QUANTIZATION, quality),
+        scaled_quantization(&CHROMINANCE_QUANTIZATION, quality),
+    ];
+    let dc_tables = [
+        HuffmanCodes::new(&LUMINANCE_DC_BITS, &LUMINANCE_DC_VALUES),
+        HuffmanCodes::new(&CHROMINANCE_DC_BITS, &CHROMINANCE_DC_VALUES),
+    ];
+    let ac_tables = [
+        HuffmanCodes::new(&LUMINANCE_AC_BITS, &LUMINANCE_AC_VALUES),
+        HuffmanCodes::new(&CHROMINANCE_AC_BITS, &CHROMINANCE_AC_VALUES),
+    ];
+
+    let mut header = vec![0xff, 0xd8];
+    write_segment(
+        &mut header,
+        0xe0,
+        &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0],
+    );
+    let mut tables = vec![];
+    for (id, table) in quantization.iter().enumerate() {
+        tables.push(id as u8);
+        tables.extend(ZIGZAG.iter().map(|&index| table[index] as u8));
+    }
+    write_segment(&mut header, 0xdb, &tables);
+    let (w, h) = (width as u16, height as u16);
+    let mut frame = vec![8];
+    frame.extend(h.to_be_bytes());
+    frame.extend(w.to_be_bytes());
+    frame.extend([3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    write_segment(&mut header, 0xc0, &frame);
+    let mut huffman = vec![];
+    for (class_and_id, bits, values) in [
+        (0x00, &LUMINANCE_DC_BITS, &LUMINANCE_DC_VALUES[..]),
+        (0x10, &LUMINANCE_AC_BITS, &LUMINANCE_AC_VALUES[..]),
+        (0x01, &CHROMINANCE_DC_BITS, &CHROMINANCE_DC_VALUES[..]),
+        (0x11, &CHROMINANCE_AC_BITS, &CHROMINANCE_AC_VALUES[..]),
+    ] {
+        huffman.push(class_and_id);
+        huffman.extend(bits);
+        huffman.extend(values);
+    }
+    write_segment(&mut header, 0xc4, &huffman);
+    write_segment(&mut header, 0xda, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    writer.write_all(&header)?;
+
+    // every 8x8 block of the image gives one block per component, with the
+    // pixels beyond the border repeating the last row and column
+    let mut bits = BitWriter::default();
+    let mut predictions = [0; 3];
+    let cosines = dct_matrix();
+    for block_y in (0..height).step_by(8) {
+        for block_x in (0..width).step_by(8) {
+            let samples: [[f64; 3]; 64] = std::array::from_fn(|index| {
+                let x = (block_x + index % 8).min(width - 1);
+                let y = (block_y + index / 8).min(height - 1);
+                let rgb = &pixels[3 * (y * width + x)..3 * (y * width + x) + 3];
+                let (r, g, b) = (rgb[0] as f64, rgb[1] as f64, rgb[2] as f64);
+                [
+                    0.299 * r + 0.587 * g + 0.114 * b - 128.0,
+                    -0.168736 * r - 0.331264 * g + 0.5 * b,
+                    0.5 * r - 0.418688 * g - 0.081312 * b,
+                ]
+            });
+            let blocks: [[f64; 64]; 3] =
+                std::array::from_fn(|component| samples.map(|sample| sample[component]));
+
+            for (component, block) in blocks.iter().enumerate() {
+                let table = (component > 0) as usize;
+                let coefficients = forward_dct(block, &cosines);
+                let mut quantized = [0; 64];
+                for (k, &index) in ZIGZAG.iter().enumerate() {
+                    quantized[k] =
+                        (coefficients[index] / quantization[table][index] as f64).round() as i32;
+                }
+                bits.write_block(
+                    &quantized,
+                    &mut predictions[component],
+                    &dc_tables[table],
+                    &ac_tables[table],
+                );
+            }
+        }
+    }
+    writer.write_all(&bits.finish())?;
+    writer.write_all(&[0xff, 0xd9])?;
+
+    Ok(writer.flush()?)
+}
+
+/// Reads a baseline JPEG image with one or three components and no chroma
+/// subsampling, like the ones written by `write_jpeg`, and returns its width,
+/// height and 8 bit RGB pixels. Other images fail with a `Parse` error naming
+/// what is not supported.
+pub fn read_jpeg<R: Read>(mut reader: R) -> Result<(usize, usize, Vec<u8>), PathtracerError> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    let unsupported = |what: &str| PathtracerError::Parse(format!("jpeg: {}", what));
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err(unsupported("missing start of image"));
+    }
+
+    let mut quantization = [[0; 64]; 4];
+    let mut dc_tables: [Option<HuffmanDecoder>; 4] = Default::default();
+    let mut ac_tables: [Option<HuffmanDecoder>; 4] = Default::default();
+    // id and quantization table of every component
+    let mut components: Vec<(u8, usize)> = vec![];
+    let (mut width, mut height) = (0, 0);
+    let mut planes: Vec<Vec<u8>> = vec![];
+    let mut position = 2;
+    let cosines = dct_matrix();
+
+    loop {
+        let marker = match data.get(position..position + 2) {
+            Some(&[0xff, marker]) => marker,
+            _ => return Err(unsupported("missing marker")),
+        };
+        position += 2;
+        if marker == 0xd9 {
+            break;
+        }
+        let length = match data.get(position..position + 2) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]) as usize,
+            _ => return Err(unsupported("truncated segment")),
+        };
+        let segment = data
+            .get(position + 2..position + length)
+            .ok_or_else(|| unsupported("truncated segment"))?;
+        position += length;
+
+        match marker {
+            0xdb => {
+                for table in segment.chunks(65) {
+                    if table.len() != 65 || table[0] >> 4 != 0 {
+                        return Err(unsupported("only 8 bit quantization tables are supported"));
+                    }
+                    let id = (table[0] & 3) as usize;
+                    for (k, &index) in ZIGZAG.iter().enumerate() {
+                        quantization[id][index] = table[1 + k] as i32;
+                    }
+                }
+            }
+            0xc4 => {
+                let mut rest = segment;
+                while !rest.is_empty() {
+                    let count: usize = rest
+                        .get(1..17)
+                        .ok_or_else(|| unsupported("truncated huffman table"))?
+                        .iter()
+                        .map(|&count| count as usize)
+                        .sum();
+                    let table = rest
+                        .get(..17 + count)
+                        .ok_or_else(|| unsupported("truncated huffman table"))?;
+                    let decoder = HuffmanDecoder::new(&table[1..17], &table[17..]);
+                    let id = (table[0] & 3) as usize;
+                    match table[0] >> 4 {
+                        0 => dc_tables[id] = Some(decoder),
+                        _ => ac_tables[id] = Some(decoder),
+                    }
+                    rest = &rest[17 + count..];
+                }
+            }
+            0xc0 => {
+                if !components.is_empty() {
+                    return Err(unsupported("only a single frame is supported"));
+                }
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err(unsupported("only 8 bit samples are supported"));
+                }
+                height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                for component in segment[6..].chunks(3) {
+                    if component.len() != 3 || component[1] != 0x11 {
+                        return Err(unsupported("chroma subsampling is not supported"));
+                    }
+                    components.push((component[0], (component[2] & 3) as usize));
+                }
+                if !matches!(components.len(), 1 | 3) || width == 0 || height == 0 {
+                    return Err(unsupported("unsupported frame"));
+                }
+            }
+            0xc1..=0xcf if marker != 0xc8 && marker != 0xcc => {
+                return Err(unsupported("only baseline images are supported"));
+            }
+            0xdd if segment.len() >= 2 && segment[..2] != [0, 0] => {
+                return Err(unsupported("restart intervals are not supported"));
+            }
+            0xda => {
+                // table selectors in the order of the frame's components
+                let mut selectors = vec![];
+                for &(id, _) in &components {
+                    let count = *segment.first().unwrap_or(&0) as usize;
+                    let selector = segment
+                        .get(1..1 + 2 * count)
+                        .unwrap_or_default()
+                        .chunks(2)
+                        .find(|scan_component| scan_component[0] == id)
+                        .map(|scan_component| scan_component[1])
+                        .ok_or_else(|| unsupported("only interleaved scans are supported"))?;
+                    selectors.push((((selector >> 4) & 3) as usize, (selector & 3) as usize));
+                }
+
+                let (blocks_x, blocks_y) = (width.div_ceil(8), height.div_ceil(8));
+                let stride = 8 * blocks_x;
+                planes = vec![vec![0; stride * 8 * blocks_y]; components.len()];
+                let mut bits = BitReader::new(&data[position..]);
+                let mut predictions = vec![0; components.len()];
+                for block_y in 0..blocks_y {
+                    for block_x in 0..blocks_x {
+                        for (component, &(_, table)) in components.iter().enumerate() {
+                            let (dc, ac) = selectors[component];
+                            let (Some(dc), Some(ac)) = (&dc_tables[dc], &ac_tables[ac]) else {
+                                return Err(unsupported("missing huffman table"));
+                            };
+                            let block = bits
+                                .read_block(
+                                    &mut predictions[component],
+                                    dc,
+                                    ac,
+                                    &quantization[table],
+                                )
+                                .ok_or_else(|| unsupported("corrupt image data"))?;
+                            for (index, value) in inverse_dct(&block, &cosines).iter().enumerate() {
+                                let (x, y) = (8 * block_x + index % 8, 8 * block_y + index / 8);
+                                planes[component][y * stride + x] =
+                                    (value + 128.0).round().clamp(0.0, 255.0) as u8;
+                            }
+                        }
+                    }
+                }
+                position += bits.position;
+                // padding bits up to the next marker
+                while position < data.len()
+                    && !(data[position] == 0xff && data.get(position + 1) != Some(&0))
+                {
+                    position += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if planes.is_empty() {
+        return Err(unsupported("missing image data"));
+    }
+    let stride = 8 * width.div_ceil(8);
+    let mut pixels = Vec::with_capacity(3 * width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * stride + x;
+            if planes.len() == 1 {
+                pixels.extend([planes[0][index]; 3]);
+                continue;
+            }
+            let luma = planes[0][index] as f64;
+            let (cb, cr) = (
+                planes[1][index] as f64 - 128.0,
+                planes[2][index] as f64 - 128.0,
+            );
+            pixels.extend(
+                [
+                    luma + 1.402 * cr,
+                    luma - 0.344136 * cb - 0.714136 * cr,
+                    luma + 1.772 * cb,
+                ]
+                .map(|value| value.round().clamp(0.0, 255.0) as u8),
+            );
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+fn write_segment(output: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    output.extend([0xff, marker]);
+    output.extend((payload.len() as u16 + 2).to_be_bytes());
+    output.extend(payload);
+}
+
+/// `table` scaled for `quality` like the IJG encoder does.
+fn scaled_quantization(table: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality as u32;
+    let scale = match quality < 50 {
+        true => 5000 / quality,
+        false => 200 - 2 * quality,
+    };
+    table.map(|value| ((value as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+/// `cosines[u][x]` of the orthonormal 8 point DCT-II, so the forward and the
+/// inverse transform use the same matrix. Computed once per image and passed
+/// to every block.
+fn dct_matrix() -> [[f64; 8]; 8] {
+    let mut cosines = [[0.0; 8]; 8];
+    for (u, row) in cosines.iter_mut().enumerate() {
+        let scale = if u == 0 { 0.5 / 2f64.sqrt() } else { 0.5 };
+        for (x, cosine) in row.iter_mut().enumerate() {
+            *cosine = scale * ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+/// Coefficients of a block of 8x8 samples, both row by row.
+fn forward_dct(block: &[f64; 64], cosines: &[[f64; 8]; 8]) -> [f64; 64] {
+    let mut rows = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| cosines[u][x] * block[y * 8 + x]).sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..8).map(|y| cosines[v][y] * rows[y * 8 + u]).sum();
+        }
+    }
+    coefficients
+}
+
+/// Samples of a block of 8x8 coefficients, both row by row.
+fn inverse_dct(coefficients: &[f64; 64], cosines: &[[f64; 8]; 8]) -> [f64; 64] {
+    let mut columns = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            columns[y * 8 + u] = (0..8)
+                .map(|v| cosines[v][y] * coefficients[v * 8 + u])
+                .sum();
+        }
+    }
+    let mut block = [0.0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            block[y * 8 + x] = (0..8).map(|u| cosines[u][x] * columns[y * 8 + u]).sum();
+        }
+    }
+    block
+}
+
+/// Number of bits of the magnitude of `value`, its category in the standard.
+fn category(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// Code and length of every value of a Huffman table.
+struct HuffmanCodes {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanCodes {
+    fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0;
+        let mut values = values.iter();
+        for (length, &count) in bits.iter().enumerate() {
+            for value in values.by_ref().take(count as usize) {
+                codes[*value as usize] = (code, length as u8 + 1);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Self { codes }
+    }
+}
+
+struct HuffmanDecoder {
+    values: HashMap<(u8, u16), u8>,
+}
+
+impl HuffmanDecoder {
+    fn new(bits: &[u8], values: &[u8]) -> Self {
+        let mut decoded = HashMap::new();
+        let mut code: u16 = 0;
+        let mut values = values.iter();
+        for (length, &count) in bits.iter().enumerate() {
+            for value in values.by_ref().take(count as usize) {
+                decoded.insert((length as u8 + 1, code), *value);
+                // tables of corrupt files can run out of codes
+                code = code.wrapping_add(1);
+            }
+            code <<= 1;
+        }
+        Self { values: decoded }
+    }
+}
+
+/// Packs the entropy coded data, inserting a zero byte after every 0xff.
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u16, length: u8) {
+        for shift in (0..length).rev() {
+            self.buffer = (self.buffer << 1) | ((bits >> shift) & 1) as u32;
+            self.count += 1;
+            if self.count == 8 {
+                self.output.push(self.buffer as u8);
+                if self.buffer as u8 == 0xff {
+                    self.output.push(0);
+                }
+                self.buffer = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn write_code(&mut self, codes: &HuffmanCodes, value: u8) {
+        let (code, length) = codes.codes[value as usize];
+        self.write(code, length);
+    }
+
+    /// Writes the lowest `length` bits of `value`, with negative values
+    /// offset by one as the standard asks for.
+    fn write_value(&mut self, value: i32, length: u8) {
+        let bits = if value < 0 { value - 1 } else { value };
+        self.write((bits & ((1 << length) - 1)) as u16, length);
+    }
+
+    /// Writes a quantized block in zigzag order. The DC coefficient is coded
+    /// as the difference to the previous block of the component.
+    fn write_block(
+        &mut self,
+        block: &[i32; 64],
+        prediction: &mut i32,
+        dc: &HuffmanCodes,
+        ac: &HuffmanCodes,
+    ) {
+        let difference = block[0] - *prediction;
+        *prediction = block[0];
+        let size = category(difference);
+        self.write_code(dc, size);
+        self.write_value(difference, size);
+
+        let mut zeros = 0;
+        for &coefficient in &block[1..] {
+            if coefficient == 0 {
+                zeros += 1;
+                continue;
+            }
+            while zeros > 15 {
+                self.write_code(ac, 0xf0);
+                zeros -= 16;
+            }
+            let size = category(coefficient);
+            self.write_code(ac, (zeros << 4) | size);
+            self.write_value(coefficient, size);
+            zeros = 0;
+        }
+        if zeros > 0 {
+            self.write_code(ac, 0x00);
+        }
+    }
+
+    /// The written data, with the last byte padded with ones.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            let padding = 8 - self.count;
+            self.write((1 << padding) - 1, padding);
+        }
+        self.output
+    }
+}
+
+/// Reads the entropy coded data, skipping the zero bytes after 0xff.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            byte: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Option<u16> {
+        if self.count == 0 {
+            self.byte = *self.data.get(self.position)?;
+            if self.byte == 0xff {
+                // anything but a stuffed zero is a marker
+                if self.data.get(self.position + 1) != Some(&0) {
+                    return None;
+                }
+                self.position += 1;
+            }
+            self.position += 1;
+            self.count = 8;
+        }
+        self.count -= 1;
+        Some(((self.byte >> self.count) & 1) as u16)
+    }
+
+    fn read_bits(&mut self, length: u8) -> Option<u16> {
+        (0..length).try_fold(0, |bits, _| Some((bits << 1) | self.read_bit()?))
+    }
+
+    fn read_code(&mut self, decoder: &HuffmanDecoder) -> Option<u8> {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = (code << 1) | self.read_bit()?;
+            if let Some(&value) = decoder.values.get(&(length, code)) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Reads `length` bits holding a value as written by
+    /// `BitWriter::write_value`.
+    fn read_value(&mut self, length: u8) -> Option<i32> {
+        if length == 0 {
+            return Some(0);
+        }
+        if length > 15 {
+            return None;
+        }
+        let bits = self.read_bits(length)? as i32;
+        Some(match bits < 1 << (length - 1) {
+            true => bits - (1 << length) + 1,
+            false => bits,
+        })
+    }
+
+    /// Reads a block and returns its dequantized coefficients in natural
+    /// order.
+    fn read_block(
+        &mut self,
+        prediction: &mut i32,
+        dc: &HuffmanDecoder,
+        ac: &HuffmanDecoder,
+        quantization: &[i32; 64],
+    ) -> Option<[f64; 64]> {
+        let mut coefficients = [0.0; 64];
+        let size = self.read_code(dc)?;
+        // corrupt files may add up differences beyond any valid coefficient
+        *prediction = prediction.wrapping_add(self.read_value(size)?);
+        coefficients[0] = *prediction as f64 * quantization[0] as f64;
+
+        let mut k = 1;
+        while k < 64 {
+            let run_and_size = self.read_code(ac)?;
+            let (zeros, size) = ((run_and_size >> 4) as usize, run_and_size & 15);
+            if size == 0 {
+                if zeros != 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += zeros;
+            let index = *ZIGZAG.get(k)?;
+            coefficients[index] = (self.read_value(size)? * quantization[index]) as f64;
+            k += 1;
+        }
+        Some(coefficients)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path};
+
+    use super::*;
+
+    /// Smooth gradients with a hard edge, not a multiple of the block size.
+    fn test_image() -> (usize, usize, Vec<u8>) {
+        let (width, height) = (21, 13);
+        let pixels = (0..width * height)
+            .flat_map(|index| {
+                let (x, y) = (index % width, index / width);
+                let edge = if x < 10 { 40 } else { 220 };
+                [(12 * x) as u8, (19 * y) as u8, edge]
+            })
+            .collect();
+        (width, height, pixels)
+    }
+
+    fn mean_error(a: &[u8], b: &[u8]) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(&a, &b)| (a as f64 - b as f64).abs())
+            .sum::<f64>()
+            / a.len() as f64
+    }
+
+    #[test]
+    fn jpeg_round_trip() {
+        let (width, height, pixels) = test_image();
+
+        let mut buffer = vec![];
+        write_jpeg(&mut buffer, width, height, &pixels, DEFAULT_QUALITY).unwrap();
+        let (read_width, read_height, read_pixels) = read_jpeg(&buffer[..]).unwrap();
+        assert_eq!((width, height), (read_width, read_height));
+        let error = mean_error(&pixels, &read_pixels);
+        assert!(error < 3.0, "mean error {}", error);
+
+        let mut small = vec![];
+        write_jpeg(&mut small, width, height, &pixels, 10).unwrap();
+        assert!(small.len() < buffer.len());
+        assert!(read_jpeg(&small[..]).is_ok());
+
+        for quality in [0, 101] {
+            assert!(matches!(
+                write_jpeg(vec![], width, height, &pixels, quality),
+                Err(PathtracerError::InvalidJpegQuality(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn missing_pixels_are_rejected() {
+        let (width, height, pixels) = test_image();
+
+        assert!(matches!(
+            write_jpeg(vec![], width, height + 1, &pixels, DEFAULT_QUALITY),
+            Err(PathtracerError::InvalidImageSize { .. })
+        ));
+        assert!(matches!(
+            write_jpeg(vec![], width, height, &pixels[1..], DEFAULT_QUALITY),
+            Err(PathtracerError::InvalidImageSize { .. })
+        ));
+    }
+
+    #[test]
+    fn reference_encoder_images_are_read() {
+        // the test image written by libjpeg-turbo 2.1.5 at quality 90 without
+        // chroma subsampling and with optimized Huffman tables
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/libjpeg_gradient.jpg");
+        let (width, height, pixels) = test_image();
+
+        let (read_width, read_height, read_pixels) = read_jpeg(File::open(path).unwrap()).unwrap();
+        assert_eq!((width, height), (read_width, read_height));
+        let error = mean_error(&pixels, &read_pixels);
+        assert!(error < 3.0, "mean error {}", error);
+    }
+}
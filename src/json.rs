@@ -0,0 +1,394 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::error::PathtracerError;
+
+/// Deepest nesting of arrays and objects which is parsed. Deeper documents
+/// are rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON document. Objects keep their members in file order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Member `key` of an object, `None` for missing keys and other values.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            Self::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a complete JSON document.
+pub fn parse(source: &str) -> Result<JsonValue, PathtracerError> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+        line: 1,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek().copied() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("unexpected '{}' after the document", c))),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    /// Arrays and objects around the current value.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> PathtracerError {
+        PathtracerError::Parse(format!("line {}: {}", self.line, message))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), PathtracerError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found the end", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, PathtracerError> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some(c @ ('{' | '[')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(&format!("nested deeper than {} levels", MAX_DEPTH)));
+                }
+                self.depth += 1;
+                let value = match c {
+                    '{' => self.object(),
+                    _ => self.array(),
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => Ok(JsonValue::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(c) if c.is_alphabetic() => self.literal(),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, PathtracerError> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.next();
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, PathtracerError> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.next();
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, PathtracerError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.unicode_escape()?;
+                        // characters beyond U+FFFF are escaped as a pair of
+                        // UTF-16 surrogates
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.next() != Some('\\') || self.next() != Some('u') {
+                                return Err(self.error("unpaired surrogate in unicode escape"));
+                            }
+                            let low = self.unicode_escape()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(self.error("unpaired surrogate in unicode escape"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        let c = char::from_u32(code)
+                            .ok_or_else(|| self.error("unpaired surrogate in unicode escape"))?;
+                        string.push(c);
+                    }
+                    Some(c @ ('"' | '\\' | '/')) => string.push(c),
+                    Some(c) => return Err(self.error(&format!("invalid escape '\\{}'", c))),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) if c < ' ' => {
+                    return Err(self.error("unescaped control character in string"))
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The four hex digits following `\u`.
+    fn unicode_escape(&mut self) -> Result<u32, PathtracerError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = 16 * code + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<JsonValue, PathtracerError> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            text.push(c);
+            self.next();
+        }
+        if !is_number(&text) {
+            return Err(self.error(&format!("invalid number '{}'", text)));
+        }
+        text.parse()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error(&format!("invalid number '{}'", text)))
+    }
+
+    fn literal(&mut self) -> Result<JsonValue, PathtracerError> {
+        let mut word = String::new();
+        while self.chars.peek().is_some_and(|c| c.is_alphabetic()) {
+            word.push(self.next().unwrap());
+        }
+        match word.as_str() {
+            "true" => Ok(JsonValue::Bool(true)),
+            "false" => Ok(JsonValue::Bool(false)),
+            "null" => Ok(JsonValue::Null),
+            _ => Err(self.error(&format!("unexpected '{}'", word))),
+        }
+    }
+}
+
+/// Whether `text` follows the grammar of JSON numbers,
+/// `-? (0 | [1-9][0-9]*) (\.[0-9]+)? ([eE][+-]?[0-9]+)?`, which is stricter
+/// than what `f64::from_str` accepts.
+fn is_number(text: &str) -> bool {
+    let mut chars = text.chars().peekable();
+    let digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let mut count = 0;
+        while chars.next_if(char::is_ascii_digit).is_some() {
+            count += 1;
+        }
+        count
+    };
+
+    chars.next_if_eq(&'-');
+    match chars.peek() {
+        Some('0') => {
+            chars.next();
+        }
+        Some('1'..='9') => {
+            digits(&mut chars);
+        }
+        _ => return false,
+    }
+    if chars.next_if_eq(&'.').is_some() && digits(&mut chars) == 0 {
+        return false;
+    }
+    if chars.next_if(|&c| c == 'e' || c == 'E').is_some() {
+        chars.next_if(|&c| c == '+' || c == '-');
+        if digits(&mut chars) == 0 {
+            return false;
+        }
+    }
+    chars.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_documents() {
+        let value = parse(
+            r#"{
+                "name": "a \"quoted\" é",
+                "values": [1, -2.5e1, true, null, {}],
+                "empty": []
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("a \"quoted\" é"), value.get("name").unwrap().as_str());
+        let values = value.get("values").unwrap().as_array().unwrap();
+        assert_eq!(Some(-25.0), values[1].as_f64());
+        assert_eq!(Some(true), values[2].as_bool());
+        assert_eq!(JsonValue::Null, values[3]);
+        assert_eq!(Some(&[][..]), value.get("empty").unwrap().as_array());
+        assert!(value.get("missing").is_none());
+
+        for invalid in ["{", "[1 2]", "{\"a\" 1}", "nope", "1 2", "\"open"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let value = parse(r#""\"\\\/\b\f\n\r\t \u00e9 \u20AC \ud83d\ude00""#).unwrap();
+        assert_eq!(Some("\"\\/\u{8}\u{c}\n\r\t é € 😀"), value.as_str());
+
+        for invalid in [
+            r#""\u12""#,
+            r#""\u+123""#,
+            r#""\ud83d""#,
+            r#""\ud83d x""#,
+            r#""\ud83d\u0041""#,
+            r#""\ude00""#,
+            r#""\"#,
+            r#""\q""#,
+            r#""\'""#,
+            "\"tab\tinside\"",
+            "\"new\nline\"",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_numbers() {
+        for (text, expected) in [
+            ("0", 0.0),
+            ("-7", -7.0),
+            ("0.25", 0.25),
+            ("1E3", 1000.0),
+            ("-2.5e-2", -0.025),
+            ("1e+2", 100.0),
+        ] {
+            assert_eq!(Some(expected), parse(text).unwrap().as_f64(), "{}", text);
+        }
+
+        for invalid in [
+            "-", "1e", "1.2.3", "--1", "+1", ".5", "[1,]", "01", "1.", "-01", "1.e5", "1e5.0",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        // far too deep to recurse into
+        assert!(matches!(
+            parse(&"[{\"a\":".repeat(100_000)),
+            Err(PathtracerError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = parse("{\n  \"a\": 1,\n  \"b\": tru\n}").unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+        for invalid in ["", "  ", "{\"a\": 1,}", "[1] [2]", "{1: 2}", "\"a\nb"] {
+            assert!(parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+pub mod bvh;
+pub mod camera;
+pub mod environment;
+pub mod error;
+pub mod film;
+pub mod geometry;
+pub mod integrator;
+pub mod jpeg;
+pub mod json;
+pub mod light;
+pub mod material;
+pub mod obj_model;
+pub mod output;
+pub mod ray;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod texture;
+pub mod tone_mapping;
+pub mod transformation;
+pub mod vec3;
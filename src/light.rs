@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+
+use crate::{geometry::Hittable, vec3::Vec3};
+
+/// Picks emitters with a probability proportional to their emitted power, so
+/// that a small but bright light receives more shadow rays than a large dim
+/// one.
+#[derive(Clone)]
+pub struct LightSampler {
+    lights: Vec<Arc<dyn Hittable>>,
+    cdf: Vec<f64>,
+}
+
+impl LightSampler {
+    /// Keeps all objects which emit any light.
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        let mut lights = vec![];
+        let mut cdf = vec![];
+        let mut total_power = 0.0;
+
+        for object in objects {
+            let power = object.emitted_power();
+            let power = (power.x() + power.y() + power.z()) / 3.0;
+            if power > 0.0 {
+                total_power += power;
+                lights.push(object.clone());
+                cdf.push(total_power);
+            }
+        }
+
+        for value in cdf.iter_mut() {
+            *value /= total_power;
+        }
+
+        Self { lights, cdf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Probability of picking the light at `index`.
+    pub fn probability(&self, index: usize) -> f64 {
+        match index {
+            0 => self.cdf[0],
+            _ => self.cdf[index] - self.cdf[index - 1],
+        }
+    }
+
+    /// Picks a light and returns it together with its index.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Option<(usize, &dyn Hittable)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let value = rng.gen::<f64>();
+        let index = self
+            .cdf
+            .partition_point(|&probability| probability <= value)
+            .min(self.lights.len() - 1);
+
+        Some((index, &*self.lights[index]))
+    }
+
+    /// Probability density, per solid angle, of sampling `direction` from
+    /// `origin` by first picking a light and then a point on it.
+    pub fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        self.lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| self.probability(index) * light.pdf_value(origin, direction))
+            .sum()
+    }
+
+    /// Picks a light and a random direction from `origin` towards it.
+    pub fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let (_, light) = self.sample(rng)?;
+        Some(light.random(origin, rng))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::{RectangleXZ, Sphere},
+        material::{DiffuseLightMaterial, LambertianMaterial},
+        vec3::{Color, Vec3},
+    };
+
+    #[test]
+    fn lights_are_picked_by_power() {
+        let objects: Vec<Arc<dyn Hittable>> = vec![
+            // area 100, radiance 1 => 100π
+            Arc::new(
+                RectangleXZ::new(
+                    Vec3::new(0.0, 5.0, 0.0),
+                    Vec3::new(10.0, 5.0, 10.0),
+                    -1.0,
+                    Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                        1.0, 1.0, 1.0,
+                    ))),
+                )
+                .unwrap(),
+            ),
+            Arc::new(Sphere::new(
+                Vec3::default(),
+                1.0,
+                Arc::new(LambertianMaterial::new_from_color(Color::new(
+                    1.0, 1.0, 1.0,
+                ))),
+            )),
+            // area 1, radiance 300 => 300π
+            Arc::new(
+                RectangleXZ::new(
+                    Vec3::new(0.0, 4.0, 0.0),
+                    Vec3::new(1.0, 4.0, 1.0),
+                    -1.0,
+                    Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                        300.0, 300.0, 300.0,
+                    ))),
+                )
+                .unwrap(),
+            ),
+        ];
+
+        let sampler = LightSampler::new(&objects);
+        assert_eq!(2, sampler.len());
+        assert!((sampler.probability(0) - 0.25).abs() < 1e-9);
+        assert!((sampler.probability(1) - 0.75).abs() < 1e-9);
+
+        let mut rng = rand::thread_rng();
+        let picked_bright = (0..10000)
+            .filter(|_| sampler.sample(&mut rng).unwrap().0 == 1)
+            .count();
+        assert!((7000..8000).contains(&picked_bright));
+    }
+}
@@ -1,91 +1,567 @@
-use std::{fs::File, io::BufWriter, path::Path};
-
-mod bvh;
-mod camera;
-mod geometry;
-mod material;
-mod obj_model;
-mod ray;
-mod renderer;
-mod scene;
-mod texture;
-mod vec3;
-
-use indicatif::ProgressBar;
-use indicatif::ProgressStyle;
-use scene::Scene;
-use scene::*;
-
-fn main() {
-    let scene: Box<dyn Scene> = Box::new(ModelTestScene {
-        path_str: String::from("./model.obj"),
-    });
-    let world = scene.get_world();
-    let settings = scene.get_output_settings();
-    let amount_of_frames = match settings {
-        scene::OutputSettings::StaticImage { image_settings: _ } => 1,
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+};
+
+use clap::{Parser, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use pathtracer::scene::{self, Scene, *};
+use pathtracer::{
+    bvh::BvhNode,
+    camera::Camera,
+    environment::Environment,
+    error::PathtracerError,
+    integrator, jpeg, output,
+    ray::{RayEpsilon, DEFAULT_RAY_EPSILON},
+    renderer::{self, RenderMode, Tile},
+    sampler::SampleSource,
+    scene_file::FileScene,
+    texture::ImageTexture,
+    vec3::{linear_to_srgb, Color},
+};
+use rayon::prelude::*;
+
+/// Roughness floor used by `--regularize`.
+const REGULARIZATION_ROUGHNESS: f64 = 0.3;
+
+/// Resolution of the progress bar within a frame.
+const PIXEL_PROGRESS_STEPS: u64 = 1000;
+
+/// Samples per pixel between two saves of `--checkpoint`.
+const CHECKPOINT_SAMPLES: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// 8 bit gamma corrected PNG
+    Png,
+    /// Linear radiance as Radiance .hdr, without clamping
+    Hdr,
+    /// 8 bit gamma corrected JPEG, see --jpeg-quality
+    Jpeg,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IntegratorKind {
+    /// Only follow the directions picked by the materials
+    Naive,
+    /// Also sample the lights directly at every diffuse bounce
+    Importance,
+    /// Show the surface normals of the first hit instead of shading
+    Normals,
+    /// Show the distance to the first hit within --depth-range
+    Depth,
+    /// Show the number of BVH tests for the first hit, up to --max-bvh-cost
+    BvhCost,
+}
+
+#[derive(Parser)]
+#[command(about = "A simple pathtracer")]
+struct Args {
+    /// Built-in scene to render (sphere-field, bouncing-spheres,
+    /// three-spheres, two-sphere-checkers, perlin-spheres, bumpy-sphere,
+    /// light-test, cornell-box, cornell-orbit, triangle-test, uv-sphere,
+    /// model-test), the path of a .json scene file or the path of an .obj
+    /// model
+    #[arg(long, default_value = "cornell-box")]
+    scene: String,
+
+    /// Directory the rendered frames are written to
+    #[arg(long, default_value = "./output")]
+    output_dir: PathBuf,
+
+    /// File format of the rendered frames
+    #[arg(long, value_enum, default_value_t = OutputFormat::Png)]
+    format: OutputFormat,
+
+    /// Quality of JPEG frames from 1 to 100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: Option<u8>,
+
+    /// Image width in pixels. If only one of width and height is given, the
+    /// other one follows the aspect ratio of the scene
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Image height in pixels
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Only render the pixels from (X0, Y0) up to (X1, Y1), counted from the
+    /// top left, and leave the rest of the image black
+    #[arg(long, num_args = 4, value_names = ["X0", "Y0", "X1", "Y1"])]
+    crop: Option<Vec<usize>>,
+
+    /// Equirectangular PNG panorama lighting the scene instead of its
+    /// background color
+    #[arg(long)]
+    environment: Option<PathBuf>,
+
+    /// Samples per pixel
+    #[arg(long)]
+    samples: Option<usize>,
+
+    /// Maximum number of bounces per path
+    #[arg(long)]
+    max_bounces: Option<usize>,
+
+    /// Number of render threads, 0 uses all cores
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Roughen specular materials after the first diffuse bounce to reduce
+    /// caustic noise
+    #[arg(long)]
+    regularize: bool,
+
+    /// Distance rays leaving a surface travel before they can hit anything,
+    /// 0.001 unless given. Raise it if surfaces show dark speckles
+    #[arg(long)]
+    ray_epsilon: Option<f64>,
+
+    /// Scale the ray epsilon with the coordinates of the hit points, for very
+    /// large scenes
+    #[arg(long)]
+    relative_epsilon: bool,
+
+    /// Seed for reproducible renders. It also fixes everything random about
+    /// the scene, like object placement, noise textures and fog. Without a
+    /// seed every render uses fresh random numbers
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Light transport algorithm, overriding the one the scene asks for
+    #[arg(long, value_enum)]
+    integrator: Option<IntegratorKind>,
+
+    /// Distances shown as white and black by `--integrator depth`
+    #[arg(long, num_args = 2, value_names = ["NEAR", "FAR"], default_values_t = [0.0, 20.0])]
+    depth_range: Vec<f64>,
+
+    /// Number of BVH tests shown as red by `--integrator bvh-cost`
+    #[arg(long, default_value_t = 64)]
+    max_bvh_cost: usize,
+
+    /// Collect the frames into a single animated image.gif at the frame rate
+    /// of the scene instead of writing one image per frame
+    #[arg(long)]
+    gif: bool,
+
+    /// Also write the linear radiance of every frame as a NumPy .npy file
+    #[arg(long)]
+    npy: bool,
+
+    /// Write PNG frames with an alpha channel which is transparent where
+    /// only the background is seen. Only works with --format png and
+    /// without --gif
+    #[arg(long)]
+    alpha: bool,
+
+    /// Also write the depth and normal of the first hit per pixel as
+    /// separate images
+    #[arg(long)]
+    aovs: bool,
+
+    /// Render several animation frames at the same time. Faster for small
+    /// frames, but keeps more images in memory at once
+    #[arg(long)]
+    parallel_frames: bool,
+
+    /// Save the accumulated samples of every frame to the output directory
+    /// every few samples and continue from there if the render is restarted
+    #[arg(long)]
+    checkpoint: bool,
+
+    /// Render a quick unfiltered preview with one sample per pixel and flat
+    /// shading, for checking the camera placement
+    #[arg(long)]
+    preview_fast: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Picks the scene named by `--scene`.
+fn select_scene(name: &str) -> Result<Box<dyn Scene>, PathtracerError> {
+    if let Some(scene) = scene::scene_by_name(name) {
+        return Ok(scene);
+    }
+
+    match Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("obj") => Ok(Box::new(ModelTestScene {
+            path_str: name.to_string(),
+        })),
+        Some("json") => Ok(Box::new(FileScene::from_file(Path::new(name))?)),
+        Some("pbrt") => Err(PathtracerError::Parse(format!(
+            "{}: pbrt scene files are not supported",
+            name
+        ))),
+        _ => Err(PathtracerError::Parse(format!(
+            "unknown scene '{}', expected one of {} or a scene file",
+            name,
+            SCENE_NAMES.join(", ")
+        ))),
+    }
+}
+
+fn run(args: &Args) -> Result<(), PathtracerError> {
+    // only PNG frames can carry the alpha channel
+    if args.alpha && (args.format != OutputFormat::Png || args.gif) {
+        return Err(PathtracerError::Parse(
+            "--alpha needs PNG frames, it cannot be combined with --gif or another --format"
+                .to_string(),
+        ));
+    }
+    let scene = select_scene(&args.scene)?;
+    let world = match args.seed {
+        Some(seed) => scene.get_seeded_world(seed)?,
+        None => scene.get_world()?,
+    };
+    let mut settings = scene.get_output_settings();
+    let (amount_of_frames, fps) = match settings {
+        scene::OutputSettings::StaticImage { image_settings: _ } => (1, 1.0),
         scene::OutputSettings::Animation {
             image_settings: _,
             ref fps,
             ref duration,
-        } => (fps * duration) as u64,
+        } => ((fps * duration) as u64, *fps),
     };
     let image_settings = match settings {
-        scene::OutputSettings::StaticImage { ref image_settings } => image_settings,
+        scene::OutputSettings::StaticImage {
+            ref mut image_settings,
+        } => image_settings,
         scene::OutputSettings::Animation {
-            ref image_settings,
+            ref mut image_settings,
             fps: _,
             duration: _,
         } => image_settings,
     };
+    let aspect_ratio = image_settings.width as f64 / image_settings.height as f64;
+    match (args.width, args.height) {
+        (Some(width), Some(height)) => {
+            image_settings.width = width;
+            image_settings.height = height;
+        }
+        (Some(width), None) => {
+            image_settings.width = width;
+            image_settings.height = (width as f64 / aspect_ratio).round() as usize;
+        }
+        (None, Some(height)) => {
+            image_settings.width = (height as f64 * aspect_ratio).round() as usize;
+            image_settings.height = height;
+        }
+        (None, None) => {}
+    }
+    if let Some(samples) = args.samples {
+        image_settings.samples_per_pixel = samples;
+    }
+    if let Some(max_bounces) = args.max_bounces {
+        image_settings.max_bounces = max_bounces;
+    }
+    if let Some(path) = &args.environment {
+        image_settings.background = Environment::Image(Arc::new(ImageTexture::load(path)?));
+    }
+    if args.regularize {
+        image_settings.path_regularization = Some(REGULARIZATION_ROUGHNESS);
+    }
+    let epsilon = args.ray_epsilon.unwrap_or(DEFAULT_RAY_EPSILON);
+    if args.relative_epsilon {
+        image_settings.ray_epsilon = RayEpsilon::Relative(epsilon);
+    } else if args.ray_epsilon.is_some() {
+        image_settings.ray_epsilon = RayEpsilon::Absolute(epsilon);
+    }
+    image_settings.coverage = args.alpha;
+    if let Some(quality) = args.jpeg_quality {
+        image_settings.jpeg_quality = quality;
+    }
+    if let Some(crop) = &args.crop {
+        image_settings.crop = Some(Tile::from_corners(crop[0], crop[1], crop[2], crop[3]));
+    }
+    if let Some(seed) = args.seed {
+        image_settings.sample_source = SampleSource::Seeded { seed };
+    }
+    if let Some(integrator) = args.integrator {
+        image_settings.next_event_estimation = integrator != IntegratorKind::Naive;
+        image_settings.render_mode = match integrator {
+            IntegratorKind::Normals => RenderMode::Normals,
+            IntegratorKind::Depth => RenderMode::Depth {
+                near: args.depth_range[0],
+                far: args.depth_range[1],
+            },
+            IntegratorKind::BvhCost => RenderMode::BvhCost {
+                max_cost: args.max_bvh_cost,
+            },
+            _ => RenderMode::PathTraced,
+        };
+    }
+    if args.preview_fast {
+        image_settings.use_fast_preview();
+    }
+
+    fs::create_dir_all(&args.output_dir)?;
 
     let bar_style = ProgressStyle::default_bar()
             .template("{prefix:.white} [{elapsed_precise}/{duration_precise}] {bar:40.green/green} {percent}%")
             .expect("template error for indicatif");
 
-    let frame_progress = ProgressBar::new(amount_of_frames);
+    let progress = MultiProgress::new();
+    let frame_progress = progress.add(ProgressBar::new(amount_of_frames));
     frame_progress.set_prefix("💻 Rendering Frames ");
-    frame_progress.set_style(bar_style);
+    frame_progress.set_style(bar_style.clone());
     frame_progress.tick();
 
-    for frame_index in 0..(amount_of_frames as usize) {
-        let t = (frame_index as f64) / amount_of_frames as f64;
-        let camera = scene.get_camera_at(t);
+    // progress within a frame, only meaningful while frames are rendered one
+    // after another
+    let pixel_progress = match args.parallel_frames {
+        true => ProgressBar::hidden(),
+        false => progress.add(ProgressBar::new(PIXEL_PROGRESS_STEPS)),
+    };
+    pixel_progress.set_prefix("🖼  Rendering Pixels ");
+    pixel_progress.set_style(bar_style);
+    let on_progress = |fraction: f64| {
+        pixel_progress.set_position((fraction * PIXEL_PROGRESS_STEPS as f64) as u64);
+    };
 
-        // Render
-        let pixels: Vec<u8> = renderer::render(
+    // cameras are placed up front, scenes are not shared between threads
+    let image_settings: &ImageSettings = image_settings;
+    let cameras: Vec<Camera> = (0..amount_of_frames)
+        .map(|frame_index| scene.get_camera_at(frame_index as f64 / amount_of_frames as f64))
+        .collect();
+    let gif = match args.gif {
+        true => Some(Mutex::new(GifFrames {
+            writer: output::GifWriter::new(
+                BufWriter::new(File::create(args.output_dir.join("image.gif"))?),
+                image_settings.width,
+                image_settings.height,
+                fps,
+            )?,
+            next_frame: 0,
+            pending: BTreeMap::new(),
+        })),
+        false => None,
+    };
+    let render = |(frame_index, camera): (usize, &Camera)| -> Result<(), PathtracerError> {
+        let pixels = render_frame(
+            args,
             &world,
-            &camera,
-            image_settings.width,
-            image_settings.height,
-            image_settings.background,
-            image_settings.samples_per_pixel,
-            image_settings.max_bounces,
-        );
-
-        // Write PNG
-        let path_str = format!("./output/image_{:04}.png", frame_index);
-        let path = Path::new(&path_str);
-        let file = File::create(path).expect("could not create image file");
-        write_file(file, image_settings.width, image_settings.height, &pixels)
-            .expect("could not write image data");
-
+            camera,
+            image_settings,
+            frame_index,
+            &on_progress,
+        )?;
+        if let Some(gif) = &gif {
+            gif.lock()
+                .expect("gif writer poisoned")
+                .add(frame_index, pixels)?;
+        }
         frame_progress.inc(1);
+        Ok(())
+    };
+    renderer::thread_pool(args.threads)?.install(|| match args.parallel_frames {
+        true => cameras.par_iter().enumerate().try_for_each(render),
+        false => cameras.iter().enumerate().try_for_each(render),
+    })?;
+    if let Some(gif) = gif {
+        let gif = gif.into_inner().expect("gif writer poisoned");
+        gif.writer.finish()?;
     }
+    pixel_progress.finish_and_clear();
     frame_progress.finish();
+
+    Ok(())
 }
 
-fn write_file(
-    file: File,
-    width: usize,
-    height: usize,
-    pixels: &[u8],
-) -> Result<(), png::EncodingError> {
-    let w = BufWriter::new(file);
+/// Writes the frames of an animation into a GIF in order, whichever order
+/// they are rendered in.
+struct GifFrames {
+    writer: output::GifWriter<BufWriter<File>>,
+    next_frame: usize,
+    /// Frames rendered ahead of `next_frame`.
+    pending: BTreeMap<usize, Vec<u8>>,
+}
 
-    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
-    encoder.set_color(png::ColorType::Rgb);
-    let mut writer = encoder.write_header()?;
+impl GifFrames {
+    fn add(&mut self, frame_index: usize, pixels: Vec<u8>) -> Result<(), PathtracerError> {
+        self.pending.insert(frame_index, pixels);
+        while let Some(pixels) = self.pending.remove(&self.next_frame) {
+            self.writer.write_frame(&pixels)?;
+            self.next_frame += 1;
+        }
+        Ok(())
+    }
+}
 
-    writer.write_image_data(pixels)
+/// Renders frame `frame_index` and writes it, together with the extra
+/// outputs requested on the command line, to the output directory. Returns
+/// the 8 bit RGB pixels of the frame. With `--gif` the frame itself is left
+/// to the caller.
+fn render_frame(
+    args: &Args,
+    world: &BvhNode,
+    camera: &Camera,
+    image_settings: &ImageSettings,
+    frame_index: usize,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<Vec<u8>, PathtracerError> {
+    // Render
+    let (colors, coverage) = match args.checkpoint {
+        true => {
+            let checkpoint = args
+                .output_dir
+                .join(format!("image_{:04}.checkpoint", frame_index));
+            loop {
+                let resumed = renderer::render_resume(
+                    world,
+                    camera,
+                    image_settings,
+                    &checkpoint,
+                    CHECKPOINT_SAMPLES,
+                )?;
+                on_progress(resumed.samples_taken as f64 / image_settings.samples_per_pixel as f64);
+                if resumed.is_complete(image_settings) {
+                    // nothing left to resume, and a stale checkpoint would
+                    // be picked up by the next render of this frame
+                    match std::fs::remove_file(&checkpoint) {
+                        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                            return Err(error.into())
+                        }
+                        _ => {}
+                    }
+                    break (resumed.colors, resumed.coverage);
+                }
+            }
+        }
+        false => {
+            let film = renderer::render_film(
+                world,
+                camera,
+                image_settings,
+                &*integrator::for_settings(world, image_settings),
+                on_progress,
+            )?;
+            (film.resolve(), film.resolve_coverage())
+        }
+    };
+
+    if args.npy {
+        let file = File::create(
+            args.output_dir
+                .join(format!("image_{:04}.npy", frame_index)),
+        )?;
+        output::write_npy(
+            BufWriter::new(file),
+            image_settings.width,
+            image_settings.height,
+            &colors,
+        )?;
+    }
+
+    let stem = args.output_dir.join(format!("image_{:04}", frame_index));
+    let pixels = renderer::to_rgb(&colors, image_settings);
+    match (args.alpha, args.format) {
+        _ if args.gif => {}
+        (true, OutputFormat::Png) => {
+            let file = File::create(stem.with_extension("png"))?;
+            output::write_png_rgba(
+                BufWriter::new(file),
+                image_settings.width,
+                image_settings.height,
+                &renderer::to_rgba(&colors, &coverage, image_settings),
+            )?;
+        }
+        _ => write_image(args.format, &stem, image_settings, &colors, &pixels)?,
+    }
+
+    if args.aovs {
+        let aovs = renderer::render_aovs(world, camera, image_settings)?;
+        let depth: Vec<Color> = aovs
+            .depth
+            .iter()
+            .map(|&depth| match depth.is_finite() {
+                true => Color::new(depth, depth, depth),
+                false => Color::default(),
+            })
+            .collect();
+        let depth_image = aovs.depth_image();
+        let normal_image = aovs.normal_image();
+        let unmapped = |colors: &[Color]| -> Vec<u8> {
+            colors
+                .iter()
+                .flat_map(|color| linear_to_srgb(*color, 1.0))
+                .collect()
+        };
+
+        write_image(
+            args.format,
+            &stem.with_file_name(format!("image_{:04}_depth", frame_index)),
+            image_settings,
+            &depth,
+            &unmapped(&depth_image),
+        )?;
+        write_image(
+            args.format,
+            &stem.with_file_name(format!("image_{:04}_normal", frame_index)),
+            image_settings,
+            &normal_image,
+            &unmapped(&normal_image),
+        )?;
+    }
+
+    Ok(pixels)
+}
+
+/// Writes either the linear `colors` or the 8 bit `pixels` of an image to
+/// `stem` with the extension of `format`.
+fn write_image(
+    format: OutputFormat,
+    stem: &Path,
+    settings: &ImageSettings,
+    colors: &[Color],
+    pixels: &[u8],
+) -> Result<(), PathtracerError> {
+    match format {
+        OutputFormat::Png => {
+            let file = File::create(stem.with_extension("png"))?;
+            output::write_png(
+                BufWriter::new(file),
+                settings.width,
+                settings.height,
+                pixels,
+            )
+        }
+        OutputFormat::Jpeg => {
+            let file = File::create(stem.with_extension("jpg"))?;
+            jpeg::write_jpeg(
+                BufWriter::new(file),
+                settings.width,
+                settings.height,
+                pixels,
+                settings.jpeg_quality,
+            )
+        }
+        OutputFormat::Hdr => {
+            let file = File::create(stem.with_extension("hdr"))?;
+            output::write_hdr(
+                BufWriter::new(file),
+                settings.width,
+                settings.height,
+                colors,
+            )
+        }
+    }
 }
@@ -1,10 +1,12 @@
-use std::ops::Neg;
+use std::{ops::Neg, sync::Arc};
+
+use rand::{Rng, RngCore};
 
 use crate::{
     geometry::HitRecord,
     ray::Ray,
     texture::{SolidColorTexture, Texture},
-    vec3::{Color, Vec3},
+    vec3::{Color, OrthonormalBasis, Vec3},
 };
 
 pub struct Scatter {
@@ -13,46 +15,141 @@ pub struct Scatter {
 }
 
 pub trait Material: Sync + Send {
-    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Option<Scatter> {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        None
+    }
+    /// Scatters like `scatter`, but widens glossy and specular lobes to a
+    /// roughness of at least `min_roughness`. Used for path regularization.
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        _min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter(ray_in, hit_record, rng)
+    }
+    fn is_specular(&self) -> bool {
+        false
+    }
+    /// BSDF times cosine for light arriving from `direction`, together with
+    /// the density of `scatter` picking that direction. Only materials which
+    /// can be lit by sampling lights directly return anything.
+    fn evaluate(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: &HitRecord,
+        _direction: Vec3,
+    ) -> Option<(Color, f64)> {
         None
     }
     fn emits(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Color {
         Color::default()
     }
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    /// Rough average of the emitted radiance, used to weigh lights against
+    /// each other.
+    fn average_emission(&self) -> Color {
+        Color::default()
+    }
+}
+
+/// How a `LambertianMaterial` picks the direction of scattered rays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffuseSampling {
+    /// Every direction of the hemisphere around the normal is equally
+    /// likely. Rays are weighted by `2 cos θ` to make up for the grazing
+    /// directions, which contribute little, being picked as often as the
+    /// ones around the normal. Converges slower, mostly useful to compare.
+    Uniform,
+    /// Directions are picked with a density of `cos θ / π`, which is the
+    /// Lambertian BRDF times the cosine up to the albedo. Both cancel, so
+    /// rays carry exactly the albedo without any extra PDF factor.
+    #[default]
+    Cosine,
 }
 
 pub struct LambertianMaterial {
     pub albedo: Box<dyn Texture>,
+    pub sampling: DiffuseSampling,
 }
 
 impl LambertianMaterial {
     pub fn new(albedo: Box<dyn Texture>) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            sampling: DiffuseSampling::default(),
+        }
     }
 
     pub fn new_from_color(color: Color) -> Self {
-        Self {
-            albedo: Box::new(SolidColorTexture::new(color)),
-        }
+        Self::new(Box::new(SolidColorTexture::new(color)))
+    }
+
+    pub fn with_sampling(mut self, sampling: DiffuseSampling) -> Self {
+        self.sampling = sampling;
+        self
     }
 }
 
 impl Material for LambertianMaterial {
-    fn scatter(&self, _: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
-        let mut scatter_direction = hit_record.normal + Vec3::random_on_unitsphere();
-
-        if scatter_direction.near_zero() {
-            println!("Near zero");
-            scatter_direction = hit_record.normal;
-        }
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        let albedo = self.albedo.value_with_normal(
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+            hit_record.normal,
+        );
+        let (scatter_direction, attenuation) = match self.sampling {
+            DiffuseSampling::Uniform => {
+                let direction = Vec3::random_on_unitsphere(rng);
+                let cosine = direction.dot(hit_record.normal);
+                match cosine < 0.0 {
+                    true => (-direction, -2.0 * cosine * albedo),
+                    false => (direction, 2.0 * cosine * albedo),
+                }
+            }
+            DiffuseSampling::Cosine => (
+                OrthonormalBasis::from_normal(hit_record.normal)
+                    .local(Vec3::random_cosine_direction(rng)),
+                albedo,
+            ),
+        };
 
         Some(Scatter {
-            scattered_ray: Ray::new(hit_record.point, scatter_direction),
-            attenuation: self
-                .albedo
-                .value(hit_record.u, hit_record.v, hit_record.point),
+            scattered_ray: Ray::new_at_time(hit_record.point, scatter_direction, ray_in.time),
+            attenuation,
         })
     }
+
+    fn evaluate(&self, _: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<(Color, f64)> {
+        let cosine = hit_record.normal.dot(direction.unit_vector()).max(0.0);
+        let value = cosine / std::f64::consts::PI;
+        let pdf = match (self.sampling, cosine > 0.0) {
+            (_, false) => 0.0,
+            (DiffuseSampling::Uniform, true) => 1.0 / (2.0 * std::f64::consts::PI),
+            (DiffuseSampling::Cosine, true) => value,
+        };
+        let albedo = self.albedo.value_with_normal(
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+            hit_record.normal,
+        );
+        Some((value * albedo, pdf))
+    }
 }
 
 pub struct MetalMaterial {
@@ -71,38 +168,325 @@ impl MetalMaterial {
             fuzz,
         }
     }
+
+    fn scatter_with_fuzz(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        fuzz: f64,
+    ) -> Option<Scatter> {
+        let reflected_direction = ray_in.direction.unit_vector().reflect(hit_record.normal);
+        if reflected_direction.dot(hit_record.normal) <= 0.0 {
+            return None;
+        }
+
+        // fuzz can cancel out the reflection entirely, such rays are absorbed
+        let scattered_direction =
+            (reflected_direction + fuzz * Vec3::random_in_unitsphere(rng)).try_unit_vector()?;
+
+        Some(Scatter {
+            scattered_ray: Ray::new_at_time(hit_record.point, scattered_direction, ray_in.time),
+            attenuation: self.albedo.value_with_normal(
+                hit_record.u,
+                hit_record.v,
+                hit_record.point,
+                hit_record.normal,
+            ),
+        })
+    }
 }
 
 impl Material for MetalMaterial {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
-        let reflected_direction = ray_in.direction.unit_vector().reflect(hit_record.normal);
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.scatter_with_fuzz(ray_in, hit_record, rng, self.fuzz)
+    }
 
-        if reflected_direction.dot(hit_record.normal) > 0.0 {
-            Some(Scatter {
-                scattered_ray: Ray::new(
-                    hit_record.point,
-                    reflected_direction + self.fuzz * Vec3::random_in_unitsphere(),
-                ),
-                attenuation: self
-                    .albedo
-                    .value(hit_record.u, hit_record.v, hit_record.point),
-            })
-        } else {
-            None
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter_with_fuzz(ray_in, hit_record, rng, self.fuzz.max(min_roughness))
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// A metal whose reflectance follows the Fresnel equations for conductors,
+/// given the complex index of refraction `eta + i k` per RGB channel. Unlike
+/// `MetalMaterial`, it gets brighter and less tinted towards grazing angles.
+pub struct ConductorMaterial {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+}
+
+impl ConductorMaterial {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self { eta, k, roughness }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminum(roughness: f64) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    /// Fraction of unpolarized light reflected per channel, for light
+    /// arriving at `cosine` to the normal.
+    pub fn reflectance(&self, cosine: f64) -> Color {
+        Color::new(
+            Self::fresnel(cosine, self.eta.x(), self.k.x()),
+            Self::fresnel(cosine, self.eta.y(), self.k.y()),
+            Self::fresnel(cosine, self.eta.z(), self.k.z()),
+        )
+    }
+
+    // From "Physically Based Rendering", section 8.2.1.
+    fn fresnel(cosine: f64, eta: f64, k: f64) -> f64 {
+        let cos2 = cosine.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cosine * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    }
+
+    fn scatter_with_roughness(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        roughness: f64,
+    ) -> Option<Scatter> {
+        let unit_direction = ray_in.direction.unit_vector();
+        let reflected_direction =
+            unit_direction.reflect(hit_record.normal) + roughness * Vec3::random_in_unitsphere(rng);
+        if reflected_direction.dot(hit_record.normal) <= 0.0 {
+            return None;
         }
+
+        let cosine = unit_direction.neg().dot(hit_record.normal);
+        Some(Scatter {
+            scattered_ray: Ray::new_at_time(hit_record.point, reflected_direction, ray_in.time),
+            attenuation: self.reflectance(cosine),
+        })
+    }
+}
+
+impl Material for ConductorMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness)
+    }
+
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness.max(min_roughness))
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// Rough reflections between a mirror and a matte surface, using the GGX
+/// (Trowbridge-Reitz) microfacet distribution with the Smith shadowing term
+/// and Schlick's Fresnel approximation. Dielectrics (`metallic` 0) reflect
+/// 4% head on and scatter the rest diffusely in the base color, metals
+/// (`metallic` 1) tint the reflection with it.
+pub struct MicrofacetMaterial {
+    pub base_color: Box<dyn Texture>,
+    pub roughness: f64,
+    pub metallic: f64,
+}
+
+impl MicrofacetMaterial {
+    pub fn new(base_color: Box<dyn Texture>, roughness: f64, metallic: f64) -> Self {
+        Self {
+            base_color,
+            roughness: roughness.clamp(0.0, 1.0),
+            metallic: metallic.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn new_from_color(base_color: Color, roughness: f64, metallic: f64) -> Self {
+        Self::new(
+            Box::new(SolidColorTexture::new(base_color)),
+            roughness,
+            metallic,
+        )
+    }
+
+    fn scatter_with_roughness(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        roughness: f64,
+    ) -> Option<Scatter> {
+        let normal = hit_record.normal;
+        let to_viewer = ray_in.direction.unit_vector().neg();
+        let normal_dot_view = normal.dot(to_viewer).max(1e-6);
+        let base_color = self.base_color.value_with_normal(
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+            hit_record.normal,
+        );
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let f0 = 0.04 * (1.0 - self.metallic) * white + self.metallic * base_color;
+        // the specular lobe is picked at least half of the time
+        let specular_probability = 0.5 + 0.5 * self.metallic;
+
+        if rng.gen::<f64>() >= specular_probability {
+            // diffuse lobe, with the light the surface reflects taken away
+            let fresnel = f0 + (white - f0) * (1.0 - normal_dot_view).powi(5);
+            let mut direction = normal + Vec3::random_on_unitsphere(rng);
+            if direction.near_zero() {
+                direction = normal;
+            }
+            return Some(Scatter {
+                scattered_ray: Ray::new_at_time(hit_record.point, direction, ray_in.time),
+                attenuation: (1.0 - self.metallic) * (white - fresnel) * base_color
+                    / (1.0 - specular_probability),
+            });
+        }
+
+        // sample a microfacet normal from the GGX distribution and reflect
+        let alpha = (roughness * roughness).max(1e-4);
+        let alpha2 = alpha * alpha;
+        let (u1, u2) = (rng.gen::<f64>(), rng.gen::<f64>());
+        let cos_theta = ((1.0 - u1) / (1.0 + (alpha2 - 1.0) * u1)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * u2;
+        let OrthonormalBasis {
+            u: tangent,
+            v: bitangent,
+            ..
+        } = OrthonormalBasis::from_normal(normal);
+        let half = phi.cos() * sin_theta * tangent
+            + phi.sin() * sin_theta * bitangent
+            + cos_theta * normal;
+
+        let direction = to_viewer.neg().reflect(half);
+        let normal_dot_light = normal.dot(direction);
+        let view_dot_half = to_viewer.dot(half);
+        if normal_dot_light <= 0.0 || view_dot_half <= 0.0 {
+            return None;
+        }
+
+        let smith = |cosine: f64| {
+            2.0 * cosine / (cosine + (alpha2 + (1.0 - alpha2) * cosine * cosine).sqrt())
+        };
+        let shadowing = smith(normal_dot_view) * smith(normal_dot_light);
+        let fresnel = f0 + (white - f0) * (1.0 - view_dot_half).powi(5);
+
+        // BRDF times cosine divided by the density of the sampled direction,
+        // D(h) cancels out
+        let weight = shadowing * view_dot_half / (normal_dot_view * cos_theta.max(1e-6));
+        Some(Scatter {
+            scattered_ray: Ray::new_at_time(hit_record.point, direction, ray_in.time),
+            attenuation: weight / specular_probability * fresnel,
+        })
+    }
+}
+
+impl Material for MicrofacetMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness)
+    }
+
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness.max(min_roughness))
+    }
+
+    fn is_specular(&self) -> bool {
+        true
     }
 }
 
 pub struct DielectricMaterial {
     pub index_of_refraction: f64,
+    /// Fraction of light absorbed per unit of distance traveled inside the
+    /// material, per channel (Beer-Lambert law). Zero for clear glass.
+    pub absorption: Color,
 }
 
 impl DielectricMaterial {
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             index_of_refraction,
+            absorption: Color::default(),
         }
     }
+
+    /// Tints the material by absorbing light inside of it, so thick parts
+    /// get darker than thin ones. Hits on the back face of a surface are
+    /// taken to end a segment inside the material.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
 }
 
 impl DielectricMaterial {
@@ -111,57 +495,894 @@ impl DielectricMaterial {
         let r0 = ((1.0 - refraction_index) / (1.0 + refraction_index)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
+
+    fn scatter_with_roughness(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        roughness: f64,
+    ) -> Option<Scatter> {
+        let (direction, _) =
+            refract_or_reflect(ray_in, hit_record, self.index_of_refraction, roughness, rng)?;
+
+        // the ray reached this hit from the inside
+        let attenuation = match hit_record.front_face {
+            true => Color::new(1.0, 1.0, 1.0),
+            false => {
+                let distance = hit_record.t * ray_in.direction.len();
+                self.absorption.map(|a| (-a * distance).exp())
+            }
+        };
+
+        Some(Scatter {
+            scattered_ray: Ray::new_at_time(hit_record.point, direction, ray_in.time),
+            attenuation,
+        })
+    }
+}
+
+/// Reflects or refracts `ray_in` at the boundary of a dielectric, picking
+/// between the two by the Fresnel reflectance, and perturbs the direction by
+/// `roughness`. Also tells whether the ray was reflected. `None` if the
+/// perturbation cancels out the direction or pushes it through to the other
+/// side of the surface.
+fn refract_or_reflect(
+    ray_in: &Ray,
+    hit_record: &HitRecord,
+    index_of_refraction: f64,
+    roughness: f64,
+    rng: &mut dyn RngCore,
+) -> Option<(Vec3, bool)> {
+    let refraction_ratio = if hit_record.front_face {
+        1.0 / index_of_refraction
+    } else {
+        index_of_refraction
+    };
+
+    let unit_direction = ray_in.direction.unit_vector();
+
+    let cos_theta = unit_direction.neg().dot(hit_record.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let reflected = (refraction_ratio * sin_theta > 1.0)
+        || (DielectricMaterial::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>());
+    let direction = match reflected {
+        true => unit_direction.reflect(hit_record.normal),
+        false => unit_direction.refract(hit_record.normal, refraction_ratio),
+    };
+    if roughness <= 0.0 {
+        return Some((direction, reflected));
+    }
+
+    let perturbed = (direction.unit_vector() + roughness * Vec3::random_in_unitsphere(rng))
+        .try_unit_vector()?;
+    if perturbed.dot(hit_record.normal) * direction.dot(hit_record.normal) <= 0.0 {
+        return None;
+    }
+    Some((perturbed, reflected))
 }
 
 impl Material for DielectricMaterial {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
-        let refraction_ratio = if hit_record.front_face {
-            1.0 / self.index_of_refraction
-        } else {
-            self.index_of_refraction
-        };
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, 0.0)
+    }
 
-        let unit_direction = ray_in.direction.unit_vector();
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, min_roughness)
+    }
 
-        let cos_theta = unit_direction.neg().dot(hit_record.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
 
-        let mut direction = unit_direction;
-        if (refraction_ratio * sin_theta > 1.0)
-            || (DielectricMaterial::reflectance(cos_theta, refraction_ratio)
-                > rand::random::<f64>())
-        {
-            direction = direction.reflect(hit_record.normal);
-        } else {
-            direction = direction.refract(hit_record.normal, refraction_ratio);
+/// Glass which blurs what is seen through and reflected by it, by
+/// perturbing the scattered direction like `MetalMaterial` does with its
+/// fuzz. Reflected and transmitted light can be tinted independently.
+pub struct RoughDielectricMaterial {
+    pub index_of_refraction: f64,
+    pub roughness: f64,
+    pub reflection_color: Color,
+    pub transmission_color: Color,
+}
+
+impl RoughDielectricMaterial {
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        Self {
+            index_of_refraction,
+            roughness,
+            reflection_color: Color::new(1.0, 1.0, 1.0),
+            transmission_color: Color::new(1.0, 1.0, 1.0),
         }
+    }
+
+    pub fn with_colors(mut self, reflection_color: Color, transmission_color: Color) -> Self {
+        self.reflection_color = reflection_color;
+        self.transmission_color = transmission_color;
+        self
+    }
+
+    fn scatter_with_roughness(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        roughness: f64,
+    ) -> Option<Scatter> {
+        let (direction, reflected) =
+            refract_or_reflect(ray_in, hit_record, self.index_of_refraction, roughness, rng)?;
+        let attenuation = match reflected {
+            true => self.reflection_color,
+            false => self.transmission_color,
+        };
 
         Some(Scatter {
-            scattered_ray: Ray::new(hit_record.point, direction),
-            attenuation: Color::new(1.0, 1.0, 1.0),
+            scattered_ray: Ray::new_at_time(hit_record.point, direction, ray_in.time),
+            attenuation,
         })
     }
 }
 
+impl Material for RoughDielectricMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness)
+    }
+
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.scatter_with_roughness(ray_in, hit_record, rng, self.roughness.max(min_roughness))
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// Wraps another material and bends the shading normal by a normal map, a
+/// texture storing tangent space normals as colors (`0.5 * (n + 1)`, with
+/// blue pointing away from the surface). The tangent comes from the
+/// primitive if it knows its texture coordinates, otherwise an arbitrary
+/// one is used.
+pub struct NormalMapped {
+    pub inner: Arc<dyn Material>,
+    pub normal_map: Box<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(inner: Arc<dyn Material>, normal_map: Box<dyn Texture>) -> Self {
+        Self { inner, normal_map }
+    }
+
+    fn perturbed<'a>(&self, hit_record: &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = hit_record.normal;
+        let (tangent, bitangent) = shading_frame(hit_record);
+
+        let sample = self
+            .normal_map
+            .value(hit_record.u, hit_record.v, hit_record.point);
+        let local = 2.0 * sample - Vec3::new(1.0, 1.0, 1.0);
+        let perturbed = local.x() * tangent + local.y() * bitangent + local.z() * normal;
+
+        with_shading_normal(hit_record, perturbed)
+    }
+}
+
+/// Tangent and bitangent around the normal of the hit, following the
+/// tangent of the primitive where it has one.
+fn shading_frame(hit_record: &HitRecord) -> (Vec3, Vec3) {
+    let normal = hit_record.normal;
+    let arbitrary = || {
+        let basis = OrthonormalBasis::from_normal(normal);
+        (basis.u, basis.v)
+    };
+    match hit_record.tangent {
+        Some(tangent) => {
+            // Gram-Schmidt, the normal may have been flipped or smoothed
+            let tangent = tangent - tangent.dot(normal) * normal;
+            if tangent.near_zero() {
+                arbitrary()
+            } else {
+                let tangent = tangent.unit_vector();
+                (tangent, normal.cross(tangent))
+            }
+        }
+        None => arbitrary(),
+    }
+}
+
+/// Copy of the hit with `normal` as its shading normal, unless that would
+/// point away from the side the ray came from.
+fn with_shading_normal<'a>(hit_record: &HitRecord<'a>, normal: Vec3) -> HitRecord<'a> {
+    HitRecord {
+        t: hit_record.t,
+        point: hit_record.point,
+        normal: match normal.dot(hit_record.normal) > 0.0 {
+            true => normal.unit_vector(),
+            false => hit_record.normal,
+        },
+        u: hit_record.u,
+        v: hit_record.v,
+        front_face: hit_record.front_face,
+        material: hit_record.material,
+        tangent: hit_record.tangent,
+    }
+}
+
+/// Step in texture space for the finite differences of `BumpMapped`.
+const BUMP_DELTA: f64 = 1e-3;
+
+/// Wraps another material and tilts the shading normal along the slope of a
+/// grayscale height map, which gives flat surfaces some relief. The slope is
+/// estimated by central differences of the luminance of `height` in texture
+/// space, and `strength` scales it.
+pub struct BumpMapped {
+    pub inner: Arc<dyn Material>,
+    pub height: Box<dyn Texture>,
+    pub strength: f64,
+}
+
+impl BumpMapped {
+    pub fn new(inner: Arc<dyn Material>, height: Box<dyn Texture>, strength: f64) -> Self {
+        Self {
+            inner,
+            height,
+            strength,
+        }
+    }
+
+    fn perturbed<'a>(&self, hit_record: &HitRecord<'a>) -> HitRecord<'a> {
+        let (tangent, bitangent) = shading_frame(hit_record);
+        // the point moves along as well, for solid textures
+        let height = |du: f64, dv: f64| {
+            self.height
+                .value(
+                    hit_record.u + du,
+                    hit_record.v + dv,
+                    hit_record.point + du * tangent + dv * bitangent,
+                )
+                .luminance()
+        };
+        let slope_u = (height(BUMP_DELTA, 0.0) - height(-BUMP_DELTA, 0.0)) / (2.0 * BUMP_DELTA);
+        let slope_v = (height(0.0, BUMP_DELTA) - height(0.0, -BUMP_DELTA)) / (2.0 * BUMP_DELTA);
+
+        let perturbed =
+            hit_record.normal - self.strength * (slope_u * tangent + slope_v * bitangent);
+        with_shading_normal(hit_record, perturbed)
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.inner.scatter(ray_in, &self.perturbed(hit_record), rng)
+    }
+
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.inner
+            .scatter_regularized(ray_in, &self.perturbed(hit_record), rng, min_roughness)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.inner.is_specular()
+    }
+
+    fn evaluate(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        self.inner
+            .evaluate(ray_in, &self.perturbed(hit_record), direction)
+    }
+
+    fn emits(&self, ray_in: &Ray, hit_record: &HitRecord) -> Color {
+        self.inner.emits(ray_in, &self.perturbed(hit_record))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn average_emission(&self) -> Color {
+        self.inner.average_emission()
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        self.inner.scatter(ray_in, &self.perturbed(hit_record), rng)
+    }
+
+    fn scatter_regularized(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+        min_roughness: f64,
+    ) -> Option<Scatter> {
+        self.inner
+            .scatter_regularized(ray_in, &self.perturbed(hit_record), rng, min_roughness)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.inner.is_specular()
+    }
+
+    fn evaluate(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        self.inner
+            .evaluate(ray_in, &self.perturbed(hit_record), direction)
+    }
+
+    fn emits(&self, ray_in: &Ray, hit_record: &HitRecord) -> Color {
+        self.inner.emits(ray_in, &self.perturbed(hit_record))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn average_emission(&self) -> Color {
+        self.inner.average_emission()
+    }
+}
+
+/// Emitting surface. Emits only from the front face unless made two-sided.
 pub struct DiffuseLightMaterial {
     pub emit: Box<dyn Texture>,
+    /// Factor the texture is scaled with, so that emitters can be brighter
+    /// than what a texture like an `ImageTexture` can hold.
+    pub intensity: f64,
+    pub two_sided: bool,
 }
 
 impl DiffuseLightMaterial {
-    pub fn new_from_color(color: Color) -> Self {
+    pub fn new(emit: Box<dyn Texture>) -> Self {
         Self {
-            emit: Box::new(SolidColorTexture::new(color)),
+            emit,
+            intensity: 1.0,
+            two_sided: false,
         }
     }
+
+    pub fn new_from_color(color: Color) -> Self {
+        Self::new(Box::new(SolidColorTexture::new(color)))
+    }
+
+    pub fn new_from_color_with_intensity(color: Color, intensity: f64) -> Self {
+        Self::new_from_color(color).with_intensity(intensity)
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Emit from the back face as well.
+    pub fn two_sided(mut self) -> Self {
+        self.two_sided = true;
+        self
+    }
 }
 
 impl Material for DiffuseLightMaterial {
     fn emits(&self, _: &Ray, hit_record: &HitRecord) -> Color {
-        if hit_record.front_face {
-            self.emit
-                .value(hit_record.u, hit_record.v, hit_record.point)
+        if hit_record.front_face || self.two_sided {
+            self.intensity
+                * self.emit.value_with_normal(
+                    hit_record.u,
+                    hit_record.v,
+                    hit_record.point,
+                    hit_record.normal,
+                )
         } else {
             Color::default()
         }
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn average_emission(&self) -> Color {
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        sides * self.intensity * self.emit.value(0.5, 0.5, Vec3::default())
+    }
+}
+
+/// Emitting surface which only lights a cone around `direction`, like a
+/// stage light. Within `inner_angle` of the axis it emits fully, beyond
+/// `outer_angle` not at all, and in between it falls off smoothly. Angles are
+/// in degrees and only the front face emits.
+pub struct SpotLightMaterial {
+    pub emit: Box<dyn Texture>,
+    /// See `DiffuseLightMaterial::intensity`.
+    pub intensity: f64,
+    direction: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLightMaterial {
+    pub fn new(
+        emit: Box<dyn Texture>,
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let outer_angle = outer_angle.clamp(0.0, 180.0);
+        let inner_angle = inner_angle.clamp(0.0, outer_angle);
+        Self {
+            emit,
+            intensity: 1.0,
+            direction: direction.unit_vector(),
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    pub fn new_from_color(
+        color: Color,
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        Self::new(
+            Box::new(SolidColorTexture::new(color)),
+            direction,
+            inner_angle,
+            outer_angle,
+        )
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Share of the full emission sent out along `outgoing`, a unit vector.
+    fn falloff(&self, outgoing: Vec3) -> f64 {
+        let cos_angle = outgoing.dot(self.direction);
+        if cos_angle <= self.cos_outer {
+            return 0.0;
+        }
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+        let t = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Material for SpotLightMaterial {
+    fn emits(&self, ray_in: &Ray, hit_record: &HitRecord) -> Color {
+        if !hit_record.front_face {
+            return Color::default();
+        }
+        let outgoing = -ray_in.direction.unit_vector();
+        if outgoing.dot(hit_record.normal) <= 0.0 {
+            return Color::default();
+        }
+        self.falloff(outgoing)
+            * self.intensity
+            * self.emit.value_with_normal(
+                hit_record.u,
+                hit_record.v,
+                hit_record.point,
+                hit_record.normal,
+            )
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn average_emission(&self) -> Color {
+        // the cone covers about this share of the hemisphere
+        let share = (1.0 - 0.5 * (self.cos_inner + self.cos_outer)).min(1.0);
+        share * self.intensity * self.emit.value(0.5, 0.5, Vec3::default())
+    }
+}
+
+/// Phase function of a participating medium, scattering equally into all
+/// directions. See `ConstantMedium`.
+pub struct IsotropicMaterial {
+    pub albedo: Box<dyn Texture>,
+}
+
+impl IsotropicMaterial {
+    pub fn new(albedo: Box<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    pub fn new_from_color(color: Color) -> Self {
+        Self {
+            albedo: Box::new(SolidColorTexture::new(color)),
+        }
+    }
+}
+
+impl Material for IsotropicMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Scatter> {
+        Some(Scatter {
+            scattered_ray: Ray::new_at_time(
+                hit_record.point,
+                Vec3::random_on_unitsphere(rng),
+                ray_in.time,
+            ),
+            attenuation: self.albedo.value_with_normal(
+                hit_record.u,
+                hit_record.v,
+                hit_record.point,
+                hit_record.normal,
+            ),
+        })
+    }
+
+    fn evaluate(&self, _: &Ray, hit_record: &HitRecord, _: Vec3) -> Option<(Color, f64)> {
+        let pdf = 1.0 / (4.0 * std::f64::consts::PI);
+        let albedo = self.albedo.value_with_normal(
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+            hit_record.normal,
+        );
+        Some((pdf * albedo, pdf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::{
+        geometry::{Hittable, Plane, Sphere},
+        texture::{GradientAxis, GradientTexture},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(LambertianMaterial::new_from_color(Color::default()))
+    }
+
+    /// Hit of `ray` on a unit sphere around the origin, for tests which only
+    /// need a hit with a normal and texture coordinates.
+    fn unit_sphere_hit(ray: &Ray) -> HitRecord<'static> {
+        static SPHERE: OnceLock<Sphere> = OnceLock::new();
+        SPHERE
+            .get_or_init(|| Sphere::new(Vec3::default(), 1.0, material()))
+            .hit(ray, 0.001, f64::INFINITY)
+            .unwrap()
+    }
+
+    /// Hit of `ray` on the plane y = 0.
+    fn ground_hit(ray: &Ray) -> HitRecord<'static> {
+        static PLANE: OnceLock<Plane> = OnceLock::new();
+        PLANE
+            .get_or_init(|| Plane::new(Vec3::default(), Vec3::new(0.0, 1.0, 0.0), material()))
+            .hit(ray, 0.001, f64::INFINITY)
+            .unwrap()
+    }
+
+    #[test]
+    fn conductors_reflect_more_at_grazing_angles() {
+        for conductor in [
+            ConductorMaterial::gold(0.0),
+            ConductorMaterial::copper(0.0),
+            ConductorMaterial::aluminum(0.0),
+        ] {
+            // metals can dip a little at intermediate angles, but end up
+            // reflecting more near grazing than head on
+            let average = |color: Color| (color.x() + color.y() + color.z()) / 3.0;
+            let head_on = conductor.reflectance(1.0);
+            assert!(average(conductor.reflectance(0.02)) > average(head_on));
+            for cosine in [0.8, 0.5, 0.2, 0.05] {
+                let reflectance = conductor.reflectance(cosine);
+                assert!(reflectance
+                    .e
+                    .iter()
+                    .all(|&value| (0.0..=1.0).contains(&value)));
+            }
+            // at grazing incidence every metal becomes a white mirror
+            assert!(conductor.reflectance(0.0).x() > 0.999);
+        }
+
+        // gold is yellow head on: more red than blue
+        let gold = ConductorMaterial::gold(0.0).reflectance(1.0);
+        assert!(gold.x() > 0.9 && gold.z() < 0.5, "{:?}", gold);
+    }
+
+    #[test]
+    fn absorbing_glass_darkens_with_thickness() {
+        let green_glass =
+            Arc::new(DielectricMaterial::new(1.5).with_absorption(Color::new(1.0, 0.1, 1.0)));
+        let mut rng = StdRng::seed_from_u64(1316);
+        // from the center to the back face, through `radius` of glass
+        let transmitted = |radius: f64, rng: &mut dyn RngCore| {
+            let sphere = Sphere::new(Vec3::default(), radius, green_glass.clone());
+            let ray = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, -2.0));
+            let hit_record = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!(!hit_record.front_face);
+            green_glass
+                .scatter(&ray, &hit_record, rng)
+                .unwrap()
+                .attenuation
+        };
+
+        let thin = transmitted(0.5, &mut rng);
+        let thick = transmitted(2.0, &mut rng);
+        assert!(((-0.5f64).exp() - thin.x()).abs() < 1e-9);
+        assert!(thick.x() < thin.x() && thick.y() < thin.y());
+        assert!(thick.y() > thick.x() && thick.y() > thick.z());
+
+        // entering the glass does not absorb anything yet
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = unit_sphere_hit(&ray);
+        let entering = green_glass.scatter(&ray, &hit_record, &mut rng).unwrap();
+        assert_eq!(1.0, entering.attenuation.x());
+    }
+
+    #[test]
+    fn microfacet_roughness_spreads_reflections() {
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit_record = ground_hit(&ray);
+        let mirror = Vec3::new(1.0, 1.0, 0.0).unit_vector();
+        let mut rng = StdRng::seed_from_u64(1285);
+
+        // mean cosine between scattered rays and the mirror direction
+        let mut alignment = |material: &MicrofacetMaterial| {
+            let samples = 2000;
+            (0..samples)
+                .filter_map(|_| material.scatter(&ray, &hit_record, &mut rng))
+                .map(|scatter| scatter.scattered_ray.direction.unit_vector().dot(mirror))
+                .sum::<f64>()
+                / samples as f64
+        };
+
+        let polished = MicrofacetMaterial::new_from_color(Color::new(0.9, 0.9, 0.9), 0.01, 1.0);
+        assert!(alignment(&polished) > 0.999);
+
+        let rough = MicrofacetMaterial::new_from_color(Color::new(0.9, 0.9, 0.9), 1.0, 1.0);
+        let diffuse = MicrofacetMaterial::new_from_color(Color::new(0.9, 0.9, 0.9), 1.0, 0.0);
+        assert!(alignment(&rough) < 0.85);
+        assert!(alignment(&diffuse) < 0.85);
+    }
+
+    #[test]
+    fn normal_maps_bend_the_normal() {
+        let ray = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit_record = unit_sphere_hit(&ray);
+        let inner = material();
+
+        // a flat normal map changes nothing
+        let flat = NormalMapped::new(
+            inner.clone(),
+            Box::new(SolidColorTexture::new(Color::new(0.5, 0.5, 1.0))),
+        );
+        assert!((flat.perturbed(&hit_record).normal - hit_record.normal).near_zero());
+
+        // tilted towards the tangent, which runs around the y axis
+        let tilted = NormalMapped::new(
+            inner,
+            Box::new(SolidColorTexture::new(Color::new(1.0, 0.5, 1.0))),
+        );
+        let normal = tilted.perturbed(&hit_record).normal;
+        let expected = Vec3::new(1.0, 0.0, -1.0).unit_vector();
+        assert!((normal - expected).near_zero(), "{:?}", normal);
+    }
+
+    #[test]
+    fn bump_maps_follow_the_height_slope() {
+        let ray = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit_record = unit_sphere_hit(&ray);
+        let inner = material();
+
+        // a constant height changes nothing
+        let flat = BumpMapped::new(
+            inner.clone(),
+            Box::new(SolidColorTexture::new(Color::new(0.7, 0.7, 0.7))),
+            5.0,
+        );
+        assert!((flat.perturbed(&hit_record).normal - hit_record.normal).near_zero());
+
+        // rising along u, which runs around the y axis, tilts the normal back
+        let ramp = BumpMapped::new(
+            inner,
+            Box::new(GradientTexture::new(
+                GradientAxis::U,
+                0.0,
+                1.0,
+                Color::new(0.0, 0.0, 0.0),
+                Color::new(1.0, 1.0, 1.0),
+            )),
+            1.0,
+        );
+        let normal = ramp.perturbed(&hit_record).normal;
+        let expected = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        assert!((normal - expected).near_zero(), "{:?}", normal);
+    }
+
+    #[test]
+    fn two_sided_lights_emit_from_the_back() {
+        let outside = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let inside = Ray::new(Vec3::default(), Vec3::new(-1.0, 0.0, 0.0));
+        let front = unit_sphere_hit(&outside);
+        let back = unit_sphere_hit(&inside);
+
+        let color = Color::new(1.0, 0.5, 0.25);
+        let one_sided = DiffuseLightMaterial::new_from_color_with_intensity(color, 4.0);
+        assert!((one_sided.emits(&outside, &front) - 4.0 * color).near_zero());
+        assert!(one_sided.emits(&inside, &back).near_zero());
+
+        let two_sided = DiffuseLightMaterial::new_from_color_with_intensity(color, 4.0).two_sided();
+        assert!((two_sided.emits(&inside, &back) - 4.0 * color).near_zero());
+        assert!((two_sided.average_emission() - 2.0 * one_sided.average_emission()).near_zero());
+    }
+
+    #[test]
+    fn spot_lights_emit_only_within_their_cone() {
+        let color = Color::new(1.0, 0.5, 0.25);
+        let spot = SpotLightMaterial::new_from_color(color, Vec3::new(0.0, -1.0, 0.0), 20.0, 40.0)
+            .with_intensity(4.0);
+        // seen from `degrees` off the axis, looking at the point of the
+        // sphere facing the viewer
+        let emitted = |degrees: f64| {
+            let radians = f64::to_radians(degrees);
+            let outgoing = Vec3::new(radians.sin(), -radians.cos(), 0.0);
+            let ray = Ray::new(2.0 * outgoing, -outgoing);
+            let hit_record = unit_sphere_hit(&ray);
+            spot.emits(&ray, &hit_record)
+        };
+
+        assert!((emitted(0.0) - 4.0 * color).near_zero());
+        assert!((emitted(15.0) - 4.0 * color).near_zero());
+        let falloff = emitted(30.0).x() / 4.0;
+        assert!(falloff > 0.0 && falloff < 1.0, "{}", falloff);
+        assert!(emitted(25.0).x() > emitted(35.0).x());
+        for degrees in [40.0, 45.0, 90.0, 170.0] {
+            assert!(emitted(degrees).near_zero(), "{}", degrees);
+        }
+    }
+
+    #[test]
+    fn smooth_rough_dielectric_matches_dielectric() {
+        let dielectric = DielectricMaterial::new(1.5);
+        let rough = RoughDielectricMaterial::new(1.5, 0.0);
+
+        for origin in [Vec3::new(2.0, 0.3, 0.1), Vec3::new(0.1, -0.2, 0.3)] {
+            let ray = Ray::new(origin, Vec3::new(-1.0, 0.1, 0.0));
+            let hit_record = unit_sphere_hit(&ray);
+
+            let mut expected_rng = StdRng::seed_from_u64(7);
+            let mut actual_rng = StdRng::seed_from_u64(7);
+            for _ in 0..32 {
+                let expected = dielectric
+                    .scatter(&ray, &hit_record, &mut expected_rng)
+                    .unwrap();
+                let actual = rough.scatter(&ray, &hit_record, &mut actual_rng).unwrap();
+                assert!(
+                    (expected.scattered_ray.direction - actual.scattered_ray.direction).near_zero()
+                );
+                assert!((expected.attenuation - actual.attenuation).near_zero());
+            }
+        }
+    }
+
+    #[test]
+    fn regularization_raises_the_roughness_floor() {
+        // close to grazing, where roughening easily crosses the surface
+        let ray = Ray::new(Vec3::new(-1.0, 0.2, 0.0), Vec3::new(1.0, -0.2, 0.0));
+        let hit_record = ground_hit(&ray);
+        let smooth = DielectricMaterial::new(1.5);
+        let materials: [Box<dyn Material>; 2] = [
+            Box::new(DielectricMaterial::new(1.5)),
+            Box::new(RoughDielectricMaterial::new(1.5, 0.05)),
+        ];
+
+        for material in materials {
+            // mean deviation from the smooth direction, without and with
+            // regularization
+            let mut spread = (0.0, 0.0);
+            for seed in 0..500 {
+                // the same seed picks reflection or refraction alike
+                let scatter = |material: &dyn Material, min_roughness: Option<f64>| {
+                    let rng = &mut StdRng::seed_from_u64(seed);
+                    match min_roughness {
+                        Some(min_roughness) => {
+                            material.scatter_regularized(&ray, &hit_record, rng, min_roughness)
+                        }
+                        None => material.scatter(&ray, &hit_record, rng),
+                    }
+                    .map(|scatter| scatter.scattered_ray.direction.unit_vector())
+                };
+                let ideal = scatter(&smooth, None).unwrap();
+                let plain = scatter(material.as_ref(), None).unwrap();
+                spread.0 += 1.0 - plain.dot(ideal);
+                if let Some(regularized) = scatter(material.as_ref(), Some(0.5)) {
+                    assert!(
+                        regularized.dot(hit_record.normal) * ideal.dot(hit_record.normal) > 0.0
+                    );
+                    spread.1 += 1.0 - regularized.dot(ideal);
+                }
+            }
+            assert!(spread.1 > 2.0 * spread.0 + 1.0, "{:?}", spread);
+        }
+    }
+
+    #[test]
+    fn diffuse_sampling_strategies_agree() {
+        let ray = Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit_record = unit_sphere_hit(&ray);
+        let albedo = Color::new(0.8, 0.5, 0.2);
+
+        // under a sky as bright as the cosine to the normal, a Lambertian
+        // surface reflects albedo / π · ∫ cos² dω = 2/3 of its albedo
+        let mut rng = StdRng::seed_from_u64(1335);
+        for sampling in [DiffuseSampling::Uniform, DiffuseSampling::Cosine] {
+            let material = LambertianMaterial::new_from_color(albedo).with_sampling(sampling);
+            let samples = 100_000;
+            let mut sum = Color::default();
+            for _ in 0..samples {
+                let scatter = material.scatter(&ray, &hit_record, &mut rng).unwrap();
+                let direction = scatter.scattered_ray.direction.unit_vector();
+                assert!(direction.y() >= 0.0);
+                sum += scatter.attenuation * direction.y();
+            }
+            let average = sum / samples as f64;
+            assert!(
+                (average - 2.0 / 3.0 * albedo).len() < 0.01,
+                "{:?}: {:?}",
+                sampling,
+                average
+            );
+        }
+    }
 }
@@ -1,10 +1,12 @@
-use std::{path::Path, sync::Arc};
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
 
 use crate::{
     bvh::{Aabb, BvhNode},
+    error::PathtracerError,
     geometry::{HitRecord, Hittable, Triangle},
     material::{DielectricMaterial, LambertianMaterial, Material, MetalMaterial},
     ray::Ray,
+    texture::ImageTexture,
     vec3::{Color, Vec3},
 };
 
@@ -16,66 +18,50 @@ pub struct ObjModel {
 }
 
 impl ObjModel {
-    pub fn new_from_path(path: &Path) -> Self {
+    pub fn new_from_path(path: &Path) -> Result<Self, PathtracerError> {
         let load_options = tobj::LoadOptions {
             single_index: false,
             ignore_lines: true,
             ignore_points: true,
             triangulate: true,
         };
-        let (models, materials) = tobj::load_obj(path, &load_options).unwrap();
+        // Referenced MTL files are resolved relative to the OBJ file, not the
+        // current working directory.
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut reader = BufReader::new(File::open(path)?);
+        let (models, materials) = tobj::load_obj_buf(&mut reader, &load_options, |mtl_path| {
+            Self::load_materials(&directory.join(mtl_path))
+        })?;
 
-        let materials_mapped: Vec<Arc<dyn Material>> = materials
-            .unwrap()
+        let materials_mapped: Vec<Arc<dyn Material>> = materials?
             .iter()
             .map(|m| {
                 let material: Arc<dyn Material> = match m.illumination_model {
                     Some(7) => Arc::new(DielectricMaterial::new(m.optical_density.into())),
                     Some(5) => Arc::new(MetalMaterial::new_from_color(
-                        Color::new(
-                            m.diffuse[0].into(),
-                            m.diffuse[1].into(),
-                            m.diffuse[2].into(),
-                        ),
+                        Color::from(m.diffuse.map(f64::from)),
                         1.0 / <f32 as std::convert::Into<f64>>::into(m.shininess),
                     )),
-                    _ => Arc::new(LambertianMaterial::new_from_color(Color::new(
-                        m.diffuse[0].into(),
-                        m.diffuse[1].into(),
-                        m.diffuse[2].into(),
+                    // missing textures show up magenta, see `ImageTexture`
+                    _ if !m.diffuse_texture.is_empty() => Arc::new(LambertianMaterial::new(
+                        Box::new(ImageTexture::new_from_path(Path::new(&m.diffuse_texture))),
+                    )),
+                    _ => Arc::new(LambertianMaterial::new_from_color(Color::from(
+                        m.diffuse.map(f64::from),
                     ))),
                 };
                 material
             })
             .collect();
 
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
         for model in models {
             let mesh = model.mesh;
 
             for triangle_index in 0..(mesh.indices.len() / 3) {
-                let (vertex_index0, vertex_index1, vertex_index2) = (
-                    mesh.indices[triangle_index * 3] as usize,
-                    mesh.indices[triangle_index * 3 + 1] as usize,
-                    mesh.indices[triangle_index * 3 + 2] as usize,
-                );
-                let (vertex0, vertex1, vertex2) = (
-                    Vec3::new(
-                        mesh.positions[vertex_index0 * 3].into(),
-                        mesh.positions[vertex_index0 * 3 + 1].into(),
-                        mesh.positions[vertex_index0 * 3 + 2].into(),
-                    ),
-                    Vec3::new(
-                        mesh.positions[vertex_index1 * 3].into(),
-                        mesh.positions[vertex_index1 * 3 + 1].into(),
-                        mesh.positions[vertex_index1 * 3 + 2].into(),
-                    ),
-                    Vec3::new(
-                        mesh.positions[vertex_index2 * 3].into(),
-                        mesh.positions[vertex_index2 * 3 + 1].into(),
-                        mesh.positions[vertex_index2 * 3 + 2].into(),
-                    ),
-                );
+                let corners = [0, 1, 2].map(|corner| triangle_index * 3 + corner);
+                let [vertex0, vertex1, vertex2] = corners
+                    .map(|corner| Self::vec3_at(&mesh.positions, mesh.indices[corner] as usize));
 
                 let material = match mesh.material_id {
                     Some(i) => materials_mapped[i].clone(),
@@ -84,30 +70,74 @@ impl ObjModel {
                     ))),
                 };
 
-                let triangle = if mesh.normals.is_empty() {
-                    Triangle::new_without_normal(vertex0, vertex1, vertex2, material)
-                } else {
-                    let normal = Vec3::new(
-                        mesh.normals[vertex_index0 * 3].into(),
-                        mesh.normals[vertex_index0 * 3 + 1].into(),
-                        mesh.normals[vertex_index0 * 3 + 2].into(),
+                let mut triangle =
+                    Triangle::new_without_normal(vertex0, vertex1, vertex2, material);
+                // Normals and texture coordinates have their own indices, unless
+                // they share the ones of the positions.
+                if !mesh.normals.is_empty() {
+                    let indices = match mesh.normal_indices.is_empty() {
+                        true => &mesh.indices,
+                        false => &mesh.normal_indices,
+                    };
+                    triangle = triangle.with_vertex_normals(
+                        corners
+                            .map(|corner| Self::vec3_at(&mesh.normals, indices[corner] as usize)),
                     );
-                    Triangle::new(vertex0, vertex1, vertex2, normal, material)
-                };
+                }
+                if !mesh.texcoords.is_empty() {
+                    let indices = match mesh.texcoord_indices.is_empty() {
+                        true => &mesh.indices,
+                        false => &mesh.texcoord_indices,
+                    };
+                    triangle = triangle.with_texture_coordinates(corners.map(|corner| {
+                        let index = indices[corner] as usize;
+                        (
+                            mesh.texcoords[index * 2].into(),
+                            mesh.texcoords[index * 2 + 1].into(),
+                        )
+                    }));
+                }
 
-                world.push(Box::new(triangle));
+                world.push(Arc::new(triangle));
             }
         }
 
-        let bb = world.bounding_box();
-        let minimum = bb.minimum;
-        let maximum = bb.maximum;
+        let triangles = BvhNode::new(world)?;
+        let bb = triangles.bounding_box();
+
+        Ok(Self {
+            triangles,
+            minimum: bb.minimum,
+            maximum: bb.maximum,
+        })
+    }
+
+    fn vec3_at(values: &[f32], index: usize) -> Vec3 {
+        Vec3::from([0, 1, 2].map(|axis| values[index * 3 + axis].into()))
+    }
+
+    /// Loads an MTL file and makes its texture paths relative to the
+    /// directory of the MTL file.
+    fn load_materials(path: &Path) -> tobj::MTLLoadResult {
+        let (mut materials, names) = tobj::load_mtl(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
 
-        Self {
-            triangles: BvhNode::new(world),
-            minimum,
-            maximum,
+        for material in materials.iter_mut() {
+            for texture in [
+                &mut material.ambient_texture,
+                &mut material.diffuse_texture,
+                &mut material.specular_texture,
+                &mut material.normal_texture,
+                &mut material.shininess_texture,
+                &mut material.dissolve_texture,
+            ] {
+                if !texture.is_empty() {
+                    *texture = directory.join(&*texture).to_string_lossy().into_owned();
+                }
+            }
         }
+
+        Ok((materials, names))
     }
 }
 
@@ -116,7 +146,75 @@ impl Hittable for ObjModel {
         Aabb::new(self.minimum, self.maximum)
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         self.triangles.hit(ray, t_min, t_max)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn material_paths_are_relative_to_their_files() {
+        let directory = std::env::temp_dir().join("pathtracer_obj_relative_paths");
+        fs::create_dir_all(directory.join("materials")).unwrap();
+        fs::write(
+            directory.join("model.obj"),
+            "mtllib materials/model.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nusemtl red\nf 1 2 3\nf 1 2 4\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("materials/model.mtl"),
+            "newmtl red\nKd 1 0 0\nmap_Kd textures/red.png\n",
+        )
+        .unwrap();
+
+        ObjModel::new_from_path(&directory.join("model.obj")).unwrap();
+
+        let (materials, _) =
+            ObjModel::load_materials(&directory.join("materials/model.mtl")).unwrap();
+        assert_eq!(
+            directory.join("materials/textures/red.png"),
+            Path::new(&materials[0].diffuse_texture)
+        );
+    }
+
+    #[test]
+    fn loading_errors_are_returned() {
+        let missing = std::env::temp_dir().join("pathtracer_missing/model.obj");
+        assert!(matches!(
+            ObjModel::new_from_path(&missing),
+            Err(PathtracerError::Io(_))
+        ));
+
+        let directory = std::env::temp_dir().join("pathtracer_obj_empty");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("empty.obj"), "v 0 0 0\n").unwrap();
+        assert!(matches!(
+            ObjModel::new_from_path(&directory.join("empty.obj")),
+            Err(PathtracerError::EmptyScene)
+        ));
+    }
+
+    #[test]
+    fn diffuse_textures_are_mapped_with_uvs() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/textured_quad/quad.obj");
+        let model = ObjModel::new_from_path(&path).unwrap();
+
+        // the upper left quarter of the quad shows the red upper left pixel
+        let ray = Ray::new(Vec3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = model.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.u - 0.25).abs() < 1e-9);
+        assert!((hit_record.v - 0.75).abs() < 1e-9);
+
+        let mut rng = rand::thread_rng();
+        let scatter = hit_record
+            .material
+            .scatter(&ray, &hit_record, &mut rng)
+            .unwrap();
+        assert!((scatter.attenuation - Color::new(1.0, 0.0, 0.0)).near_zero());
+    }
+}
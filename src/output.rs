@@ -0,0 +1,338 @@
+#[cfg(test)]
+use std::io::BufRead;
+use std::io::Write;
+
+use crate::{error::PathtracerError, vec3::Color};
+
+/// Writes 8 bit RGB pixels, row by row from the top, as a PNG image.
+pub fn write_png<W: Write>(
+    writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<(), PathtracerError> {
+    write_png_as(writer, width, height, pixels, png::ColorType::Rgb)
+}
+
+/// Writes 8 bit RGBA pixels, row by row from the top, as a PNG image with
+/// an alpha channel, see `renderer::to_rgba`.
+pub fn write_png_rgba<W: Write>(
+    writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<(), PathtracerError> {
+    write_png_as(writer, width, height, pixels, png::ColorType::Rgba)
+}
+
+fn write_png_as<W: Write>(
+    writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    color_type: png::ColorType,
+) -> Result<(), PathtracerError> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(color_type);
+    let mut writer = encoder.write_header()?;
+
+    Ok(writer.write_image_data(pixels)?)
+}
+
+/// Speed of the color quantization of GIF frames, from 1 (best) to 30.
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Writes 8 bit RGB frames, row by row from the top, one after another into
+/// an animated GIF which loops forever. Every frame gets its own palette of
+/// 256 colors.
+pub struct GifWriter<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u16,
+    height: u16,
+    /// Time every frame is shown, in hundredths of a second.
+    delay: u16,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Starts an animation playing at `fps` frames per second. GIFs can't be
+    /// larger than 65535 pixels in either direction.
+    pub fn new(writer: W, width: usize, height: usize, fps: f64) -> Result<Self, PathtracerError> {
+        let size = (u16::try_from(width), u16::try_from(height));
+        let (Ok(gif_width), Ok(gif_height)) = size else {
+            return Err(PathtracerError::InvalidImageSize { width, height });
+        };
+        if gif_width == 0 || gif_height == 0 {
+            return Err(PathtracerError::InvalidImageSize { width, height });
+        }
+
+        let mut encoder = gif::Encoder::new(writer, gif_width, gif_height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            width: gif_width,
+            height: gif_height,
+            delay: (100.0 / fps).round().clamp(1.0, u16::MAX as f64) as u16,
+        })
+    }
+
+    pub fn write_frame(&mut self, pixels: &[u8]) -> Result<(), PathtracerError> {
+        let mut frame =
+            gif::Frame::from_rgb_speed(self.width, self.height, pixels, GIF_QUANTIZATION_SPEED);
+        frame.delay = self.delay;
+        Ok(self.encoder.write_frame(&frame)?)
+    }
+
+    /// Ends the animation and returns the underlying writer.
+    pub fn finish(self) -> Result<W, PathtracerError> {
+        Ok(self.encoder.into_inner()?)
+    }
+}
+
+/// Writes linear colors as a NumPy `.npy` array of 32 bit floats with the
+/// shape `(height, width, 3)`.
+pub fn write_npy<W: Write>(
+    mut writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> Result<(), PathtracerError> {
+    if width.checked_mul(height) != Some(pixels.len()) {
+        return Err(PathtracerError::InvalidImageSize { width, height });
+    }
+
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, 3), }}",
+        height, width
+    );
+    // magic (6) + version (2) + header length (2) + header has to be a
+    // multiple of 64 bytes, the header ends with a newline
+    let unpadded_length = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded_length % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+
+    for pixel in pixels {
+        for value in pixel.e {
+            writer.write_all(&(value as f32).to_le_bytes())?;
+        }
+    }
+
+    Ok(writer.flush()?)
+}
+
+/// Writes linear colors as an uncompressed Radiance `.hdr` image, which keeps
+/// the full dynamic range with a shared 8 bit exponent per pixel.
+pub fn write_hdr<W: Write>(
+    mut writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> Result<(), PathtracerError> {
+    if width.checked_mul(height) != Some(pixels.len()) {
+        return Err(PathtracerError::InvalidImageSize { width, height });
+    }
+
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    for pixel in pixels {
+        writer.write_all(&to_rgbe(*pixel))?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+/// Reads an uncompressed Radiance `.hdr` image as written by `write_hdr` and
+/// returns its width, height and linear colors. Only used to check the
+/// writer, it trusts the size in the header.
+#[cfg(test)]
+fn read_hdr<R: BufRead>(mut reader: R) -> Result<(usize, usize, Vec<Color>), PathtracerError> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(PathtracerError::Parse(String::from(
+            "missing radiance header",
+        )));
+    }
+
+    // the header ends with an empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(PathtracerError::Parse(String::from(
+                "unexpected end of radiance header",
+            )));
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line.trim() != "FORMAT=32-bit_rle_rgbe" {
+            return Err(PathtracerError::Parse(format!(
+                "unsupported radiance format {}",
+                line.trim()
+            )));
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse(), width.parse()),
+        _ => {
+            return Err(PathtracerError::Parse(format!(
+                "unsupported radiance resolution {}",
+                line.trim()
+            )))
+        }
+    };
+    let (height, width): (usize, usize) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => return Err(PathtracerError::Parse(String::from("invalid image size"))),
+    };
+
+    let mut data = vec![0; width * height * 4];
+    reader.read_exact(&mut data)?;
+    if width >= 8 && data.starts_with(&[2, 2]) {
+        return Err(PathtracerError::Parse(String::from(
+            "run length encoded radiance images are not supported",
+        )));
+    }
+
+    let pixels = data
+        .chunks_exact(4)
+        .map(|rgbe| from_rgbe([rgbe[0], rgbe[1], rgbe[2], rgbe[3]]))
+        .collect();
+    Ok((width, height, pixels))
+}
+
+/// Packs a color into three mantissas and their shared exponent.
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let color = color.map(|v| v.max(0.0));
+    let max = color.x().max(color.y()).max(color.z());
+    if max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    [
+        (color.x() * scale).min(255.0) as u8,
+        (color.y() * scale).min(255.0) as u8,
+        (color.z() * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+#[cfg(test)]
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::default();
+    }
+
+    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_animations() {
+        let (width, height) = (5, 3);
+        let red: Vec<u8> = [255, 0, 0].repeat(width * height);
+        let blue: Vec<u8> = [0, 0, 255].repeat(width * height);
+        let mut writer = GifWriter::new(vec![], width, height, 25.0).unwrap();
+        writer.write_frame(&red).unwrap();
+        writer.write_frame(&blue).unwrap();
+        let buffer = writer.finish().unwrap();
+        assert!(buffer.starts_with(b"GIF89a"));
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(&buffer[..]).unwrap();
+        assert_eq!((5, 3), (decoder.width(), decoder.height()));
+        let mut frames = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(4, frame.delay);
+            frames.push(frame.buffer[..4].to_vec());
+        }
+        assert_eq!(vec![vec![255, 0, 0, 255], vec![0, 0, 255, 255]], frames);
+
+        assert!(GifWriter::new(vec![], 70_000, 1, 25.0).is_err());
+    }
+
+    #[test]
+    fn npy_layout() {
+        let pixels = vec![Color::new(0.5, 2.0, -1.0); 6];
+        let mut buffer = vec![];
+        write_npy(&mut buffer, 3, 2, &pixels).unwrap();
+
+        assert_eq!(b"\x93NUMPY\x01\x00", &buffer[..8]);
+        let header_length = u16::from_le_bytes([buffer[8], buffer[9]]) as usize;
+        assert_eq!(0, (10 + header_length) % 64);
+
+        let header = std::str::from_utf8(&buffer[10..10 + header_length]).unwrap();
+        assert!(header.contains("'shape': (2, 3, 3)"));
+        assert!(header.ends_with('\n'));
+
+        let data = &buffer[10 + header_length..];
+        assert_eq!(6 * 3 * 4, data.len());
+        assert_eq!(2.0, f32::from_le_bytes(data[4..8].try_into().unwrap()));
+
+        assert!(matches!(
+            write_npy(vec![], 2, 2, &pixels),
+            Err(PathtracerError::InvalidImageSize {
+                width: 2,
+                height: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let pixels = vec![
+            Color::new(12.5, 0.25, 1000.0),
+            Color::default(),
+            Color::new(0.001, 0.002, 0.0),
+            Color::new(-1.0, 1.0, 0.5),
+        ];
+        let mut buffer = vec![];
+        write_hdr(&mut buffer, 2, 2, &pixels).unwrap();
+
+        assert!(matches!(
+            write_hdr(vec![], 2, 3, &pixels),
+            Err(PathtracerError::InvalidImageSize {
+                width: 2,
+                height: 3
+            })
+        ));
+
+        let (width, height, read) = read_hdr(&buffer[..]).unwrap();
+        assert_eq!((2, 2), (width, height));
+        for (expected, actual) in pixels.iter().zip(read) {
+            let expected = expected.map(|v| v.max(0.0));
+            // the shared exponent leaves 8 bits relative to the brightest channel
+            let tolerance = expected.x().max(expected.y()).max(expected.z()) / 128.0;
+            for channel in 0..3 {
+                assert!(
+                    (expected.e[channel] - actual.e[channel]).abs() <= tolerance,
+                    "{:?} != {:?}",
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+}
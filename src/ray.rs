@@ -1,42 +1,585 @@
+use rand::{Rng, RngCore};
+
 use crate::{
-    geometry::Hittable,
+    environment::Environment,
+    geometry::{HitRecord, Hittable},
+    light::LightSampler,
     vec3::{Color, Vec3},
 };
 
+/// Distance secondary rays start away from the surface they leave, see
+/// `RayEpsilon`.
+pub const DEFAULT_RAY_EPSILON: f64 = 0.001;
+
+/// How far rays leaving a surface have to travel before they can hit
+/// something. Rounding puts hit points slightly off the surface, so without
+/// this rays hit the surface they start from again, which shows up as dark
+/// speckles (shadow acne). Too large a distance lets light leak through
+/// thin walls and into corners instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayEpsilon {
+    /// The same distance everywhere, which suits scenes around unit scale.
+    Absolute(f64),
+    /// The distance times the largest coordinate of the ray origin, but at
+    /// least the distance itself. Rounding errors grow with the coordinates,
+    /// so this suits scenes of any scale, and ones spanning several.
+    Relative(f64),
+}
+
+impl Default for RayEpsilon {
+    fn default() -> Self {
+        Self::Absolute(DEFAULT_RAY_EPSILON)
+    }
+}
+
+impl RayEpsilon {
+    /// Smallest `t` for hits along `ray`, which does not need a unit
+    /// direction.
+    pub fn t_min(&self, ray: &Ray) -> f64 {
+        let distance = match *self {
+            Self::Absolute(distance) => distance,
+            Self::Relative(distance) => {
+                let origin = ray.origin.map(f64::abs);
+                distance * origin.x().max(origin.y()).max(origin.z()).max(1.0)
+            }
+        };
+        distance / ray.direction.len()
+    }
+}
+
+/// Optional variations of the path tracing done by `Ray::color_with`.
+#[derive(Clone, Copy, Default)]
+pub struct PathOptions<'a> {
+    /// See `Ray::color_regularized`.
+    pub min_roughness: Option<f64>,
+    /// Number of bounces after which paths are randomly terminated with a
+    /// probability based on their remaining throughput (Russian roulette).
+    /// Surviving paths are weighted up, so the result stays unbiased.
+    pub roulette_depth: Option<usize>,
+    /// Emitters to sample directly at every diffuse bounce (next-event
+    /// estimation). Light found this way and light hit by chance are
+    /// combined with multiple importance sampling.
+    pub light_sampler: Option<&'a LightSampler>,
+    /// Keeps rays from hitting the surface they leave.
+    pub epsilon: RayEpsilon,
+}
+
+/// Where a path currently is, passed along the recursion of `Ray::trace`.
+#[derive(Clone, Copy)]
+struct PathState {
+    bounces_left: usize,
+    depth: usize,
+    throughput: Color,
+    after_diffuse: bool,
+    /// Density with which the previous bounce picked this ray, if that bounce
+    /// also sampled the lights.
+    bsdf_pdf: Option<f64>,
+}
+
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Point in time within the camera shutter interval, for motion blur.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self::new_at_time(origin, direction, 0.0)
+    }
+
+    pub fn new_at_time(origin: Vec3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
     }
 
-    pub fn color(&self, hittable: &impl Hittable, background: Color, bounces_left: usize) -> Color {
-        if bounces_left == 0 {
+    pub fn color(
+        &self,
+        hittable: &(impl Hittable + ?Sized),
+        environment: &Environment,
+        bounces_left: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        self.color_with(
+            hittable,
+            environment,
+            bounces_left,
+            &PathOptions::default(),
+            rng,
+        )
+    }
+
+    /// Like `color`, but once the path went through a non-specular bounce,
+    /// specular and glossy materials are roughened to at least
+    /// `min_roughness`. This trades a bit of bias for a lot less caustic
+    /// noise, while highlights seen directly stay crisp.
+    pub fn color_regularized(
+        &self,
+        hittable: &(impl Hittable + ?Sized),
+        environment: &Environment,
+        bounces_left: usize,
+        min_roughness: f64,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let options = PathOptions {
+            min_roughness: Some(min_roughness),
+            ..Default::default()
+        };
+        self.color_with(hittable, environment, bounces_left, &options, rng)
+    }
+
+    pub fn color_with(
+        &self,
+        hittable: &(impl Hittable + ?Sized),
+        environment: &Environment,
+        bounces_left: usize,
+        options: &PathOptions,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let state = PathState {
+            bounces_left,
+            depth: 0,
+            throughput: Color::new(1.0, 1.0, 1.0),
+            after_diffuse: false,
+            bsdf_pdf: None,
+        };
+        self.trace(hittable, environment, options, state, rng)
+    }
+
+    /// Cheap shading for previews: the albedo of the first hit, lit by a
+    /// light at the camera, plus whatever the surface emits. No secondary
+    /// rays are traced.
+    pub fn preview_color(
+        &self,
+        hittable: &(impl Hittable + ?Sized),
+        environment: &Environment,
+        epsilon: RayEpsilon,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        match hittable.hit_with_rng(self, epsilon.t_min(self), f64::INFINITY, rng) {
+            Some(hit_record) => {
+                let material = hit_record.material;
+                let facing = hit_record.normal.dot(self.direction.unit_vector()).abs();
+                let albedo = material
+                    .scatter(self, &hit_record, rng)
+                    .map_or(Color::default(), |scatter| scatter.attenuation);
+
+                material.emits(self, &hit_record) + facing * albedo
+            }
+            None => environment.value(self.direction),
+        }
+    }
+
+    fn trace(
+        &self,
+        hittable: &(impl Hittable + ?Sized),
+        environment: &Environment,
+        options: &PathOptions,
+        state: PathState,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        if state.bounces_left == 0 {
             return Color::default();
         }
 
-        if let Some(hit_record) = hittable.hit(self, 0.001, f64::INFINITY) {
-            let emitted = hit_record.material.emits(self, &hit_record);
+        if let Some(hit_record) =
+            hittable.hit_with_rng(self, options.epsilon.t_min(self), f64::INFINITY, rng)
+        {
+            let material = hit_record.material;
+            let mut emitted = material.emits(self, &hit_record);
+            if let (Some(light_sampler), Some(bsdf_pdf)) = (options.light_sampler, state.bsdf_pdf) {
+                let light_pdf = light_sampler.pdf_value(self.origin, self.direction);
+                emitted *= power_heuristic(bsdf_pdf, light_pdf);
+            }
+
+            let direct = match options.light_sampler {
+                Some(light_sampler) if state.bounces_left > 1 => {
+                    Self::sample_light(hittable, light_sampler, options, self, &hit_record, rng)
+                }
+                _ => None,
+            };
+            let emitted = emitted + direct.unwrap_or_default();
+
+            let scatter = match options.min_roughness {
+                Some(min_roughness) if state.after_diffuse => {
+                    material.scatter_regularized(self, &hit_record, rng, min_roughness)
+                }
+                _ => material.scatter(self, &hit_record, rng),
+            };
+
+            if let Some(scatter) = scatter {
+                let mut attenuation = scatter.attenuation;
+                let throughput = state.throughput * attenuation;
 
-            if let Some(scatter) = hit_record.material.scatter(self, &hit_record) {
+                if options
+                    .roulette_depth
+                    .is_some_and(|roulette_depth| state.depth >= roulette_depth)
+                {
+                    let survival = throughput
+                        .x()
+                        .max(throughput.y())
+                        .max(throughput.z())
+                        .min(0.95);
+                    if rng.gen::<f64>() >= survival {
+                        return emitted;
+                    }
+                    attenuation /= survival;
+                }
+
+                let next_state = PathState {
+                    bounces_left: state.bounces_left - 1,
+                    depth: state.depth + 1,
+                    throughput: state.throughput * attenuation,
+                    after_diffuse: state.after_diffuse || !material.is_specular(),
+                    bsdf_pdf: direct
+                        .and(material.evaluate(self, &hit_record, scatter.scattered_ray.direction))
+                        .map(|(_, pdf)| pdf),
+                };
                 return emitted
-                    + scatter.attenuation
-                        * scatter
-                            .scattered_ray
-                            .color(hittable, background, bounces_left - 1);
+                    + attenuation
+                        * scatter.scattered_ray.trace(
+                            hittable,
+                            environment,
+                            options,
+                            next_state,
+                            rng,
+                        );
             }
 
             return emitted;
         }
 
-        // Background
-        background
+        environment.value(self.direction)
+    }
+
+    /// Light arriving at the hit directly from a randomly picked light,
+    /// weighted against finding the same light through `scatter`. `None` if
+    /// the material can not be lit this way.
+    fn sample_light(
+        hittable: &(impl Hittable + ?Sized),
+        light_sampler: &LightSampler,
+        options: &PathOptions,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<Color> {
+        // specular materials can not be evaluated for arbitrary directions
+        let material = hit_record.material;
+        material.evaluate(ray_in, hit_record, hit_record.normal)?;
+
+        let direction = match light_sampler.random(hit_record.point, rng) {
+            Some(direction) => direction,
+            None => return Some(Color::default()),
+        };
+        let light_pdf = light_sampler.pdf_value(hit_record.point, direction);
+        let (bsdf_cos, bsdf_pdf) = material.evaluate(ray_in, hit_record, direction)?;
+        if light_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+            return Some(Color::default());
+        }
+
+        let shadow_ray = Ray::new_at_time(hit_record.point, direction, ray_in.time);
+        let t_min = options.epsilon.t_min(&shadow_ray);
+        let light = match hittable.hit_with_rng(&shadow_ray, t_min, f64::INFINITY, rng) {
+            Some(light_hit) => light_hit.material.emits(&shadow_ray, &light_hit),
+            None => return Some(Color::default()),
+        };
+
+        Some(power_heuristic(light_pdf, bsdf_pdf) / light_pdf * bsdf_cos * light)
+    }
+}
+
+/// Weight for a sample taken with density `pdf` which could also have been
+/// taken by another strategy with density `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        geometry::{Cylinder, Disk, HittableList, RectangleXZ, Sphere, Triangle},
+        material::{DiffuseLightMaterial, LambertianMaterial},
+    };
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        // rays bounce many times between two bright disks before escaping
+        let material = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.9, 0.9, 0.9,
+        )));
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Disk::new(
+                Vec3::default(),
+                Vec3::new(0.0, 1.0, 0.0),
+                3.0,
+                material.clone(),
+            )),
+            Arc::new(Disk::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                3.0,
+                material,
+            )),
+        ];
+        let world = HittableList::from(world);
+        let ray = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let environment = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+        let mut rng = StdRng::seed_from_u64(1267);
+
+        let samples = 20000;
+        let mut mean = |options: &PathOptions| {
+            (0..samples)
+                .map(|_| {
+                    ray.color_with(&world, &environment, 20, options, &mut rng)
+                        .x()
+                })
+                .sum::<f64>()
+                / samples as f64
+        };
+        let without = mean(&PathOptions::default());
+        let with = mean(&PathOptions {
+            roulette_depth: Some(2),
+            ..Default::default()
+        });
+
+        assert!(
+            (without - with).abs() < 0.03 * without,
+            "{} vs {}",
+            without,
+            with
+        );
+    }
+
+    #[test]
+    fn light_sampling_reduces_noise() {
+        let white = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.7, 0.7, 0.7,
+        )));
+        let light = Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+            20.0, 20.0, 20.0,
+        )));
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(
+                RectangleXZ::new(
+                    Vec3::new(-5.0, 0.0, -5.0),
+                    Vec3::new(5.0, 0.0, 5.0),
+                    1.0,
+                    white,
+                )
+                .unwrap(),
+            ),
+            Arc::new(
+                RectangleXZ::new(
+                    Vec3::new(-0.5, 3.0, -0.5),
+                    Vec3::new(0.5, 3.0, 0.5),
+                    -1.0,
+                    light.clone(),
+                )
+                .unwrap(),
+            ),
+            Arc::new(Sphere::new(Vec3::new(2.0, 2.0, 0.0), 0.25, light)),
+        ];
+        let world = HittableList::from(world);
+        let mut lights = vec![];
+        world.collect_lights(&mut lights);
+        let light_sampler = LightSampler::new(&lights);
+        assert_eq!(2, light_sampler.len());
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 2.0), Vec3::new(0.0, -1.0, -1.0));
+        let mut rng = StdRng::seed_from_u64(1269);
+        // enough for the naive estimate to be within 5% of the mean, its
+        // standard error is about a quarter of that
+        let samples = 200000;
+        let mut statistics = |options: &PathOptions| {
+            let values: Vec<f64> = (0..samples)
+                .map(|_| {
+                    ray.color_with(&world, &Environment::default(), 5, options, &mut rng)
+                        .x()
+                })
+                .collect();
+            let mean = values.iter().sum::<f64>() / samples as f64;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / samples as f64;
+            (mean, variance)
+        };
+
+        let (mean, variance) = statistics(&PathOptions::default());
+        let (nee_mean, nee_variance) = statistics(&PathOptions {
+            light_sampler: Some(&light_sampler),
+            ..Default::default()
+        });
+
+        assert!(
+            (mean - nee_mean).abs() < 0.05 * mean,
+            "{} vs {}",
+            mean,
+            nee_mean
+        );
+        assert!(
+            nee_variance * 10.0 < variance,
+            "{} vs {}",
+            variance,
+            nee_variance
+        );
+    }
+
+    #[test]
+    fn flat_lights_are_sampled_without_bias() {
+        let white = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.7, 0.7, 0.7,
+        )));
+        let light = Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+            10.0, 10.0, 10.0,
+        )));
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Disk::new(
+                Vec3::default(),
+                Vec3::new(0.0, 1.0, 0.0),
+                5.0,
+                white,
+            )),
+            // facing down, towards the floor
+            Arc::new(Triangle::new_without_normal(
+                Vec3::new(-1.0, 2.0, -1.0),
+                Vec3::new(1.0, 2.0, -1.0),
+                Vec3::new(0.0, 2.0, 1.0),
+                light.clone(),
+            )),
+            Arc::new(Disk::new(
+                Vec3::new(2.0, 2.5, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                0.5,
+                light.clone(),
+            )),
+            // has no sampling, so it is only found by scattered rays
+            Arc::new(Cylinder::new(0.2, 0.0, 1.0, true, light)),
+        ];
+        let world = HittableList::from(world);
+        let mut lights = vec![];
+        world.collect_lights(&mut lights);
+        let light_sampler = LightSampler::new(&lights);
+        assert_eq!(2, light_sampler.len());
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 2.0), Vec3::new(0.0, -1.0, -1.0));
+        let mut rng = StdRng::seed_from_u64(1269);
+        let samples = 20000;
+        let mut statistics = |options: &PathOptions| {
+            let values: Vec<f64> = (0..samples)
+                .map(|_| {
+                    ray.color_with(&world, &Environment::default(), 5, options, &mut rng)
+                        .x()
+                })
+                .collect();
+            let mean = values.iter().sum::<f64>() / samples as f64;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / samples as f64;
+            (mean, variance)
+        };
+
+        let (mean, variance) = statistics(&PathOptions::default());
+        let (nee_mean, _) = statistics(&PathOptions {
+            light_sampler: Some(&light_sampler),
+            ..Default::default()
+        });
+        let standard_error = (variance / samples as f64).sqrt();
+        assert!(
+            (mean - nee_mean).abs() < 3.0 * standard_error,
+            "{} vs {}",
+            mean,
+            nee_mean
+        );
+    }
+
+    #[test]
+    fn no_acne_at_any_scale() {
+        // a convex diffuse sphere under a white sky reflects exactly its
+        // albedo, unless scattered rays hit the sphere again
+        let acne = |scale: f64, epsilon: RayEpsilon| {
+            let material = Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            )));
+            let world = Sphere::new(Vec3::new(0.0, 0.0, -3.0 * scale), scale, material);
+            let environment = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+            let options = PathOptions {
+                epsilon,
+                ..Default::default()
+            };
+            let mut rng = StdRng::seed_from_u64(1336);
+            (0..2000)
+                .filter(|_| {
+                    let target = Vec3::random_in_unitdisk_xy(&mut rng) * 0.9 * scale
+                        + Vec3::new(0.0, 0.0, -3.0 * scale);
+                    let ray = Ray::new(Vec3::default(), target);
+                    let color = ray.color_with(&world, &environment, 50, &options, &mut rng);
+                    (color.x() - 0.5).abs() > 1e-9
+                })
+                .count()
+        };
+
+        for scale in [1.0, 1000.0] {
+            assert!(acne(scale, RayEpsilon::Absolute(0.0)) > 0);
+            assert_eq!(0, acne(scale, RayEpsilon::default()), "{}", scale);
+            let relative = RayEpsilon::Relative(DEFAULT_RAY_EPSILON);
+            assert_eq!(0, acne(scale, relative), "{}", scale);
+        }
+        // a fixed distance eventually drowns in the rounding errors
+        assert!(acne(1e13, RayEpsilon::default()) > 0);
+        assert_eq!(0, acne(1e13, RayEpsilon::Relative(DEFAULT_RAY_EPSILON)));
+
+        // light sampling has to find the lights the paths hit at any scale,
+        // or the two are not weighted against each other correctly
+        let lit = |scale: f64| {
+            let world: Vec<Arc<dyn Hittable>> = vec![
+                Arc::new(Sphere::new(
+                    Vec3::new(0.0, -100.0, 0.0) * scale,
+                    100.0 * scale,
+                    Arc::new(LambertianMaterial::new_from_color(Color::new(
+                        0.5, 0.5, 0.5,
+                    ))),
+                )),
+                Arc::new(Sphere::new(
+                    Vec3::new(0.0, 0.8, 0.0) * scale,
+                    0.5 * scale,
+                    Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                        4.0, 4.0, 4.0,
+                    ))),
+                )),
+            ];
+            let world = HittableList::from(world);
+            let mut lights = vec![];
+            world.collect_lights(&mut lights);
+            let light_sampler = LightSampler::new(&lights);
+            let options = PathOptions {
+                epsilon: RayEpsilon::Absolute(DEFAULT_RAY_EPSILON * scale),
+                light_sampler: Some(&light_sampler),
+                ..Default::default()
+            };
+            // onto the floor just beside the light
+            let ray = Ray::new(Vec3::new(0.0, 0.5, 3.0) * scale, Vec3::new(0.0, -0.5, -2.5));
+            let mut rng = StdRng::seed_from_u64(1336);
+            (0..2000)
+                .map(|_| {
+                    ray.color_with(&world, &Environment::default(), 5, &options, &mut rng)
+                        .x()
+                })
+                .sum::<f64>()
+        };
+        let (unit, small) = (lit(1.0), lit(0.001));
+        assert!((unit - small).abs() < 1e-3 * unit, "{} vs {}", unit, small);
     }
 }
@@ -1,37 +1,1717 @@
-use rayon::prelude::*;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+};
 
-use crate::{camera::Camera, geometry::Hittable, vec3::Color};
+use rand::{rngs::ThreadRng, Rng, RngCore};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 
+use crate::{
+    camera::Camera,
+    error::PathtracerError,
+    film::Film,
+    geometry::Hittable,
+    integrator::{self, Integrator},
+    ray::{Ray, RayEpsilon},
+    sampler::{
+        splitmix64, stratified_offset, PixelRng, SampleSource, SampleTable, SamplingStrategy,
+    },
+    scene::ImageSettings,
+    vec3::{linear_to_srgb, Color, Vec3},
+};
+
+/// Rectangular part of the image in raster space (y downwards).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    /// Splits an image into tiles of at most `tile_size` x `tile_size`
+    /// pixels, row by row from the top left.
+    pub fn split_image(width: usize, height: usize, tile_size: usize) -> Vec<Self> {
+        let tile_size = tile_size.max(1);
+        let mut tiles = vec![];
+        for y in (0..height).step_by(tile_size) {
+            for x in (0..width).step_by(tile_size) {
+                tiles.push(Self {
+                    x,
+                    y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// The pixels from `(x0, y0)` up to but excluding `(x1, y1)`.
+    pub fn from_corners(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+        Self {
+            x: x0,
+            y: y0,
+            width: x1.saturating_sub(x0),
+            height: y1.saturating_sub(y0),
+        }
+    }
+
+    /// The pixels in both tiles, if there are any.
+    pub fn intersection(&self, other: &Tile) -> Option<Tile> {
+        let (x0, y0) = (self.x.max(other.x), self.y.max(other.y));
+        let (x1, y1) = (
+            (self.x + self.width).min(other.x + other.width),
+            (self.y + self.height).min(other.y + other.height),
+        );
+        (x0 < x1 && y0 < y1).then(|| Self::from_corners(x0, y0, x1, y1))
+    }
+
+    /// The tile grown by `margin` pixels on every side, within an image of
+    /// `width` x `height` pixels.
+    fn expanded(&self, margin: usize, width: usize, height: usize) -> Tile {
+        Self::from_corners(
+            self.x.saturating_sub(margin),
+            self.y.saturating_sub(margin),
+            (self.x + self.width + margin).min(width),
+            (self.y + self.height + margin).min(height),
+        )
+    }
+}
+
+/// How the color of a camera ray is computed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RenderMode {
+    /// Full path tracing, see `Ray::color`.
+    #[default]
+    PathTraced,
+    /// Flat albedo lit by a headlight at the camera, see `Ray::preview_color`.
+    Preview,
+    /// The outward normal of the first hit as a color, see
+    /// `integrator::NormalsIntegrator`.
+    Normals,
+    /// The distance to the first hit as gray levels, white at `near` and
+    /// black at `far`, see `integrator::DepthIntegrator`.
+    Depth { near: f64, far: f64 },
+    /// The number of BVH tests for the first hit on a blue to red ramp, see
+    /// `integrator::BvhCostIntegrator`.
+    BvhCost { max_cost: usize },
+}
+
+/// Parameters of `render_adaptive`. Every pixel takes samples in batches of
+/// `min_samples` until the 95% confidence interval of its mean luminance is
+/// within `tolerance` times the mean, or `max_samples` are taken.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub tolerance: f64,
+}
+
+/// Result of `render_adaptive`, both row by row from the top.
+pub struct AdaptiveImage {
+    pub colors: Vec<Color>,
+    pub sample_counts: Vec<usize>,
+}
+
+impl AdaptiveImage {
+    /// Sample counts as gray levels, white where the most samples were taken.
+    pub fn sample_heatmap(&self) -> Vec<Color> {
+        let max_count = self.sample_counts.iter().copied().max().unwrap_or(0).max(1);
+        self.sample_counts
+            .iter()
+            .map(|&count| {
+                let level = count as f64 / max_count as f64;
+                Color::new(level, level, level)
+            })
+            .collect()
+    }
+}
+
+/// Result of `render_resume`.
+pub struct ResumedRender {
+    /// Linear radiance per pixel of all samples so far, row by row from the
+    /// top.
+    pub colors: Vec<Color>,
+    /// Samples per pixel taken so far, at most `samples_per_pixel`.
+    pub samples_taken: usize,
+    /// Coverage per pixel of all samples so far, see
+    /// `Film::resolve_coverage`. All zero unless `ImageSettings::coverage`
+    /// is set.
+    pub coverage: Vec<f64>,
+}
+
+impl ResumedRender {
+    /// Whether all `samples_per_pixel` of `settings` are taken.
+    pub fn is_complete(&self, settings: &ImageSettings) -> bool {
+        self.samples_taken >= settings.samples_per_pixel
+    }
+}
+
+/// Result of `render_aovs`, both row by row from the top.
+pub struct AovImage {
+    /// Distance along the camera ray to the first hit, infinite for misses.
+    pub depth: Vec<f64>,
+    /// Normal at the first hit, facing the camera. Zero for misses.
+    pub normals: Vec<Color>,
+}
+
+impl AovImage {
+    /// Depth as gray levels, white at the farthest hit and black for misses.
+    pub fn depth_image(&self) -> Vec<Color> {
+        let max_depth = self
+            .depth
+            .iter()
+            .copied()
+            .filter(|depth| depth.is_finite())
+            .fold(0.0, f64::max);
+        self.depth
+            .iter()
+            .map(|&depth| match depth.is_finite() && max_depth > 0.0 {
+                true => Color::new(depth, depth, depth) / max_depth,
+                false => Color::default(),
+            })
+            .collect()
+    }
+
+    /// Normals mapped from `[-1, 1]` to `[0, 1]` per axis, black for misses.
+    pub fn normal_image(&self) -> Vec<Color> {
+        self.normals
+            .iter()
+            .map(|&normal| match normal.near_zero() {
+                true => Color::default(),
+                false => 0.5 * (normal + Color::new(1.0, 1.0, 1.0)),
+            })
+            .collect()
+    }
+}
+
+struct RenderContext<'a, H: Hittable> {
+    world: &'a H,
+    camera: &'a Camera,
+    settings: &'a ImageSettings,
+    integrator: &'a dyn Integrator,
+    sample_table: Option<SampleTable>,
+}
+
+impl<'a, H: Hittable> RenderContext<'a, H> {
+    fn new(
+        world: &'a H,
+        camera: &'a Camera,
+        settings: &'a ImageSettings,
+        integrator: &'a dyn Integrator,
+    ) -> Self {
+        let sample_table = match settings.sample_source {
+            SampleSource::Random | SampleSource::Seeded { .. } => None,
+            SampleSource::Table { seed } => Some(SampleTable::new(seed)),
+        };
+
+        Self {
+            world,
+            camera,
+            settings,
+            integrator,
+            sample_table,
+        }
+    }
+
+    /// Takes the given samples of the pixel in column `x` and raster row
+    /// `row`.
+    fn sample_pixel(
+        &self,
+        film: &mut Film,
+        x: usize,
+        row: usize,
+        samples: Range<usize>,
+        thread_rng: &mut ThreadRng,
+    ) {
+        for sample_index in samples {
+            self.take_sample(film, x, row, sample_index, thread_rng);
+        }
+    }
+
+    /// Takes a single sample of the pixel in column `x` and raster row `row`,
+    /// adds it to `film` and returns its color.
+    fn take_sample(
+        &self,
+        film: &mut Film,
+        x: usize,
+        row: usize,
+        sample_index: usize,
+        thread_rng: &mut ThreadRng,
+    ) -> Color {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let y = height - 1 - row;
+
+        let mut table_rng = self
+            .sample_table
+            .as_ref()
+            .map(|table| table.rng_for(x, y, sample_index));
+        let mut pixel_rng = match self.settings.sample_source {
+            SampleSource::Seeded { seed } => Some(PixelRng::new(seed, x, y, sample_index)),
+            _ => None,
+        };
+        let rng: &mut dyn RngCore = match (&mut table_rng, &mut pixel_rng) {
+            (Some(table_rng), _) => table_rng,
+            (_, Some(pixel_rng)) => pixel_rng,
+            _ => thread_rng,
+        };
+
+        let (offset_x, offset_y) = match (self.settings.jitter, self.settings.sampling_strategy) {
+            (false, _) => (0.5, 0.5),
+            (true, SamplingStrategy::Random) => (rng.gen::<f64>(), rng.gen::<f64>()),
+            (true, SamplingStrategy::Stratified) => {
+                stratified_offset(sample_index, self.settings.samples_per_pixel, rng)
+            }
+        };
+        let (u, v) = (
+            (x as f64 + offset_x) / width as f64,
+            (y as f64 + offset_y) / height as f64,
+        );
+        let ray = self.camera.ray_at(u, v, rng);
+        let color = self.integrator.radiance(
+            &ray,
+            self.world,
+            &self.settings.background,
+            self.settings.max_bounces,
+            rng,
+        );
+        let color = match self.settings.max_luminance_clamp {
+            Some(max_luminance) if color.luminance() > max_luminance => {
+                max_luminance / color.luminance() * color
+            }
+            _ => color,
+        };
+        // traced after the radiance, so the colors do not depend on it
+        let covered = self.settings.coverage
+            && self
+                .world
+                .hit_with_rng(
+                    &ray,
+                    self.settings.ray_epsilon.t_min(&ray),
+                    f64::INFINITY,
+                    rng,
+                )
+                .is_some();
+        film.add_sample(
+            x as f64 + offset_x,
+            row as f64 + 1.0 - offset_y,
+            color,
+            covered,
+        );
+        color
+    }
+
+    /// Takes the given samples of all pixels in the tile. The returned film
+    /// extends beyond the tile by the filter margin, so it can be merged into
+    /// a film of the whole image.
+    fn sample_tile(&self, tile: Tile, samples: Range<usize>) -> Film {
+        let margin = self.settings.filter.margin();
+        let mut film = Film::new_region(
+            tile.x as i64 - margin,
+            tile.y as i64 - margin,
+            tile.width + 2 * margin as usize,
+            tile.height + 2 * margin as usize,
+            self.settings.filter,
+        );
+        let mut thread_rng = rand::thread_rng();
+
+        for row in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                self.sample_pixel(&mut film, x, row, samples.clone(), &mut thread_rng);
+            }
+        }
+
+        film
+    }
+
+    /// Like `sample_tile`, but keeps sampling every pixel until it converged
+    /// according to `adaptive`. Also returns the number of samples taken per
+    /// pixel of the tile.
+    fn sample_tile_adaptive(&self, tile: Tile, adaptive: &AdaptiveSampling) -> (Film, Vec<usize>) {
+        let margin = self.settings.filter.margin();
+        let mut film = Film::new_region(
+            tile.x as i64 - margin,
+            tile.y as i64 - margin,
+            tile.width + 2 * margin as usize,
+            tile.height + 2 * margin as usize,
+            self.settings.filter,
+        );
+        let mut sample_counts = Vec::with_capacity(tile.width * tile.height);
+        let mut thread_rng = rand::thread_rng();
+        let batch_size = adaptive.min_samples.max(1);
+
+        for row in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                // running mean and sum of squared deviations (Welford)
+                let (mut count, mut mean, mut squared_deviations) = (0, 0.0, 0.0);
+
+                while count < adaptive.max_samples {
+                    for _ in 0..batch_size.min(adaptive.max_samples - count) {
+                        let value = self
+                            .take_sample(&mut film, x, row, count, &mut thread_rng)
+                            .luminance();
+                        count += 1;
+                        let delta = value - mean;
+                        mean += delta / count as f64;
+                        squared_deviations += delta * (value - mean);
+                    }
+
+                    if count >= 2 {
+                        let variance = squared_deviations / (count - 1) as f64;
+                        let half_interval = 1.96 * (variance / count as f64).sqrt();
+                        if half_interval <= adaptive.tolerance * mean {
+                            break;
+                        }
+                    }
+                }
+
+                sample_counts.push(count);
+            }
+        }
+
+        (film, sample_counts)
+    }
+
+    /// The tiles of `tile_size` pixels to sample. With a crop window only
+    /// the parts of the tiles in and around it are sampled: pixels just
+    /// outside the crop window are sampled as well, since their samples
+    /// reach into it.
+    fn sampled_tiles(&self) -> Vec<Tile> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let mut tiles = Tile::split_image(width, height, self.settings.tile_size);
+        if let Some(crop) = self.settings.crop {
+            let margin = self.settings.filter.margin() as usize;
+            let sampled = crop.expanded(margin, width, height);
+            tiles.retain_mut(|tile| match tile.intersection(&sampled) {
+                Some(inside) => {
+                    *tile = inside;
+                    true
+                }
+                None => false,
+            });
+        }
+        tiles
+    }
+
+    /// Adds the samples numbered `samples` to every pixel of `film`.
+    /// `on_progress` gets the finished fraction of the image after every
+    /// tile, from whichever thread rendered it.
+    fn render_samples(
+        &self,
+        film: &mut Film,
+        samples: Range<usize>,
+        on_progress: &(dyn Fn(f64) + Sync),
+    ) {
+        // Tiles of `tile_size` pixels are rendered in parallel, each into its
+        // own film which also covers the neighboring pixels the reconstruction
+        // filter reaches into. The tiles are merged in order once the pass is
+        // done.
+        let tiles = self.sampled_tiles();
+        let tile_count = tiles.len();
+        let finished_tiles = AtomicUsize::new(0);
+        let tile_films: Vec<Film> = tiles
+            .into_par_iter()
+            .map(|tile| {
+                let tile_film = self.sample_tile(tile, samples.clone());
+                let finished = finished_tiles.fetch_add(1, Ordering::Relaxed) + 1;
+                on_progress(finished as f64 / tile_count as f64);
+                tile_film
+            })
+            .collect();
+
+        for tile_film in &tile_films {
+            film.merge(tile_film);
+        }
+        if let Some(crop) = self.settings.crop {
+            film.clear_outside(crop.x as i64, crop.y as i64, crop.width, crop.height);
+        }
+    }
+}
+
+/// Rejects images without any pixels, which none of the renders can
+/// produce.
+fn check_image_size(settings: &ImageSettings) -> Result<(), PathtracerError> {
+    match settings.width == 0 || settings.height == 0 {
+        true => Err(PathtracerError::InvalidImageSize {
+            width: settings.width,
+            height: settings.height,
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Applies the exposure and tone mapping of `settings` to linear colors,
+/// gamma corrects them and converts them to 8 bit RGB. The colors of the
+/// other render modes than `RenderMode::PathTraced` are converted as they
+/// are, so that normals and depths can be read back from the pixels.
+pub fn to_rgb(colors: &[Color], settings: &ImageSettings) -> Vec<u8> {
+    colors
+        .iter()
+        .flat_map(|color| match settings.render_mode {
+            RenderMode::PathTraced => {
+                let mapped = settings.tone_mapping.apply(*color, settings.exposure);
+                linear_to_srgb(mapped, settings.gamma)
+            }
+            _ => color.rgb(),
+        })
+        .collect()
+}
+
+/// Like `to_rgb`, with an alpha channel from the `coverage` of every pixel,
+/// see `Film::resolve_coverage`. Pixels showing only the background keep its color
+/// but are fully transparent.
+pub fn to_rgba(colors: &[Color], coverage: &[f64], settings: &ImageSettings) -> Vec<u8> {
+    to_rgb(colors, settings)
+        .chunks_exact(3)
+        .zip(coverage)
+        .flat_map(|(rgb, &coverage)| {
+            let alpha = Color::new(coverage, coverage, coverage).r();
+            [rgb[0], rgb[1], rgb[2], alpha]
+        })
+        .collect()
+}
+
+/// Renders the image with `integrator` and returns its 8 bit RGB pixels,
+/// see `to_rgb`. `on_progress` gets the finished fraction of the image
+/// whenever a tile is done. It is called from the render threads, so it has
+/// to be `Sync`.
 pub fn render(
     world: &impl Hittable,
     camera: &Camera,
-    width: usize,
-    height: usize,
-    background: Color,
-    samples_per_pixel: usize,
-    max_bounces: usize,
-) -> Vec<u8> {
-    (0..height)
+    settings: &ImageSettings,
+    integrator: &dyn Integrator,
+    on_progress: impl Fn(f64) + Sync,
+) -> Result<Vec<u8>, PathtracerError> {
+    Ok(to_rgb(
+        &render_linear_with(world, camera, settings, integrator, on_progress)?,
+        settings,
+    ))
+}
+
+/// Builds a thread pool with `threads` threads to render in, or with one per
+/// core if `threads` is 0.
+pub fn thread_pool(threads: usize) -> Result<ThreadPool, PathtracerError> {
+    Ok(ThreadPoolBuilder::new().num_threads(threads).build()?)
+}
+
+/// Like `render`, but only uses `threads` threads instead of rayon's global
+/// pool, or all cores if `threads` is 0. With a `Table` or `Seeded` sample
+/// source the image does not depend on the number of threads.
+pub fn render_with_threads(
+    threads: usize,
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    integrator: &dyn Integrator,
+    on_progress: impl Fn(f64) + Sync + Send,
+) -> Result<Vec<u8>, PathtracerError> {
+    thread_pool(threads)?.install(|| render(world, camera, settings, integrator, on_progress))
+}
+
+/// Renders the image and returns the linear radiance per pixel, row by row
+/// from the top. The integrator is picked by `integrator::for_settings`.
+pub fn render_linear(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+) -> Result<Vec<Color>, PathtracerError> {
+    render_linear_with(
+        world,
+        camera,
+        settings,
+        &*integrator::for_settings(world, settings),
+        |_| {},
+    )
+}
+
+/// Like `render_linear`, with the given integrator and progress callback,
+/// see `render`.
+pub fn render_linear_with(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    integrator: &dyn Integrator,
+    on_progress: impl Fn(f64) + Sync,
+) -> Result<Vec<Color>, PathtracerError> {
+    Ok(render_film(world, camera, settings, integrator, on_progress)?.resolve())
+}
+
+/// Like `render_linear_with`, but returns the film with all samples, which
+/// also knows the coverage of every pixel if `ImageSettings::coverage` is
+/// set.
+pub fn render_film(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    integrator: &dyn Integrator,
+    on_progress: impl Fn(f64) + Sync,
+) -> Result<Film, PathtracerError> {
+    check_image_size(settings)?;
+
+    let mut film = Film::new(settings.width, settings.height, settings.filter);
+    let context = RenderContext::new(world, camera, settings, integrator);
+    context.render_samples(&mut film, 0..settings.samples_per_pixel, &on_progress);
+    Ok(film)
+}
+
+/// Adds `samples_per_pixel` samples to every pixel of `film`, numbered from
+/// `first_sample_index` on. Successive passes into the same film accumulate,
+/// so with a fixed sample source several passes give the same result as a
+/// single pass with all of their samples.
+pub fn render_pass(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    film: &mut Film,
+    first_sample_index: usize,
+) {
+    let integrator = integrator::for_settings(world, settings);
+    RenderContext::new(world, camera, settings, &*integrator).render_samples(
+        film,
+        first_sample_index..first_sample_index + settings.samples_per_pixel,
+        &|_| {},
+    );
+}
+
+/// Renders the image in passes of 1, 2, 4, ... samples per pixel until
+/// `samples_per_pixel` are taken, e.g. for a live preview. After every pass
+/// `on_pass` gets the RGB pixels of all samples so far and the number of
+/// finished passes. Returns the final RGB pixels, which match `render` with
+/// the same settings.
+pub fn render_progressive<F>(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    mut on_pass: F,
+) -> Result<Vec<u8>, PathtracerError>
+where
+    F: FnMut(&[u8], usize),
+{
+    check_image_size(settings)?;
+
+    let integrator = integrator::for_settings(world, settings);
+    let context = RenderContext::new(world, camera, settings, &*integrator);
+    let mut film = Film::new(settings.width, settings.height, settings.filter);
+    let mut pixels = vec![];
+    let mut samples_taken = 0;
+    let mut pass_samples = 1;
+    let mut passes = 0;
+    while samples_taken < settings.samples_per_pixel {
+        let pass_end = (samples_taken + pass_samples).min(settings.samples_per_pixel);
+        context.render_samples(&mut film, samples_taken..pass_end, &|_| {});
+        samples_taken = pass_end;
+        pass_samples *= 2;
+        passes += 1;
+
+        pixels = to_rgb(&film.resolve(), settings);
+        on_pass(&pixels, passes);
+    }
+    Ok(pixels)
+}
+
+/// Continues the render saved at `checkpoint`, or starts a new one if there
+/// is no such file. Adds up to `max_new_samples` samples per pixel, without
+/// going past `samples_per_pixel`, and saves the result back to
+/// `checkpoint`. Resuming until the render is complete gives the same image
+/// as a single `render_linear` with a fixed sample source.
+pub fn render_resume(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    checkpoint: &Path,
+    max_new_samples: usize,
+) -> Result<ResumedRender, PathtracerError> {
+    check_image_size(settings)?;
+
+    let fingerprint = checkpoint_fingerprint(world, camera, settings);
+    let (mut film, mut samples_taken) = match File::open(checkpoint) {
+        Ok(file) => {
+            let (film, samples_taken, saved_fingerprint) = Film::read_checkpoint(
+                BufReader::new(file),
+                0,
+                0,
+                settings.width,
+                settings.height,
+                settings.filter,
+            )?;
+            if saved_fingerprint != fingerprint {
+                return Err(PathtracerError::CheckpointMismatch(format!(
+                    "{}: checkpoint was rendered from a different scene or settings",
+                    checkpoint.display()
+                )));
+            }
+            (film, samples_taken)
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => (
+            Film::new(settings.width, settings.height, settings.filter),
+            0,
+        ),
+        Err(error) => return Err(error.into()),
+    };
+    let target = settings
+        .samples_per_pixel
+        .min(samples_taken.saturating_add(max_new_samples));
+    if target > samples_taken {
+        let integrator = integrator::for_settings(world, settings);
+        RenderContext::new(world, camera, settings, &*integrator).render_samples(
+            &mut film,
+            samples_taken..target,
+            &|_| {},
+        );
+        samples_taken = target;
+
+        // an interrupted write must not destroy the previous checkpoint
+        let mut temporary = checkpoint.as_os_str().to_owned();
+        temporary.push(".tmp");
+        film.write_checkpoint(
+            BufWriter::new(File::create(&temporary)?),
+            samples_taken,
+            fingerprint,
+        )?;
+        std::fs::rename(&temporary, checkpoint)?;
+    }
+
+    Ok(ResumedRender {
+        colors: film.resolve(),
+        samples_taken,
+        coverage: film.resolve_coverage(),
+    })
+}
+
+/// Identifies what a checkpoint was rendered from: the camera, a summary of
+/// the world and the background, what the camera sees of the world, see
+/// `probe_fingerprint`, and the settings which change the samples.
+/// `samples_per_pixel` is left out, so that a render can be continued with
+/// more samples.
+fn checkpoint_fingerprint(world: &impl Hittable, camera: &Camera, settings: &ImageSettings) -> u64 {
+    let background = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(1.0, 2.0, 3.0),
+    ]
+    .map(|direction| settings.background.value(direction));
+    let description = format!(
+        "{:?} {:?} {} {:?} {:?} {:?} {:?}",
+        camera,
+        world.bounding_box(),
+        world.area(),
+        world.emitted_power(),
+        background,
+        (
+            settings.width,
+            settings.height,
+            settings.max_bounces,
+            settings.filter,
+            settings.sample_source,
+            settings.path_regularization,
+            settings.russian_roulette_depth,
+        ),
+        (
+            settings.max_luminance_clamp,
+            settings.next_event_estimation,
+            settings.ray_epsilon,
+            settings.jitter,
+            settings.sampling_strategy,
+            settings.crop,
+            settings.render_mode,
+            settings.coverage,
+        ),
+    );
+    let hash = description
+        .bytes()
+        .fold(0, |hash, byte| splitmix64(hash ^ u64::from(byte)));
+    probe_fingerprint(hash, world, camera, settings)
+}
+
+/// Adds to `hash` what a ray through the center of every pixel hits first:
+/// the distance and normal there and how the material emits and scatters,
+/// with random numbers from a fixed seed. Catches objects which were moved
+/// or changed inside the bounds of the world, as long as the camera sees
+/// them.
+fn probe_fingerprint(
+    mut hash: u64,
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+) -> u64 {
+    let mut add = |values: &[f64]| {
+        for value in values {
+            hash = splitmix64(hash ^ value.to_bits());
+        }
+    };
+    for y in 0..settings.height {
+        for x in 0..settings.width {
+            let mut rng = PixelRng::new(0, x, y, 0);
+            let ray = camera.pinhole_ray_at(
+                (x as f64 + 0.5) / settings.width as f64,
+                1.0 - (y as f64 + 0.5) / settings.height as f64,
+            );
+            let t_min = settings.ray_epsilon.t_min(&ray);
+            let Some(hit_record) = world.hit_with_rng(&ray, t_min, f64::INFINITY, &mut rng) else {
+                add(&[f64::INFINITY]);
+                continue;
+            };
+            add(&[hit_record.t]);
+            add(&hit_record.normal.e);
+            add(&hit_record.material.emits(&ray, &hit_record).e);
+            if let Some(scatter) = hit_record.material.scatter(&ray, &hit_record, &mut rng) {
+                add(&scatter.attenuation.e);
+                add(&scatter.scattered_ray.direction.e);
+            }
+        }
+    }
+    hash
+}
+
+/// Renders the image with a varying number of samples per pixel, see
+/// `AdaptiveSampling`. `samples_per_pixel` of the settings is ignored. Pixels
+/// outside the crop window are black and have no samples.
+pub fn render_adaptive(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+    adaptive: &AdaptiveSampling,
+) -> Result<AdaptiveImage, PathtracerError> {
+    check_image_size(settings)?;
+
+    let integrator = integrator::for_settings(world, settings);
+    let context = RenderContext::new(world, camera, settings, &*integrator);
+    let tiles = context.sampled_tiles();
+    let tile_results: Vec<(Tile, Film, Vec<usize>)> = tiles
         .into_par_iter()
-        .rev()
-        .flat_map(|y| {
-            (0..width).into_par_iter().flat_map(move |x| {
-                let mut color_sampling = Color::default();
+        .map(|tile| {
+            let (film, sample_counts) = context.sample_tile_adaptive(tile, adaptive);
+            (tile, film, sample_counts)
+        })
+        .collect();
+
+    let mut film = Film::new(settings.width, settings.height, settings.filter);
+    let mut sample_counts = vec![0; settings.width * settings.height];
+    for (tile, tile_film, tile_counts) in &tile_results {
+        film.merge(tile_film);
+        for (index, &count) in tile_counts.iter().enumerate() {
+            let (x, y) = (tile.x + index % tile.width, tile.y + index / tile.width);
+            sample_counts[y * settings.width + x] = count;
+        }
+    }
+    // like `render_samples`, only the crop window keeps its samples
+    if let Some(crop) = settings.crop {
+        film.clear_outside(crop.x as i64, crop.y as i64, crop.width, crop.height);
+        for (index, count) in sample_counts.iter_mut().enumerate() {
+            let (x, y) = (index % settings.width, index / settings.width);
+            if crop
+                .intersection(&Tile::from_corners(x, y, x + 1, y + 1))
+                .is_none()
+            {
+                *count = 0;
+            }
+        }
+    }
+
+    Ok(AdaptiveImage {
+        colors: film.resolve(),
+        sample_counts,
+    })
+}
 
-                for _ in 0..samples_per_pixel {
+/// Traces a single ray through the center of every pixel and records the
+/// depth and normal of the first hit, e.g. as input for a denoiser. The rays
+/// start at the center of the lens, so the result is the same on every run,
+/// whatever the aperture.
+pub fn render_aovs(
+    world: &impl Hittable,
+    camera: &Camera,
+    settings: &ImageSettings,
+) -> Result<AovImage, PathtracerError> {
+    check_image_size(settings)?;
+    let (width, height) = (settings.width, settings.height);
+
+    let pixels: Vec<(f64, Color)> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|row| {
+            let y = height - 1 - row;
+            (0..width)
+                .map(|x| {
                     let (u, v) = (
-                        (x as f64 + rand::random::<f64>()) / (width as f64 - 1.0),
-                        (y as f64 + rand::random::<f64>()) / (height as f64 - 1.0),
+                        (x as f64 + 0.5) / width as f64,
+                        (y as f64 + 0.5) / height as f64,
                     );
-                    let ray = camera.ray_at(u, v);
-                    color_sampling += ray.color(world, background, max_bounces);
+                    first_hit(world, &camera.pinhole_ray_at(u, v), settings.ray_epsilon)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    Ok(AovImage {
+        depth: pixels.iter().map(|&(depth, _)| depth).collect(),
+        normals: pixels.iter().map(|&(_, normal)| normal).collect(),
+    })
+}
+
+/// Distance along `ray` to its first hit in `world` beyond `epsilon` and the
+/// normal there, facing the ray. Misses are infinitely far away and have a
+/// zero normal.
+pub(crate) fn first_hit(
+    world: &(impl Hittable + ?Sized),
+    ray: &Ray,
+    epsilon: RayEpsilon,
+) -> (f64, Color) {
+    match world.hit(ray, epsilon.t_min(ray), f64::INFINITY) {
+        Some(hit_record) => (hit_record.t * ray.direction.len(), hit_record.normal),
+        None => (f64::INFINITY, Color::default()),
+    }
+}
+
+/// Renders the image tile by tile and hands every finished tile to
+/// `on_tile` together with its RGB pixels, row by row from the top. Tiles are
+/// rendered in parallel and arrive in no particular order, but `on_tile` is
+/// always called from the calling thread. A tile is finished once all tiles
+/// whose samples reach into it are rendered, and its pixels match those of
+/// `render`. With a crop window only the parts of the tiles inside it are
+/// handed on.
+pub fn render_streaming<F>(
+    settings: &ImageSettings,
+    world: &impl Hittable,
+    camera: &Camera,
+    mut on_tile: F,
+) -> Result<(), PathtracerError>
+where
+    F: FnMut(Tile, &[u8]),
+{
+    check_image_size(settings)?;
+    let integrator = integrator::for_settings(world, settings);
+    let context = RenderContext::new(world, camera, settings, &*integrator);
+    let tiles = context.sampled_tiles();
+
+    // the pixels every tile hands on, and the tiles whose samples reach them
+    let margin = settings.filter.margin() as usize;
+    let shown: Vec<Option<Tile>> = tiles
+        .iter()
+        .map(|tile| match settings.crop {
+            Some(crop) => tile.intersection(&crop),
+            None => Some(*tile),
+        })
+        .collect();
+    let sources: Vec<Vec<usize>> = shown
+        .iter()
+        .map(|shown| {
+            let Some(shown) = shown else {
+                return vec![];
+            };
+            (0..tiles.len())
+                .filter(|&source| {
+                    tiles[source]
+                        .expanded(margin, settings.width, settings.height)
+                        .intersection(shown)
+                        .is_some()
+                })
+                .collect()
+        })
+        .collect();
+    let mut waiting: Vec<usize> = sources.iter().map(Vec::len).collect();
+    let mut films: Vec<Option<Film>> = vec![None; tiles.len()];
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            tiles
+                .par_iter()
+                .enumerate()
+                .for_each_with(sender, |sender, (index, &tile)| {
+                    let film = context.sample_tile(tile, 0..settings.samples_per_pixel);
+                    // the receiver only hangs up once all tiles are sent
+                    sender.send((index, film)).ok();
+                });
+        });
+
+        for (index, film) in receiver {
+            films[index] = Some(film);
+            for (target, target_sources) in sources.iter().enumerate() {
+                if !target_sources.contains(&index) {
+                    continue;
+                }
+                waiting[target] -= 1;
+                let Some(tile) = shown[target].filter(|_| waiting[target] == 0) else {
+                    continue;
+                };
+                // merged in the same order as by `render_samples`, so the
+                // sums and with them the pixels are the same
+                let mut film = Film::new_region(
+                    tile.x as i64,
+                    tile.y as i64,
+                    tile.width,
+                    tile.height,
+                    settings.filter,
+                );
+                for &source in target_sources {
+                    film.merge(films[source].as_ref().expect("source tile is rendered"));
                 }
+                on_tile(tile, &to_rgb(&film.resolve(), settings));
+            }
+        }
+    });
+    Ok(())
+}
 
-                let color_at_pixel: Color =
-                    (color_sampling / samples_per_pixel as f64).map(|v| v.sqrt());
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-                color_at_pixel.rgb().to_vec()
-            })
+    use super::*;
+    use crate::{
+        bvh::BvhNode,
+        environment::Environment,
+        film::ReconstructionFilter,
+        geometry::{ConstantMedium, HittableList, RectangleXY, Sphere},
+        integrator::{self, NaivePathIntegrator},
+        material::{
+            DielectricMaterial, DiffuseLightMaterial, IsotropicMaterial, LambertianMaterial,
+            MetalMaterial,
+        },
+        texture::PerlinNoiseTexture,
+        tone_mapping::ToneMapping,
+        vec3::{Color, Vec3},
+    };
+
+    fn test_camera(aperture: f64) -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            aperture,
+            1.0,
+        )
+    }
+
+    /// A grey sphere in front of a pinhole camera under a blue sky.
+    fn test_scene() -> (Sphere, Camera, ImageSettings) {
+        let world = Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        );
+        let settings = ImageSettings {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 4,
+            background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+            ..Default::default()
+        };
+        (world, test_camera(0.0), settings)
+    }
+
+    #[test]
+    fn sample_table_renders_are_reproducible() {
+        let (world, _, settings) = test_scene();
+        let camera = test_camera(0.1);
+        let settings = ImageSettings {
+            sample_source: SampleSource::Table { seed: 7 },
+            ..settings
+        };
+
+        assert_eq!(
+            render(
+                &world,
+                &camera,
+                &settings,
+                &NaivePathIntegrator::default(),
+                |_| {}
+            )
+            .unwrap(),
+            render(
+                &world,
+                &camera,
+                &settings,
+                &NaivePathIntegrator::default(),
+                |_| {}
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn passes_accumulate_like_a_single_render() {
+        let (world, camera, settings) = test_scene();
+        let mut settings = ImageSettings {
+            width: 6,
+            height: 6,
+            filter: ReconstructionFilter::Tent { radius: 1.0 },
+            sample_source: SampleSource::Table { seed: 3 },
+            ..settings
+        };
+        let single = render_linear(&world, &camera, &settings).unwrap();
+
+        settings.samples_per_pixel = 1;
+        let mut film = Film::new(6, 6, settings.filter);
+        for pass in 0..4 {
+            render_pass(&world, &camera, &settings, &mut film, pass);
+        }
+
+        for (a, b) in single.iter().zip(film.resolve()) {
+            assert!((*a - b).near_zero());
+        }
+    }
+
+    #[test]
+    fn progressive_passes_converge_to_a_full_render() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            width: 6,
+            height: 6,
+            samples_per_pixel: 10,
+            filter: ReconstructionFilter::Tent { radius: 1.0 },
+            sample_source: SampleSource::Table { seed: 1303 },
+            ..settings
+        };
+
+        let mut passes = vec![];
+        let progressive = render_progressive(&world, &camera, &settings, |pixels, pass| {
+            assert_eq!(6 * 6 * 3, pixels.len());
+            passes.push(pass);
         })
-        .collect()
+        .unwrap();
+
+        // 1 + 2 + 4 + 3 samples
+        assert_eq!(vec![1, 2, 3, 4], passes);
+        let single = render_linear(&world, &camera, &settings).unwrap();
+        assert_eq!(to_rgb(&single, &settings), progressive);
+    }
+
+    #[test]
+    fn resuming_with_a_changed_bvh_is_rejected() {
+        let (_, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            samples_per_pixel: 4,
+            sample_source: SampleSource::Table { seed: 1304 },
+            ..settings
+        };
+        // the ground keeps the bounds of the world the same in every variant
+        let world = |center: Vec3, albedo: f64| {
+            let ground = Sphere::new(
+                Vec3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(LambertianMaterial::new_from_color(Color::new(
+                    0.5, 0.5, 0.5,
+                ))),
+            );
+            let sphere = Sphere::new(
+                center,
+                0.5,
+                Arc::new(LambertianMaterial::new_from_color(Color::new(
+                    albedo, albedo, albedo,
+                ))),
+            );
+            BvhNode::new(vec![Arc::new(ground), Arc::new(sphere)]).unwrap()
+        };
+        let checkpoint = std::env::temp_dir().join(format!(
+            "pathtracer-resume-bvh-{}.checkpoint",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&checkpoint);
+
+        let original = world(Vec3::new(0.0, 0.0, -1.0), 0.5);
+        render_resume(&original, &camera, &settings, &checkpoint, 2).unwrap();
+        let moved = world(Vec3::new(0.1, 0.0, -1.0), 0.5);
+        let recolored = world(Vec3::new(0.0, 0.0, -1.0), 0.8);
+        for changed in [moved, recolored] {
+            assert!(matches!(
+                render_resume(&changed, &camera, &settings, &checkpoint, 2),
+                Err(PathtracerError::CheckpointMismatch(_))
+            ));
+        }
+        let resumed = render_resume(&original, &camera, &settings, &checkpoint, 2).unwrap();
+        assert!(resumed.is_complete(&settings));
+        std::fs::remove_file(&checkpoint).unwrap();
+    }
+
+    #[test]
+    fn resumed_renders_match_a_single_render() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            width: 6,
+            height: 6,
+            samples_per_pixel: 8,
+            filter: ReconstructionFilter::Tent { radius: 1.0 },
+            sample_source: SampleSource::Table { seed: 1304 },
+            ..settings
+        };
+        let checkpoint = std::env::temp_dir().join(format!(
+            "pathtracer-resume-{}.checkpoint",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&checkpoint);
+
+        let first = render_resume(&world, &camera, &settings, &checkpoint, 3).unwrap();
+        assert_eq!(3, first.samples_taken);
+        assert!(!first.is_complete(&settings));
+        let mut temporary = checkpoint.as_os_str().to_owned();
+        temporary.push(".tmp");
+        assert!(!Path::new(&temporary).exists());
+
+        // a checkpoint only continues the render it was started with
+        let reseeded = ImageSettings {
+            sample_source: SampleSource::Table { seed: 1305 },
+            ..settings.clone()
+        };
+        assert!(matches!(
+            render_resume(&world, &camera, &reseeded, &checkpoint, 100),
+            Err(PathtracerError::CheckpointMismatch(_))
+        ));
+        let moved = Sphere::new(
+            Vec3::new(0.0, 0.1, -1.0),
+            0.5,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        );
+        assert!(matches!(
+            render_resume(&moved, &camera, &settings, &checkpoint, 100),
+            Err(PathtracerError::CheckpointMismatch(_))
+        ));
+
+        let second = render_resume(&world, &camera, &settings, &checkpoint, 100).unwrap();
+        assert!(second.is_complete(&settings));
+        std::fs::remove_file(&checkpoint).unwrap();
+
+        let single = render_linear(&world, &camera, &settings).unwrap();
+        for (a, b) in single.iter().zip(second.colors) {
+            assert!((*a - b).near_zero());
+        }
+    }
+
+    #[test]
+    fn streamed_tiles_cover_the_image() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            width: 10,
+            height: 7,
+            samples_per_pixel: 1,
+            tile_size: 4,
+            background: Environment::Constant(Color::new(1.0, 1.0, 1.0)),
+            ..settings
+        };
+
+        let mut covered = vec![0; settings.width * settings.height];
+        render_streaming(&settings, &world, &camera, |tile, pixels| {
+            assert_eq!(tile.width * tile.height * 3, pixels.len());
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    covered[y * settings.width + x] += 1;
+                }
+            }
+        })
+        .unwrap();
+
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn streamed_tiles_match_render() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            width: 16,
+            height: 12,
+            tile_size: 5,
+            filter: ReconstructionFilter::Tent { radius: 1.5 },
+            sample_source: SampleSource::Seeded { seed: 1191 },
+            crop: Some(Tile::from_corners(3, 2, 11, 9)),
+            ..settings
+        };
+        let integrator = integrator::for_settings(&world, &settings);
+        let rendered = render(&world, &camera, &settings, &*integrator, |_| {}).unwrap();
+
+        let mut streamed = vec![0; rendered.len()];
+        render_streaming(&settings, &world, &camera, |tile, pixels| {
+            for (row, pixels) in pixels.chunks_exact(3 * tile.width).enumerate() {
+                let start = 3 * ((tile.y + row) * settings.width + tile.x);
+                streamed[start..start + pixels.len()].copy_from_slice(pixels);
+            }
+        })
+        .unwrap();
+        assert_eq!(rendered, streamed);
+
+        let empty = ImageSettings {
+            width: 0,
+            ..settings
+        };
+        assert!(matches!(
+            render_streaming(&empty, &world, &camera, |_, _| {}),
+            Err(PathtracerError::InvalidImageSize { .. })
+        ));
+    }
+
+    #[test]
+    fn debug_modes_are_not_tone_mapped() {
+        let world = RectangleXY::new(
+            Vec3::new(-10.0, -10.0, -1.0),
+            Vec3::new(10.0, 10.0, -1.0),
+            1.0,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        )
+        .unwrap();
+        let settings = ImageSettings {
+            width: 1,
+            height: 1,
+            samples_per_pixel: 1,
+            jitter: false,
+            exposure: 3.0,
+            tone_mapping: ToneMapping::AcesFilmic,
+            gamma: 1.8,
+            ..Default::default()
+        };
+        let pixels = |render_mode| {
+            let settings = ImageSettings {
+                render_mode,
+                ..settings.clone()
+            };
+            let integrator = integrator::for_settings(&world, &settings);
+            render(&world, &test_camera(0.0), &settings, &*integrator, |_| {}).unwrap()
+        };
+
+        // 0.5 * (n + 1) for the normal facing the camera
+        assert_eq!(vec![127, 127, 255], pixels(RenderMode::Normals));
+        // a quarter of the way from near to far
+        assert_eq!(
+            vec![191, 191, 191],
+            pixels(RenderMode::Depth {
+                near: 0.0,
+                far: 4.0
+            })
+        );
+    }
+
+    #[test]
+    fn seeded_renders_are_identical() {
+        // the world is built anew for every render, as the binary does
+        let world = |seed: u32| {
+            let fog_boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, -1.0),
+                0.5,
+                Arc::new(LambertianMaterial::new_from_color(Color::new(
+                    0.5, 0.5, 0.5,
+                ))),
+            ));
+            let noise = PerlinNoiseTexture::new(4.0).with_seed(seed);
+            let world: Vec<Arc<dyn Hittable>> = vec![
+                Arc::new(ConstantMedium::new(
+                    fog_boundary,
+                    2.0,
+                    Arc::new(IsotropicMaterial::new_from_color(Color::new(0.8, 0.8, 0.8))),
+                )),
+                Arc::new(Sphere::new(
+                    Vec3::new(0.0, -100.5, -1.0),
+                    100.0,
+                    Arc::new(LambertianMaterial::new(Box::new(noise))),
+                )),
+            ];
+            HittableList::from(world)
+        };
+        let camera = test_camera(0.1);
+        let mut settings = ImageSettings {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 4,
+            background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+            sampling_strategy: SamplingStrategy::Stratified,
+            sample_source: SampleSource::Seeded { seed: 1312 },
+            ..Default::default()
+        };
+
+        let first = render_linear(&world(1312), &camera, &settings).unwrap();
+        let second = render_linear(&world(1312), &camera, &settings).unwrap();
+        assert_eq!(
+            first.iter().map(|color| color.e).collect::<Vec<_>>(),
+            second.iter().map(|color| color.e).collect::<Vec<_>>()
+        );
+
+        settings.sample_source = SampleSource::Seeded { seed: 1313 };
+        let other = render_linear(&world(1313), &camera, &settings).unwrap();
+        assert!(first.iter().zip(&other).any(|(a, b)| a.e != b.e));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let world = Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(MetalMaterial::new_from_color(
+                Color::new(0.8, 0.6, 0.2),
+                0.3,
+            )),
+        );
+        let camera = test_camera(0.1);
+        let settings = ImageSettings {
+            width: 16,
+            height: 12,
+            samples_per_pixel: 4,
+            tile_size: 4,
+            background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+            sample_source: SampleSource::Seeded { seed: 1319 },
+            ..Default::default()
+        };
+
+        let integrator = NaivePathIntegrator::default();
+        let single =
+            render_with_threads(1, &world, &camera, &settings, &integrator, |_| {}).unwrap();
+        for threads in [0, 3] {
+            let pixels =
+                render_with_threads(threads, &world, &camera, &settings, &integrator, |_| {})
+                    .unwrap();
+            assert_eq!(single, pixels);
+        }
+    }
+
+    #[test]
+    fn crop_windows_render_only_their_pixels() {
+        let (world, _, settings) = test_scene();
+        let camera = test_camera(0.1);
+        let mut settings = ImageSettings {
+            width: 16,
+            height: 12,
+            tile_size: 5,
+            filter: ReconstructionFilter::Tent { radius: 1.5 },
+            sample_source: SampleSource::Seeded { seed: 1327 },
+            ..settings
+        };
+        let integrator = NaivePathIntegrator::default();
+        let full = render(&world, &camera, &settings, &integrator, |_| {}).unwrap();
+
+        let crop = Tile::from_corners(3, 2, 11, 9);
+        settings.crop = Some(crop);
+        let cropped = render(&world, &camera, &settings, &integrator, |_| {}).unwrap();
+        assert_eq!(full.len(), cropped.len());
+        for y in 0..settings.height {
+            for x in 0..settings.width {
+                let index = 3 * (y * settings.width + x);
+                let inside = crop.intersection(&Tile::from_corners(x, y, x + 1, y + 1));
+                match inside {
+                    Some(_) => assert_eq!(full[index..index + 3], cropped[index..index + 3]),
+                    None => assert_eq!([0, 0, 0], cropped[index..index + 3]),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn missed_pixels_are_transparent() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            coverage: true,
+            ..settings
+        };
+        let integrator = NaivePathIntegrator::default();
+
+        let film = render_film(&world, &camera, &settings, &integrator, |_| {}).unwrap();
+        let coverage = film.resolve_coverage();
+        let pixels = to_rgba(&film.resolve(), &coverage, &settings);
+        assert_eq!(4 * 8 * 8, pixels.len());
+        // the corner only sees the background, the center only the sphere
+        assert_eq!(0.0, coverage[0]);
+        assert_eq!(0, pixels[3]);
+        assert!(pixels[..3].iter().all(|&channel| channel > 0));
+        let center = 4 * 8 + 4;
+        assert_eq!(1.0, coverage[center]);
+        assert_eq!(255, pixels[4 * center + 3]);
+
+        // coverage follows the seed, the filter and the crop like the colors
+        let crop = Tile::from_corners(4, 0, 8, 8);
+        let settings = ImageSettings {
+            filter: ReconstructionFilter::Tent { radius: 1.5 },
+            sample_source: SampleSource::Seeded { seed: 1328 },
+            crop: Some(crop),
+            ..settings
+        };
+        let coverage = || {
+            render_film(&world, &camera, &settings, &integrator, |_| {})
+                .unwrap()
+                .resolve_coverage()
+        };
+        let first = coverage();
+        assert_eq!(first, coverage());
+        assert!(first.iter().any(|&alpha| alpha > 0.0 && alpha < 1.0));
+        for (index, &alpha) in first.iter().enumerate() {
+            if index % 8 < 4 {
+                assert_eq!(0.0, alpha);
+            }
+        }
+
+        // only recorded when asked for, and without changing the colors
+        let without = ImageSettings {
+            coverage: false,
+            ..settings.clone()
+        };
+        let film = render_film(&world, &camera, &without, &integrator, |_| {}).unwrap();
+        assert!(film.resolve_coverage().iter().all(|&alpha| alpha == 0.0));
+        let with = render_film(&world, &camera, &settings, &integrator, |_| {}).unwrap();
+        for (with, without) in with.resolve().iter().zip(film.resolve()) {
+            assert_eq!(with.e, without.e);
+        }
+    }
+
+    #[test]
+    fn progress_is_reported_per_tile() {
+        let (world, camera, settings) = test_scene();
+        let settings = ImageSettings {
+            width: 10,
+            height: 7,
+            samples_per_pixel: 1,
+            tile_size: 4,
+            ..settings
+        };
+
+        let reported = std::sync::Mutex::new(vec![]);
+        render(
+            &world,
+            &camera,
+            &settings,
+            &NaivePathIntegrator::default(),
+            |fraction| reported.lock().unwrap().push(fraction),
+        )
+        .unwrap();
+
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort_by(f64::total_cmp);
+        // 3 x 2 tiles
+        assert_eq!(6, reported.len());
+        assert!((reported[0] - 1.0 / 6.0).abs() < 1e-9);
+        assert_eq!(1.0, reported[5]);
+    }
+
+    #[test]
+    fn fast_preview_is_deterministic() {
+        let (world, camera, settings) = test_scene();
+        let mut settings = ImageSettings {
+            width: 9,
+            height: 9,
+            background: Environment::Constant(Color::new(0.2, 0.2, 0.2)),
+            ..settings
+        };
+        settings.use_fast_preview();
+
+        let colors = render_linear(&world, &camera, &settings).unwrap();
+        for (a, b) in colors
+            .iter()
+            .zip(render_linear(&world, &camera, &settings).unwrap())
+        {
+            assert!((*a - b).near_zero());
+        }
+
+        // the center pixel looks straight at the sphere
+        assert!((colors[4 * 9 + 4] - Color::new(0.5, 0.5, 0.5)).near_zero());
+        assert!((colors[0] - Color::new(0.2, 0.2, 0.2)).near_zero());
+    }
+
+    #[test]
+    fn escaping_rays_return_the_background() {
+        // behind the camera, so nothing is hit
+        let world = Sphere::new(
+            Vec3::new(0.0, 0.0, 5.0),
+            0.5,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        );
+        let camera = test_camera(0.0);
+        let settings = ImageSettings {
+            width: 1,
+            height: 1,
+            samples_per_pixel: 4,
+            background: Environment::Constant(Color::new(0.25, 0.64, 1.0)),
+            gamma: 2.0,
+            ..Default::default()
+        };
+
+        let pixels = render(
+            &world,
+            &camera,
+            &settings,
+            &NaivePathIntegrator::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(vec![127, 204, 255], pixels);
+    }
+
+    #[test]
+    fn tile_size_does_not_change_the_image() {
+        let (world, camera, settings) = test_scene();
+        let mut settings = ImageSettings {
+            width: 11,
+            height: 7,
+            samples_per_pixel: 2,
+            filter: ReconstructionFilter::Tent { radius: 1.5 },
+            sample_source: SampleSource::Table { seed: 11 },
+            ..settings
+        };
+
+        let whole = render_linear(&world, &camera, &settings).unwrap();
+        settings.tile_size = 3;
+        let tiled = render_linear(&world, &camera, &settings).unwrap();
+
+        for (a, b) in whole.iter().zip(tiled) {
+            assert!((*a - b).near_zero());
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_converges_to_fixed_sampling() {
+        let material = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.5, 0.5, 0.5,
+        )));
+        // the ground makes the shading of the sphere noisy
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, -1.0),
+                0.5,
+                material.clone(),
+            )),
+            Arc::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, material)),
+        ];
+        let world = HittableList::from(world);
+        let camera = test_camera(0.0);
+        let settings = ImageSettings {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 256,
+            background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+            sample_source: SampleSource::Table { seed: 13 },
+            ..Default::default()
+        };
+        let adaptive = AdaptiveSampling {
+            min_samples: 8,
+            max_samples: 256,
+            tolerance: 0.02,
+        };
+
+        let fixed = render_linear(&world, &camera, &settings).unwrap();
+        let image = render_adaptive(&world, &camera, &settings, &adaptive).unwrap();
+
+        // the flat background converges right away, the sphere does not
+        assert_eq!(8, image.sample_counts[0]);
+        assert!(image.sample_counts[5 * 8 + 4] > 8);
+        assert!(image.sample_counts.iter().all(|&count| count <= 256));
+
+        let mean = |colors: &[Color]| colors.iter().map(|c| c.luminance()).sum::<f64>() / 64.0;
+        assert!((mean(&fixed) - mean(&image.colors)).abs() < 0.01 * mean(&fixed));
+
+        let heatmap = image.sample_heatmap();
+        assert!(heatmap[0].x() < heatmap[5 * 8 + 4].x());
+    }
+
+    #[test]
+    fn adaptive_renders_are_cropped() {
+        let (world, camera, settings) = test_scene();
+        let crop = Tile::from_corners(2, 3, 7, 6);
+        let settings = ImageSettings {
+            tile_size: 3,
+            filter: ReconstructionFilter::Tent { radius: 1.5 },
+            sample_source: SampleSource::Seeded { seed: 1266 },
+            crop: Some(crop),
+            ..settings
+        };
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 16,
+            tolerance: 0.05,
+        };
+
+        let image = render_adaptive(&world, &camera, &settings, &adaptive).unwrap();
+        for y in 0..settings.height {
+            for x in 0..settings.width {
+                let index = y * settings.width + x;
+                let inside = crop.intersection(&Tile::from_corners(x, y, x + 1, y + 1));
+                match inside {
+                    Some(_) => {
+                        assert!(image.sample_counts[index] >= 4);
+                        assert!(image.colors[index].luminance() > 0.0);
+                    }
+                    None => {
+                        assert_eq!(0, image.sample_counts[index]);
+                        assert_eq!([0.0; 3], image.colors[index].e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bright_samples_are_clamped() {
+        // a very bright light seen directly and through a glass sphere
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, -1.0),
+                0.4,
+                Arc::new(DielectricMaterial::new(1.5)),
+            )),
+            Arc::new(Sphere::new(
+                Vec3::new(0.3, 0.3, -3.0),
+                0.5,
+                Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+                    500.0, 500.0, 500.0,
+                ))),
+            )),
+        ];
+        let world = HittableList::from(world);
+        let camera = test_camera(0.0);
+        let settings = ImageSettings {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 4,
+            background: Environment::Constant(Color::new(0.1, 0.1, 0.1)),
+            max_luminance_clamp: Some(2.0),
+            ..Default::default()
+        };
+
+        let colors = render_linear(&world, &camera, &settings).unwrap();
+        assert!(colors.iter().all(|color| color.luminance() <= 2.0 + 1e-9));
+        assert!(colors.iter().any(|color| color.luminance() > 1.0));
+    }
+
+    #[test]
+    fn aovs_record_the_first_hit() {
+        let world = Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.5, 0.5, 0.5,
+            ))),
+        );
+        // the aperture does not blur the outputs
+        let camera = test_camera(0.5);
+        let settings = ImageSettings {
+            width: 3,
+            height: 3,
+            ..Default::default()
+        };
+
+        let aovs = render_aovs(&world, &camera, &settings).unwrap();
+        // the center pixel looks straight at the sphere, the corners miss it
+        assert!((aovs.depth[4] - 2.0).abs() < 1e-9);
+        assert!((aovs.normals[4] - Vec3::new(0.0, 0.0, 1.0)).near_zero());
+        assert_eq!(f64::INFINITY, aovs.depth[0]);
+        assert!(aovs.normals[0].near_zero());
+
+        assert_eq!(1.0, aovs.depth_image()[4].x());
+        assert_eq!(0.0, aovs.depth_image()[0].x());
+        assert_eq!(1.0, aovs.normal_image()[4].z());
+        assert_eq!(0.0, aovs.normal_image()[0].z());
+    }
 }
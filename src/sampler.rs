@@ -0,0 +1,228 @@
+use rand::{Rng, RngCore, SeedableRng};
+
+/// Where the renderer takes its random numbers from.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SampleSource {
+    /// Fresh random numbers on every render.
+    #[default]
+    Random,
+    /// A precomputed table generated from `seed`. Every sample of every pixel
+    /// starts reading at its own fixed position in the table, so two renders
+    /// only differ where the code differs, even if a changed code path
+    /// consumes a different amount of random numbers.
+    Table { seed: u64 },
+    /// An independent generator for every sample of every pixel, seeded from
+    /// `seed`, the pixel and the sample index. See `PixelRng`.
+    Seeded { seed: u64 },
+}
+
+/// How the samples of a pixel are placed within it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplingStrategy {
+    /// Every sample lands anywhere in the pixel.
+    #[default]
+    Random,
+    /// The pixel is split into a near-square grid with one jittered sample
+    /// per cell, which spreads the samples more evenly. See
+    /// `stratified_offset`.
+    Stratified,
+}
+
+/// Offset within the pixel of sample `index` out of `count` samples per
+/// pixel. The pixel is split into `rows` x `columns` strata with
+/// `rows = floor(sqrt(count))` and `columns = floor(count / rows)`, and the
+/// first samples of every `count` land in one stratum each. Samples left over
+/// when `count` is not a product like that are placed uniformly at random.
+pub fn stratified_offset(index: usize, count: usize, rng: &mut dyn RngCore) -> (f64, f64) {
+    let count = count.max(1);
+    let rows = (count as f64).sqrt().floor() as usize;
+    let columns = count / rows;
+    let (jitter_x, jitter_y) = (rng.gen::<f64>(), rng.gen::<f64>());
+
+    let stratum = index % count;
+    if stratum >= rows * columns {
+        return (jitter_x, jitter_y);
+    }
+    (
+        ((stratum % columns) as f64 + jitter_x) / columns as f64,
+        ((stratum / columns) as f64 + jitter_y) / rows as f64,
+    )
+}
+
+const TABLE_SIZE: usize = 1 << 20;
+
+pub struct SampleTable {
+    seed: u64,
+    values: Vec<u64>,
+}
+
+impl SampleTable {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        Self {
+            seed,
+            values: (0..TABLE_SIZE).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    /// Random number generator for the given sample of the pixel at `(x, y)`.
+    pub fn rng_for(&self, x: usize, y: usize, sample_index: usize) -> TableRng<'_> {
+        let key = splitmix64(pixel_seed(self.seed, x, y) ^ splitmix64(sample_index as u64));
+        TableRng {
+            values: &self.values,
+            position: key as usize % self.values.len(),
+        }
+    }
+}
+
+pub struct TableRng<'a> {
+    values: &'a [u64],
+    position: usize,
+}
+
+impl<'a> RngCore for TableRng<'a> {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = self.values[self.position];
+        self.position = (self.position + 1) % self.values.len();
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// SplitMix64 generator for a single sample of a pixel. Cheap to create, so
+/// every sample gets its own and renders do not depend on how the work is
+/// split between threads.
+pub struct PixelRng {
+    state: u64,
+}
+
+impl PixelRng {
+    pub fn new(seed: u64, x: usize, y: usize, sample_index: usize) -> Self {
+        Self {
+            state: pixel_seed(seed, x, y) ^ splitmix64(sample_index as u64),
+        }
+    }
+}
+
+impl RngCore for PixelRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = splitmix64(self.state);
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Seed for the pixel at `(x, y)`, derived from the Z-order curve index of the
+/// pixel so that neighboring pixels and tiles do not share correlated bits.
+pub fn pixel_seed(seed: u64, x: usize, y: usize) -> u64 {
+    splitmix64(seed ^ splitmix64(morton_code(x as u32, y as u32)))
+}
+
+/// Interleaves the bits of `x` and `y` (x in the even bits).
+pub fn morton_code(x: u32, y: u32) -> u64 {
+    fn spread(value: u32) -> u64 {
+        let mut v = value as u64;
+        v = (v | (v << 16)) & 0x0000ffff0000ffff;
+        v = (v | (v << 8)) & 0x00ff00ff00ff00ff;
+        v = (v | (v << 4)) & 0x0f0f0f0f0f0f0f0f;
+        v = (v | (v << 2)) & 0x3333333333333333;
+        v = (v | (v << 1)) & 0x5555555555555555;
+        v
+    }
+
+    spread(x) | (spread(y) << 1)
+}
+
+pub fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn morton_codes() {
+        assert_eq!(0, morton_code(0, 0));
+        assert_eq!(1, morton_code(1, 0));
+        assert_eq!(2, morton_code(0, 1));
+        assert_eq!(3, morton_code(1, 1));
+        assert_eq!(0b101010, morton_code(0, 7));
+        assert_eq!(u64::MAX, morton_code(u32::MAX, u32::MAX));
+
+        assert_eq!(pixel_seed(1, 4, 5), pixel_seed(1, 4, 5));
+        assert_ne!(pixel_seed(1, 4, 5), pixel_seed(1, 5, 4));
+        assert_ne!(pixel_seed(1, 4, 5), pixel_seed(2, 4, 5));
+    }
+
+    #[test]
+    fn samples_are_independent_of_previous_consumption() {
+        let table = SampleTable::new(42);
+
+        let mut first = table.rng_for(3, 7, 1);
+        let expected: Vec<u64> = (0..4).map(|_| first.next_u64()).collect();
+
+        // consuming more numbers in another sample does not shift this one
+        let mut other = table.rng_for(3, 7, 0);
+        for _ in 0..100 {
+            other.next_u64();
+        }
+        let mut second = table.rng_for(3, 7, 1);
+        let actual: Vec<u64> = (0..4).map(|_| second.next_u64()).collect();
+
+        assert_eq!(expected, actual);
+        assert_ne!(expected[0], table.rng_for(7, 3, 1).next_u64());
+    }
+
+    #[test]
+    fn stratified_offsets_cover_all_strata() {
+        let mut rng = rand::thread_rng();
+        for (count, rows, columns) in [(9, 3, 3), (10, 3, 3), (8, 2, 4), (1, 1, 1)] {
+            let mut covered = vec![0; rows * columns];
+            for index in 0..count {
+                let (x, y) = stratified_offset(index, count, &mut rng);
+                assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                if index < rows * columns {
+                    let stratum =
+                        (y * rows as f64) as usize * columns + (x * columns as f64) as usize;
+                    covered[stratum] += 1;
+                }
+            }
+            assert!(covered.iter().all(|&samples| samples == 1), "{}", count);
+        }
+    }
+}
@@ -1,27 +1,123 @@
-use std::{
-    ops::Neg,
-    path::{self, Path},
-    sync::Arc,
-};
+use std::{ops::Neg, path, sync::Arc};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
     bvh::BvhNode,
-    camera::Camera,
-    geometry::{AABox, Hittable, RectangleXY, RectangleXZ, RectangleYZ, Sphere, Triangle},
+    camera::{Camera, CameraAnimation, CameraBuilder, CameraKeyframe, Easing},
+    environment::Environment,
+    error::PathtracerError,
+    film::ReconstructionFilter,
+    geometry::{
+        AABox, Hittable, MovingSphere, Plane, RectangleXY, RectangleXZ, RectangleYZ, Sphere,
+        Triangle,
+    },
+    jpeg,
     material::{
         DielectricMaterial, DiffuseLightMaterial, LambertianMaterial, Material, MetalMaterial,
+        NormalMapped,
     },
     obj_model::ObjModel,
-    texture::{CheckerTexture, PerlinNoiseTexture, SolidColorTexture},
+    ray::RayEpsilon,
+    renderer::{RenderMode, Tile},
+    sampler::{SampleSource, SamplingStrategy},
+    texture::{
+        CheckerSpace, CheckerTexture, PerlinNoiseTexture, SolidColorTexture, Texture,
+        TransformedTexture,
+    },
+    tone_mapping::ToneMapping,
     vec3::{Color, Vec3},
 };
 
+#[derive(Clone)]
 pub struct ImageSettings {
     pub width: usize,
     pub height: usize,
     pub samples_per_pixel: usize,
     pub max_bounces: usize,
-    pub background: Color,
+    /// What rays see when they leave the scene.
+    pub background: Environment,
+    pub filter: ReconstructionFilter,
+    pub sample_source: SampleSource,
+    pub tile_size: usize,
+    /// Roughness floor for specular materials after the first diffuse
+    /// bounce, see `Ray::color_regularized`.
+    pub path_regularization: Option<f64>,
+    /// Bounces after which paths may be terminated early, see
+    /// `PathOptions::roulette_depth`.
+    pub russian_roulette_depth: Option<usize>,
+    /// Scales down samples brighter than this luminance before they are
+    /// accumulated. This biases the image darker, but removes most fireflies
+    /// from lucky paths through glass or off mirrors.
+    pub max_luminance_clamp: Option<f64>,
+    /// Sample the emitters of the world directly at diffuse bounces, see
+    /// `PathOptions::light_sampler`.
+    pub next_event_estimation: bool,
+    /// Keeps secondary rays from hitting the surface they leave, see
+    /// `RayEpsilon`.
+    pub ray_epsilon: RayEpsilon,
+    /// Randomly offset the samples inside their pixel. Without jitter every
+    /// sample goes through the pixel center, so edges are not anti-aliased.
+    pub jitter: bool,
+    /// How jittered samples are spread within their pixel.
+    pub sampling_strategy: SamplingStrategy,
+    /// Only render the pixels of this window and leave the rest black. The
+    /// image keeps its full size. Used by the renders which accumulate samples
+    /// in a film, like `renderer::render`.
+    pub crop: Option<Tile>,
+    pub render_mode: RenderMode,
+    /// Multiplier for the linear colors before tone mapping.
+    pub exposure: f64,
+    pub tone_mapping: ToneMapping,
+    /// Exponent of the gamma correction, see `vec3::linear_to_srgb`.
+    pub gamma: f64,
+    /// Quality from 1 to 100 of JPEG output, see `jpeg::write_jpeg`.
+    pub jpeg_quality: u8,
+    /// Also record whether the camera ray of every sample hits the world, for
+    /// an alpha channel, see `Film::resolve_coverage`. This traces one more
+    /// ray per sample, so it is off unless alpha output is asked for.
+    pub coverage: bool,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            width: 400,
+            height: 400,
+            samples_per_pixel: 100,
+            max_bounces: 20,
+            background: Environment::default(),
+            filter: ReconstructionFilter::default(),
+            sample_source: SampleSource::default(),
+            tile_size: 32,
+            path_regularization: None,
+            russian_roulette_depth: Some(3),
+            max_luminance_clamp: None,
+            next_event_estimation: true,
+            ray_epsilon: RayEpsilon::default(),
+            jitter: true,
+            sampling_strategy: SamplingStrategy::default(),
+            crop: None,
+            render_mode: RenderMode::default(),
+            exposure: 1.0,
+            tone_mapping: ToneMapping::default(),
+            gamma: 2.2,
+            jpeg_quality: jpeg::DEFAULT_QUALITY,
+            coverage: false,
+        }
+    }
+}
+
+impl ImageSettings {
+    /// Switches to a single unjittered sample per pixel with the flat preview
+    /// shading, which is good enough to judge the framing of a scene.
+    pub fn use_fast_preview(&mut self) {
+        self.samples_per_pixel = 1;
+        self.max_bounces = 1;
+        self.jitter = false;
+        self.path_regularization = None;
+        self.render_mode = RenderMode::Preview;
+    }
 }
 
 pub enum OutputSettings {
@@ -36,11 +132,113 @@ pub enum OutputSettings {
 }
 
 pub trait Scene {
-    fn get_world(&self) -> BvhNode;
+    fn get_world(&self) -> Result<BvhNode, PathtracerError>;
+    /// Like `get_world`, but everything random about the world, like the
+    /// placement of objects or noise textures, is drawn from `seed`. The same
+    /// seed always gives the same world.
+    fn get_seeded_world(&self, _seed: u64) -> Result<BvhNode, PathtracerError> {
+        self.get_world()
+    }
     fn get_camera_at(&self, t: f64) -> Camera;
     fn get_output_settings(&self) -> OutputSettings;
 }
 
+/// Chainable construction of a still `Scene` from Rust code, without
+/// collecting the objects and building the BVH by hand.
+///
+/// The aspect ratio of the camera is always taken from the image settings.
+pub struct SceneBuilder {
+    objects: Vec<Arc<dyn Hittable>>,
+    camera: CameraBuilder,
+    image_settings: ImageSettings,
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self {
+            objects: vec![],
+            camera: CameraBuilder::new(),
+            image_settings: ImageSettings::default(),
+        }
+    }
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_object(mut self, object: Arc<dyn Hittable>) -> Self {
+        self.objects.push(object);
+        self
+    }
+
+    pub fn add_sphere(self, center: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        self.add_object(Arc::new(Sphere::new(center, radius, material)))
+    }
+
+    pub fn add_box(self, start: Vec3, end: Vec3, material: Arc<dyn Material>) -> Self {
+        self.add_object(Arc::new(AABox::new(start, end, material)))
+    }
+
+    pub fn add_plane(self, point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        self.add_object(Arc::new(Plane::new(point, normal, material)))
+    }
+
+    pub fn set_camera(mut self, camera: CameraBuilder) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    pub fn set_image_settings(mut self, image_settings: ImageSettings) -> Self {
+        self.image_settings = image_settings;
+        self
+    }
+
+    pub fn build(self) -> Result<BuiltScene, PathtracerError> {
+        if self.objects.is_empty() {
+            return Err(PathtracerError::EmptyScene);
+        }
+        let camera = self
+            .camera
+            .aspect_ratio(self.image_settings.width as f64 / self.image_settings.height as f64);
+        // fail here instead of when the first frame is rendered
+        camera.clone().build()?;
+
+        Ok(BuiltScene {
+            objects: self.objects,
+            camera,
+            image_settings: self.image_settings,
+        })
+    }
+}
+
+/// Scene put together by a `SceneBuilder`.
+pub struct BuiltScene {
+    objects: Vec<Arc<dyn Hittable>>,
+    camera: CameraBuilder,
+    image_settings: ImageSettings,
+}
+
+impl Scene for BuiltScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: self.image_settings.clone(),
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        self.camera
+            .clone()
+            .build()
+            .expect("camera is checked by SceneBuilder::build")
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        BvhNode::new(self.objects.clone())
+    }
+}
+
 pub struct SphereFieldScene;
 
 impl Scene for SphereFieldScene {
@@ -51,7 +249,8 @@ impl Scene for SphereFieldScene {
                 height: 480,
                 samples_per_pixel: 250,
                 max_bounces: 20,
-                background: Color::new(1.0, 1.0, 1.0),
+                background: Environment::Constant(Color::new(1.0, 1.0, 1.0)),
+                ..Default::default()
             },
             fps: 30.0,
             duration: 10.0,
@@ -88,15 +287,20 @@ impl Scene for SphereFieldScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
+        let mut rng = StdRng::seed_from_u64(seed);
 
         let checker_texture = CheckerTexture::new(
             Box::new(SolidColorTexture::new(Color::new(0.2, 0.3, 0.1))),
             Box::new(SolidColorTexture::new(Color::new(0.9, 0.9, 0.9))),
         );
         let material_ground = Arc::new(LambertianMaterial::new(Box::new(checker_texture)));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, -1000.0, 0.0),
             1000.0,
             material_ground,
@@ -109,48 +313,48 @@ impl Scene for SphereFieldScene {
                 }
 
                 let center = Vec3::new(
-                    a as f64 + 0.5 * rand::random::<f64>(),
+                    a as f64 + 0.5 * rng.gen::<f64>(),
                     0.2,
-                    b as f64 + 0.9 * rand::random::<f64>(),
+                    b as f64 + 0.9 * rng.gen::<f64>(),
                 );
                 let radius = 0.2;
 
-                let (material, is_glass): (Arc<dyn Material>, bool) = match rand::random::<f64>() {
+                let (material, is_glass): (Arc<dyn Material>, bool) = match rng.gen::<f64>() {
                     x if x < 0.6 => {
-                        let albedo = Box::new(SolidColorTexture::new(Color::random()));
+                        let albedo = Box::new(SolidColorTexture::new(Color::random(&mut rng)));
                         (Arc::new(LambertianMaterial::new(albedo)), false)
                     }
                     x if x < 0.8 => {
-                        let albedo = Color::random_range(0.5, 1.0);
-                        let fuzz = rand::random::<f64>();
+                        let albedo = Color::random_range(&mut rng, 0.5, 1.0);
+                        let fuzz = rng.gen::<f64>();
                         (Arc::new(MetalMaterial::new_from_color(albedo, fuzz)), false)
                     }
                     _ => (Arc::new(DielectricMaterial::new(1.5)), true),
                 };
 
-                if is_glass && rand::random::<f64>() < 0.5 {
-                    world.push(Box::new(Sphere::new(center, radius, material.clone())));
-                    world.push(Box::new(Sphere::new(center, radius.neg() + 0.02, material)));
+                if is_glass && rng.gen::<f64>() < 0.5 {
+                    world.push(Arc::new(Sphere::new(center, radius, material.clone())));
+                    world.push(Arc::new(Sphere::new(center, radius.neg() + 0.02, material)));
                 } else {
-                    world.push(Box::new(Sphere::new(center, radius, material)));
+                    world.push(Arc::new(Sphere::new(center, radius, material)));
                 }
             }
         }
 
         let material_big1 = Arc::new(DielectricMaterial::new(1.5));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(-4.0, 1.0, 0.0),
             1.0,
             material_big1.clone(),
         )));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(-4.0, 1.0, 0.0),
             -0.95,
             material_big1,
         )));
 
         let material_big2 = Arc::new(DielectricMaterial::new(1.5));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(4.0, 1.0, 0.0),
             1.0,
             material_big2,
@@ -160,7 +364,7 @@ impl Scene for SphereFieldScene {
             Color::new(0.7, 0.6, 0.5),
             0.0,
         ));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, 1.0, 0.0),
             1.0,
             material_big3,
@@ -170,6 +374,95 @@ impl Scene for SphereFieldScene {
     }
 }
 
+/// Small diffuse spheres bouncing up during the exposure, rendered with
+/// motion blur.
+pub struct BouncingSpheresScene;
+
+impl Scene for BouncingSpheresScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: ImageSettings {
+                width: 400,
+                height: 225,
+                samples_per_pixel: 100,
+                max_bounces: 50,
+                background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        Camera::new(
+            Vec3::new(13.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            20.0,
+            400.0 / 225.0,
+            0.0,
+            10.0,
+        )
+        .with_shutter(0.0, 1.0)
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let checker_texture = CheckerTexture::new(
+            Box::new(SolidColorTexture::new(Color::new(0.2, 0.3, 0.1))),
+            Box::new(SolidColorTexture::new(Color::new(0.9, 0.9, 0.9))),
+        );
+        world.push(Arc::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(LambertianMaterial::new(Box::new(checker_texture))),
+        )));
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let center = Vec3::new(
+                    a as f64 + 0.9 * rng.gen::<f64>(),
+                    0.2,
+                    b as f64 + 0.9 * rng.gen::<f64>(),
+                );
+                let bounce = Vec3::new(0.0, 0.5 * rng.gen::<f64>(), 0.0);
+                let material = Arc::new(LambertianMaterial::new_from_color(
+                    Color::random(&mut rng) * Color::random(&mut rng),
+                ));
+                world.push(Arc::new(MovingSphere::new(
+                    center,
+                    center + bounce,
+                    0.0,
+                    1.0,
+                    0.2,
+                    material,
+                )));
+            }
+        }
+
+        world.push(Arc::new(Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Arc::new(DielectricMaterial::new(1.5)),
+        )));
+        world.push(Arc::new(Sphere::new(
+            Vec3::new(4.0, 1.0, 0.0),
+            1.0,
+            Arc::new(MetalMaterial::new_from_color(
+                Color::new(0.7, 0.6, 0.5),
+                0.0,
+            )),
+        )));
+
+        BvhNode::new(world)
+    }
+}
+
 pub struct TwoSphereCheckersScene;
 
 impl Scene for TwoSphereCheckersScene {
@@ -180,7 +473,8 @@ impl Scene for TwoSphereCheckersScene {
                 height: 480,
                 samples_per_pixel: 250,
                 max_bounces: 20,
-                background: Color::new(1.0, 1.0, 1.0),
+                background: Environment::Constant(Color::new(1.0, 1.0, 1.0)),
+                ..Default::default()
             },
         }
     }
@@ -209,23 +503,28 @@ impl Scene for TwoSphereCheckersScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
 
         let checker_texture = CheckerTexture::new(
             Box::new(SolidColorTexture::new(Color::new(0.2, 0.3, 0.1))),
             Box::new(SolidColorTexture::new(Color::new(0.9, 0.9, 0.9))),
         );
         let material_ground = Arc::new(LambertianMaterial::new(Box::new(checker_texture)));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, -10.0, 0.0),
             10.0,
             material_ground,
         )));
 
-        let perlin_texture = PerlinNoiseTexture::new(4.0);
+        let perlin_texture =
+            PerlinNoiseTexture::new(4.0).with_seed(StdRng::seed_from_u64(seed).gen());
         let material_top = Arc::new(LambertianMaterial::new(Box::new(perlin_texture)));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, 10.0, 0.0),
             10.0,
             material_top,
@@ -235,6 +534,175 @@ impl Scene for TwoSphereCheckersScene {
     }
 }
 
+/// The scene at the end of "Ray Tracing in One Weekend": a diffuse sphere
+/// between a glass and a metal one.
+pub struct ThreeSpheresScene;
+
+impl Scene for ThreeSpheresScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: ImageSettings {
+                width: 640,
+                height: 360,
+                samples_per_pixel: 100,
+                max_bounces: 50,
+                background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        Camera::new(
+            Vec3::new(-2.0, 2.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            16.0 / 9.0,
+            0.0,
+            3.4,
+        )
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let ground = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.8, 0.8, 0.0,
+        )));
+        let center = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.1, 0.2, 0.5,
+        )));
+        let left = Arc::new(DielectricMaterial::new(1.5));
+        let right = Arc::new(MetalMaterial::new_from_color(
+            Color::new(0.8, 0.6, 0.2),
+            0.0,
+        ));
+
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, ground)),
+            Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, center)),
+            Arc::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.5, left)),
+            Arc::new(Sphere::new(Vec3::new(1.0, 0.0, -1.0), 0.5, right)),
+        ];
+        BvhNode::new(world)
+    }
+}
+
+/// The Perlin noise scene of "Ray Tracing: The Next Week": a marbled sphere
+/// on a marbled ground.
+pub struct PerlinSpheresScene;
+
+impl Scene for PerlinSpheresScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: ImageSettings {
+                width: 640,
+                height: 360,
+                samples_per_pixel: 100,
+                max_bounces: 20,
+                background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        Camera::new(
+            Vec3::new(13.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            20.0,
+            16.0 / 9.0,
+            0.0,
+            10.0,
+        )
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut marble = |scale| -> Arc<dyn Material> {
+            Arc::new(LambertianMaterial::new(Box::new(
+                PerlinNoiseTexture::new(scale).with_seed(rng.gen()),
+            )))
+        };
+
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(
+                Vec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                marble(4.0),
+            )),
+            Arc::new(Sphere::new(Vec3::new(0.0, 2.0, 0.0), 2.0, marble(4.0))),
+        ];
+        BvhNode::new(world)
+    }
+}
+
+/// Normal map of ripples running around a sphere and bands along it.
+struct RippleNormalTexture;
+
+impl Texture for RippleNormalTexture {
+    fn value(&self, u: f64, v: f64, _: Vec3) -> Color {
+        let normal = Vec3::new(0.4 * (u * 120.0).sin(), 0.2 * (v * 40.0).sin(), 1.0).unit_vector();
+        0.5 * (normal + Vec3::new(1.0, 1.0, 1.0))
+    }
+}
+
+/// A smooth sphere shaded with a normal map, so it looks bumpy.
+pub struct BumpySphereScene;
+
+impl Scene for BumpySphereScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: ImageSettings {
+                width: 400,
+                height: 400,
+                samples_per_pixel: 200,
+                max_bounces: 20,
+                background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 2.0, 8.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            30.0,
+            1.0,
+            0.0,
+            8.0,
+        )
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let ground = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.5, 0.5, 0.5,
+        )));
+        let bumpy = Arc::new(NormalMapped::new(
+            Arc::new(LambertianMaterial::new_from_color(Color::new(
+                0.8, 0.3, 0.2,
+            ))),
+            Box::new(RippleNormalTexture),
+        ));
+        let light = Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
+            8.0, 8.0, 8.0,
+        )));
+
+        let world: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)),
+            Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, bumpy)),
+            Arc::new(Sphere::new(Vec3::new(-3.0, 5.0, 3.0), 1.0, light)),
+        ];
+        BvhNode::new(world)
+    }
+}
+
 pub struct LightTestScene;
 
 impl Scene for LightTestScene {
@@ -245,7 +713,8 @@ impl Scene for LightTestScene {
                 height: 480,
                 samples_per_pixel: 2000,
                 max_bounces: 50,
-                background: Color::new(0.0, 0.0, 0.0),
+                background: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+                ..Default::default()
             },
         }
     }
@@ -274,17 +743,22 @@ impl Scene for LightTestScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
 
-        let perlin_texture = PerlinNoiseTexture::new(4.0);
+        let perlin_texture =
+            PerlinNoiseTexture::new(4.0).with_seed(StdRng::seed_from_u64(seed).gen());
         let material_ground = Arc::new(LambertianMaterial::new(Box::new(perlin_texture)));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, -1000.0, 0.0),
             1000.0,
             material_ground.clone(),
         )));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, 2.0, 0.0),
             2.0,
             material_ground,
@@ -293,7 +767,7 @@ impl Scene for LightTestScene {
         let material_light = Arc::new(DiffuseLightMaterial::new_from_color(Color::new(
             4.0, 4.0, 4.0,
         )));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXY::new(
                 Vec3::new(3.0, 1.0, -2.0),
                 Vec3::new(5.0, 3.0, -2.0),
@@ -302,7 +776,7 @@ impl Scene for LightTestScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(-1.0, 6.0, -1.0),
                 Vec3::new(1.0, 6.0, 1.0),
@@ -311,7 +785,7 @@ impl Scene for LightTestScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleYZ::new(
                 Vec3::new(-6.0, 1.0, -2.0),
                 Vec3::new(-6.0, 3.0, 2.0),
@@ -335,7 +809,8 @@ impl Scene for CornellBoxScene {
                 height: 400,
                 samples_per_pixel: 1000,
                 max_bounces: 20,
-                background: Color::new(0.0, 0.0, 0.0),
+                background: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+                ..Default::default()
             },
         }
     }
@@ -364,8 +839,8 @@ impl Scene for CornellBoxScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
 
         let material_red = Arc::new(LambertianMaterial::new_from_color(Color::new(
             0.65, 0.05, 0.05,
@@ -381,7 +856,7 @@ impl Scene for CornellBoxScene {
         )));
         let material_glass = Arc::new(DielectricMaterial::new(1.5));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleYZ::new(
                 Vec3::new(555.0, 0.0, 0.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -390,7 +865,7 @@ impl Scene for CornellBoxScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleYZ::new(
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(0.0, 555.0, 555.0),
@@ -400,7 +875,7 @@ impl Scene for CornellBoxScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(0.0, 555.0, 0.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -409,7 +884,7 @@ impl Scene for CornellBoxScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(555.0, 0.0, 555.0),
@@ -418,7 +893,7 @@ impl Scene for CornellBoxScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(213.0, 554.0, 227.0),
                 Vec3::new(343.0, 554.0, 332.0),
@@ -428,7 +903,7 @@ impl Scene for CornellBoxScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXY::new(
                 Vec3::new(0.0, 0.0, 555.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -438,23 +913,23 @@ impl Scene for CornellBoxScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(AABox::new(
+        world.push(Arc::new(AABox::new(
             Vec3::new(130.0, 0.0, 65.0),
             Vec3::new(295.0, 165.0, 230.0),
             material_white.clone(),
         )));
-        world.push(Box::new(AABox::new(
+        world.push(Arc::new(AABox::new(
             Vec3::new(265.0, 0.0, 295.0),
             Vec3::new(430.0, 330.0, 460.0),
             material_white,
         )));
 
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(212.5, 255.0, 147.5),
             90.0,
             material_glass.clone(),
         )));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(347.5, 420.0, 377.5),
             90.0,
             material_glass,
@@ -464,6 +939,63 @@ impl Scene for CornellBoxScene {
     }
 }
 
+/// The Cornell box seen from a camera swinging around the open side of the
+/// box and zooming in, see `CameraAnimation`.
+pub struct CornellOrbitScene;
+
+impl CornellOrbitScene {
+    fn animation(&self) -> CameraAnimation {
+        let center = Vec3::new(278.0, 278.0, 278.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let keyframe = |t: f64, angle: f64, height: f64, vertical_fov| {
+            let angle = angle.to_radians();
+            let offset = Vec3::new(-angle.sin(), 0.0, -angle.cos());
+            CameraKeyframe::new(
+                t,
+                center + 1078.0 * offset + Vec3::new(0.0, height, 0.0),
+                center,
+                up,
+                vertical_fov,
+            )
+        };
+
+        CameraAnimation::new(
+            vec![
+                keyframe(0.0, -20.0, 0.0, 40.0),
+                keyframe(0.5, 0.0, 150.0, 34.0),
+                keyframe(1.0, 20.0, 0.0, 40.0),
+            ],
+            1.0,
+        )
+        .with_easing(Easing::EaseInOut)
+    }
+}
+
+impl Scene for CornellOrbitScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::Animation {
+            image_settings: ImageSettings {
+                width: 400,
+                height: 400,
+                samples_per_pixel: 200,
+                max_bounces: 20,
+                background: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+                ..Default::default()
+            },
+            fps: 24.0,
+            duration: 4.0,
+        }
+    }
+
+    fn get_camera_at(&self, t: f64) -> Camera {
+        self.animation().camera_at(t)
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        CornellBoxScene.get_world()
+    }
+}
+
 pub struct TriangleTestScene;
 
 impl Scene for TriangleTestScene {
@@ -474,7 +1006,8 @@ impl Scene for TriangleTestScene {
                 height: 400,
                 samples_per_pixel: 1000,
                 max_bounces: 20,
-                background: Color::new(0.0, 0.0, 0.0),
+                background: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+                ..Default::default()
             },
         }
     }
@@ -503,8 +1036,8 @@ impl Scene for TriangleTestScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
 
         let material_red = Arc::new(LambertianMaterial::new_from_color(Color::new(
             0.65, 0.05, 0.05,
@@ -520,7 +1053,7 @@ impl Scene for TriangleTestScene {
         )));
         let material_glass = Arc::new(DielectricMaterial::new(1.5));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleYZ::new(
                 Vec3::new(555.0, 0.0, 0.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -529,7 +1062,7 @@ impl Scene for TriangleTestScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleYZ::new(
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(0.0, 555.0, 555.0),
@@ -539,7 +1072,7 @@ impl Scene for TriangleTestScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(0.0, 555.0, 0.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -548,7 +1081,7 @@ impl Scene for TriangleTestScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(555.0, 0.0, 555.0),
@@ -557,7 +1090,7 @@ impl Scene for TriangleTestScene {
             )
             .expect("rectangle definition is not axis aligned"),
         ));
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXZ::new(
                 Vec3::new(213.0, 554.0, 227.0),
                 Vec3::new(343.0, 554.0, 332.0),
@@ -567,7 +1100,7 @@ impl Scene for TriangleTestScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(
+        world.push(Arc::new(
             RectangleXY::new(
                 Vec3::new(0.0, 0.0, 555.0),
                 Vec3::new(555.0, 555.0, 555.0),
@@ -577,13 +1110,13 @@ impl Scene for TriangleTestScene {
             .expect("rectangle definition is not axis aligned"),
         ));
 
-        world.push(Box::new(Triangle::new_without_normal(
+        world.push(Arc::new(Triangle::new_without_normal(
             Vec3::new(200.0, 100.0, 100.0),
             Vec3::new(300.0, 300.0, 500.0),
             Vec3::new(400.0, 100.0, 100.0),
             material_glass,
         )));
-        world.push(Box::new(Triangle::new_without_normal(
+        world.push(Arc::new(Triangle::new_without_normal(
             Vec3::new(100.0, 300.0, 100.0),
             Vec3::new(150.0, 400.0, 250.0),
             Vec3::new(100.0, 300.0, 400.0),
@@ -594,6 +1127,58 @@ impl Scene for TriangleTestScene {
     }
 }
 
+/// A single sphere with red and blue checkers in UV space facing the camera,
+/// to check the UV mapping of spheres. The squares are 40° of longitude wide
+/// and 20° of latitude high, shifted 5° south so the image is not symmetric
+/// top to bottom, and the point facing the camera is in a blue one.
+pub struct UvSphereScene;
+
+impl Scene for UvSphereScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: ImageSettings {
+                width: 400,
+                height: 400,
+                samples_per_pixel: 100,
+                max_bounces: 20,
+                background: Environment::sky(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            36.0,
+            1.0,
+            0.0,
+            4.0,
+        )
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let checker_texture = CheckerTexture::new(
+            Box::new(SolidColorTexture::new(Color::new(0.8, 0.1, 0.1))),
+            Box::new(SolidColorTexture::new(Color::new(0.1, 0.1, 0.8))),
+        )
+        .with_scale(9.0)
+        .with_space(CheckerSpace::Uv);
+        let checker_texture =
+            TransformedTexture::new(Box::new(checker_texture)).with_offset(0.0, 0.25 / 9.0);
+        let material = Arc::new(LambertianMaterial::new(Box::new(checker_texture)));
+
+        let world: Vec<Arc<dyn Hittable>> = vec![Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            material,
+        ))];
+        BvhNode::new(world)
+    }
+}
+
 pub struct ModelTestScene {
     pub path_str: String,
 }
@@ -606,7 +1191,8 @@ impl Scene for ModelTestScene {
                 height: 800,
                 samples_per_pixel: 250,
                 max_bounces: 20,
-                background: Color::new(1.0, 1.0, 1.0),
+                background: Environment::Constant(Color::new(1.0, 1.0, 1.0)),
+                ..Default::default()
             },
         }
     }
@@ -635,24 +1221,219 @@ impl Scene for ModelTestScene {
         )
     }
 
-    fn get_world(&self) -> BvhNode {
-        let mut world: Vec<Box<dyn Hittable>> = vec![];
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
 
         let checker_texture = CheckerTexture::new(
             Box::new(SolidColorTexture::new(Color::new(0.2, 0.3, 0.1))),
             Box::new(SolidColorTexture::new(Color::new(0.9, 0.9, 0.9))),
         );
         let material_ground = Arc::new(LambertianMaterial::new(Box::new(checker_texture)));
-        world.push(Box::new(Sphere::new(
+        world.push(Arc::new(Sphere::new(
             Vec3::new(0.0, -1000.0, 0.0),
             1000.0,
             material_ground,
         )));
 
-        world.push(Box::new(ObjModel::new_from_path(path::Path::new(
+        world.push(Arc::new(ObjModel::new_from_path(path::Path::new(
             self.path_str.as_str(),
-        ))));
+        ))?));
 
         BvhNode::new(world)
     }
 }
+
+/// Names of the built-in scenes, see `scene_by_name`.
+pub const SCENE_NAMES: [&str; 12] = [
+    "sphere-field",
+    "bouncing-spheres",
+    "three-spheres",
+    "two-sphere-checkers",
+    "perlin-spheres",
+    "bumpy-sphere",
+    "light-test",
+    "cornell-box",
+    "cornell-orbit",
+    "triangle-test",
+    "uv-sphere",
+    "model-test",
+];
+
+/// Model shown by the `model-test` scene, relative to the repository root.
+/// Other models are shown by passing their path as the scene.
+const MODEL_TEST_PATH: &str = "scenes/textured_quad/quad.obj";
+
+/// The built-in scene called `name`, one of `SCENE_NAMES`.
+pub fn scene_by_name(name: &str) -> Option<Box<dyn Scene>> {
+    match name {
+        "sphere-field" => Some(Box::new(SphereFieldScene)),
+        "bouncing-spheres" => Some(Box::new(BouncingSpheresScene)),
+        "three-spheres" => Some(Box::new(ThreeSpheresScene)),
+        "two-sphere-checkers" => Some(Box::new(TwoSphereCheckersScene)),
+        "perlin-spheres" => Some(Box::new(PerlinSpheresScene)),
+        "bumpy-sphere" => Some(Box::new(BumpySphereScene)),
+        "light-test" => Some(Box::new(LightTestScene)),
+        "cornell-box" => Some(Box::new(CornellBoxScene)),
+        "cornell-orbit" => Some(Box::new(CornellOrbitScene)),
+        "triangle-test" => Some(Box::new(TriangleTestScene)),
+        "uv-sphere" => Some(Box::new(UvSphereScene)),
+        "model-test" => Some(Box::new(ModelTestScene {
+            path_str: MODEL_TEST_PATH.to_string(),
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integrator::{NaivePathIntegrator, PreviewIntegrator},
+        renderer,
+    };
+
+    #[test]
+    fn build_and_render_a_scene() {
+        let white = Arc::new(LambertianMaterial::new_from_color(Color::new(
+            0.8, 0.8, 0.8,
+        )));
+        let scene = SceneBuilder::new()
+            .add_sphere(Vec3::new(0.0, 1.0, 0.0), 1.0, white.clone())
+            .add_box(
+                Vec3::new(1.5, 0.0, -0.5),
+                Vec3::new(2.5, 1.0, 0.5),
+                white.clone(),
+            )
+            .add_plane(Vec3::default(), Vec3::new(0.0, 1.0, 0.0), white)
+            .set_camera(
+                CameraBuilder::new()
+                    .look_from(Vec3::new(0.0, 1.0, 5.0))
+                    .look_at(Vec3::new(0.0, 1.0, 0.0))
+                    .fov(40.0),
+            )
+            .set_image_settings(ImageSettings {
+                width: 8,
+                height: 6,
+                samples_per_pixel: 4,
+                background: Environment::Constant(Color::new(1.0, 1.0, 1.0)),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let world = scene.get_world().unwrap();
+        let camera = scene.get_camera_at(0.0);
+        let settings = match scene.get_output_settings() {
+            OutputSettings::StaticImage { image_settings } => image_settings,
+            _ => panic!("built scenes are still images"),
+        };
+        let pixels = renderer::render(
+            &world,
+            &camera,
+            &settings,
+            &NaivePathIntegrator::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(8 * 6 * 3, pixels.len());
+        // lit by the white sky, so something reaches the camera
+        assert!(pixels.iter().any(|&value| value > 0));
+
+        assert!(matches!(
+            SceneBuilder::new().build(),
+            Err(PathtracerError::EmptyScene)
+        ));
+    }
+
+    #[test]
+    fn registered_scenes_build_worlds() {
+        for name in SCENE_NAMES {
+            let scene = scene_by_name(name).unwrap();
+            assert!(scene.get_world().is_ok(), "{}", name);
+            scene.get_camera_at(0.0);
+        }
+        assert!(scene_by_name("no-such-scene").is_none());
+    }
+
+    #[test]
+    fn seeded_worlds_are_reproducible() {
+        let render = |scene: &dyn Scene, seed: u64| {
+            let mut settings = ImageSettings {
+                width: 16,
+                height: 12,
+                background: Environment::Constant(Color::new(0.7, 0.8, 1.0)),
+                sample_source: SampleSource::Seeded { seed },
+                ..Default::default()
+            };
+            settings.use_fast_preview();
+            renderer::render(
+                &scene.get_seeded_world(seed).unwrap(),
+                &scene.get_camera_at(0.0),
+                &settings,
+                &PreviewIntegrator::default(),
+                |_| {},
+            )
+            .unwrap()
+        };
+
+        let scenes: [&dyn Scene; 2] = [&SphereFieldScene, &PerlinSpheresScene];
+        for scene in scenes {
+            let first = render(scene, 1312);
+            assert_eq!(first, render(scene, 1312));
+            assert_ne!(first, render(scene, 1313));
+        }
+    }
+
+    #[test]
+    fn uv_sphere_checkers() {
+        let scene = UvSphereScene;
+        let mut settings = ImageSettings {
+            width: 16,
+            height: 16,
+            background: Environment::Constant(Color::default()),
+            ..Default::default()
+        };
+        settings.use_fast_preview();
+        let pixels = renderer::render(
+            &scene.get_world().unwrap(),
+            &scene.get_camera_at(0.0),
+            &settings,
+            &PreviewIntegrator::default(),
+            |_| {},
+        )
+        .unwrap();
+        let map: Vec<String> = pixels
+            .chunks_exact(3 * 16)
+            .map(|row| {
+                row.chunks_exact(3)
+                    .map(|pixel| match pixel {
+                        [0, 0, 0] => ' ',
+                        [r, _, b] if r > b => 'R',
+                        _ => 'B',
+                    })
+                    .collect()
+            })
+            .collect();
+        // the squares are centered neither horizontally nor vertically, so
+        // mirrored u or v coordinates would change the map
+        let expected = [
+            "                ",
+            "                ",
+            "     BBRRRB     ",
+            "    RRRBBBBR    ",
+            "   RRRRBBBBRR   ",
+            "  RBBBBRRRRRBB  ",
+            "  RBBBBRRRRRBB  ",
+            "  RRRRRBBBBBRR  ",
+            "  RRRRRBBBBBRR  ",
+            "  BRRRRBBBBBRR  ",
+            "  RBBBBRRRRRBB  ",
+            "   BBBBRRRRBB   ",
+            "    RRRBBBBR    ",
+            "     RRBBBR     ",
+            "                ",
+            "                ",
+        ];
+        assert_eq!(expected.to_vec(), map);
+    }
+}
@@ -0,0 +1,657 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+use crate::{
+    bvh::BvhNode,
+    camera::{Camera, CameraBuilder},
+    environment::Environment,
+    error::PathtracerError,
+    film::ReconstructionFilter,
+    geometry::{
+        AABox, FlipFace, Hittable, Plane, Quad, RectangleXY, RectangleXZ, RectangleYZ, Sphere,
+        Triangle,
+    },
+    json::{self, JsonValue},
+    material::{
+        DielectricMaterial, DiffuseLightMaterial, DiffuseSampling, LambertianMaterial, Material,
+        MetalMaterial, SpotLightMaterial,
+    },
+    obj_model::ObjModel,
+    ray::{RayEpsilon, DEFAULT_RAY_EPSILON},
+    renderer::RenderMode,
+    scene::{ImageSettings, OutputSettings, Scene},
+    texture::{
+        CachedTexture, CheckerSpace, CheckerTexture, ImageTexture, MarbleTexture,
+        PerlinNoiseTexture, SolidColorTexture, Texture, TransformedTexture, TriplanarTexture,
+        Turbulence,
+    },
+    vec3::{Color, Vec3},
+};
+
+/// A scene described by a JSON file instead of code. The file holds an
+/// `image` object with the image settings, a `camera` object, named
+/// `materials` and a list of `objects`. `image.integrator` picks between the
+/// `"naive"` and the default `"importance"` sampled path tracer, or shows the
+/// surface normals with `"normals"`. `image.filter` sets the pixel
+/// reconstruction filter as
+/// `{ "type": "box" | "triangle" | "gaussian", "radius": 1.5, "alpha": 2 }`:
+///
+/// ```json
+/// {
+///     "image": { "width": 400, "height": 300, "samples_per_pixel": 64, "background": [0.7, 0.8, 1.0] },
+///     "camera": { "look_from": [0, 1, 5], "look_at": [0, 1, 0], "fov": 40 },
+///     "materials": {
+///         "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] },
+///         "floor": { "type": "lambertian", "albedo": { "type": "checker", "odd": [0, 0, 0], "even": [1, 1, 1] } }
+///     },
+///     "objects": [
+///         { "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": "red" },
+///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0], "material": "floor" }
+///     ]
+/// }
+/// ```
+///
+/// Colors and vectors are arrays of three numbers, and a texture can be given
+/// as a plain color. The background is either a color or
+/// `{ "type": "sky", "bottom": [...], "top": [...] }` for a gradient. Objects
+/// take either the name of a material or a material object, and
+/// `"flip": true` swaps their front and back faces. Relative paths of images
+/// and models are resolved against the directory of the scene file. See
+/// `scenes/example.json` for all the supported types.
+pub struct FileScene {
+    image_settings: ImageSettings,
+    camera: CameraBuilder,
+    materials: JsonValue,
+    objects: JsonValue,
+    directory: PathBuf,
+}
+
+impl FileScene {
+    /// Loads a scene, naming the file in syntax and schema errors. Other
+    /// errors, e.g. an invalid camera, are returned unchanged.
+    pub fn from_file(path: &Path) -> Result<Self, PathtracerError> {
+        let source = fs::read_to_string(path)?;
+        let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Self::from_json(&source, directory).map_err(|error| match error {
+            PathtracerError::Parse(message) => {
+                PathtracerError::Parse(format!("{}: {}", path.display(), message))
+            }
+            error => error,
+        })
+    }
+
+    /// Parses a scene from `source`, resolving relative paths against
+    /// `directory`.
+    pub fn from_json(source: &str, directory: PathBuf) -> Result<Self, PathtracerError> {
+        let root = json::parse(source)?;
+
+        let image_settings = match root.get("image") {
+            Some(image) => image_settings(image)?,
+            None => ImageSettings::default(),
+        };
+        let camera = camera(
+            field(&root, "camera")?,
+            image_settings.width as f64 / image_settings.height as f64,
+        )?;
+        // fail early instead of when the first frame is rendered
+        camera.clone().build()?;
+
+        Ok(Self {
+            image_settings,
+            camera,
+            materials: root
+                .get("materials")
+                .cloned()
+                .unwrap_or(JsonValue::Object(vec![])),
+            objects: field(&root, "objects")?.clone(),
+            directory,
+        })
+    }
+}
+
+impl Scene for FileScene {
+    fn get_output_settings(&self) -> OutputSettings {
+        OutputSettings::StaticImage {
+            image_settings: self.image_settings.clone(),
+        }
+    }
+
+    fn get_camera_at(&self, _: f64) -> Camera {
+        self.camera
+            .clone()
+            .build()
+            .expect("camera is checked when the scene is loaded")
+    }
+
+    fn get_world(&self) -> Result<BvhNode, PathtracerError> {
+        self.get_seeded_world(rand::random())
+    }
+
+    fn get_seeded_world(&self, seed: u64) -> Result<BvhNode, PathtracerError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut materials = HashMap::new();
+        for (name, description) in object(&self.materials, "materials")? {
+            let material = material(description, &materials, &self.directory, &mut rng)?;
+            materials.insert(name.clone(), material);
+        }
+
+        let objects = self
+            .objects
+            .as_array()
+            .ok_or_else(|| invalid("objects", "an array"))?;
+        let mut world: Vec<Arc<dyn Hittable>> = vec![];
+        for description in objects {
+            let mut object = self.hittable(description, &materials, &mut rng)?;
+            if boolean_or(description, "flip", false)? {
+                object = Arc::new(FlipFace::new(object));
+            }
+            world.push(object);
+        }
+
+        BvhNode::new(world)
+    }
+}
+
+impl FileScene {
+    fn hittable(
+        &self,
+        description: &JsonValue,
+        materials: &HashMap<String, Arc<dyn Material>>,
+        rng: &mut dyn RngCore,
+    ) -> Result<Arc<dyn Hittable>, PathtracerError> {
+        let kind = string(description, "type")?;
+        if kind == "obj" {
+            let path = self.directory.join(string(description, "path")?);
+            return Ok(Arc::new(ObjModel::new_from_path(&path)?));
+        }
+
+        let material = material(
+            field(description, "material")?,
+            materials,
+            &self.directory,
+            rng,
+        )?;
+        match kind {
+            "sphere" => Ok(Arc::new(Sphere::new(
+                vector(description, "center")?,
+                number(description, "radius")?,
+                material,
+            ))),
+            "box" => Ok(Arc::new(AABox::new(
+                vector(description, "start")?,
+                vector(description, "end")?,
+                material,
+            ))),
+            "rectangle" => {
+                let start = vector(description, "start")?;
+                let end = vector(description, "end")?;
+                let facing = number_or(description, "facing", 1.0)?;
+                let not_aligned = |_| invalid("rectangle", "flat along the x, y or z axis");
+                if start.z() == end.z() {
+                    Ok(Arc::new(
+                        RectangleXY::new(start, end, facing, material).map_err(not_aligned)?,
+                    ))
+                } else if start.y() == end.y() {
+                    Ok(Arc::new(
+                        RectangleXZ::new(start, end, facing, material).map_err(not_aligned)?,
+                    ))
+                } else {
+                    Ok(Arc::new(
+                        RectangleYZ::new(start, end, facing, material).map_err(not_aligned)?,
+                    ))
+                }
+            }
+            "quad" => Ok(Arc::new(Quad::new(
+                vector(description, "corner")?,
+                vector(description, "u")?,
+                vector(description, "v")?,
+                material,
+            ))),
+            "triangle" => {
+                let points = field(description, "points")?
+                    .as_array()
+                    .filter(|points| points.len() == 3)
+                    .ok_or_else(|| invalid("points", "an array of three points"))?;
+                Ok(Arc::new(Triangle::new_without_normal(
+                    to_vector(&points[0], "points")?,
+                    to_vector(&points[1], "points")?,
+                    to_vector(&points[2], "points")?,
+                    material,
+                )))
+            }
+            "plane" => Ok(Arc::new(Plane::new(
+                vector(description, "point")?,
+                vector(description, "normal")?,
+                material,
+            ))),
+            kind => Err(PathtracerError::Parse(format!(
+                "unknown object type '{}'",
+                kind
+            ))),
+        }
+    }
+}
+
+fn image_settings(image: &JsonValue) -> Result<ImageSettings, PathtracerError> {
+    let defaults = ImageSettings::default();
+    let count = |key, default: usize| -> Result<usize, PathtracerError> {
+        let value = number_or(image, key, default as f64)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(invalid(key, "a whole number"));
+        }
+        Ok(value as usize)
+    };
+
+    let width = count("width", defaults.width)?;
+    let height = count("height", defaults.height)?;
+    if width == 0 || height == 0 {
+        return Err(PathtracerError::InvalidImageSize { width, height });
+    }
+
+    Ok(ImageSettings {
+        width,
+        height,
+        samples_per_pixel: count("samples_per_pixel", defaults.samples_per_pixel)?,
+        max_bounces: count("max_bounces", defaults.max_bounces)?,
+        background: match image.get("background") {
+            Some(sky) if sky.get("type").is_some() => match string(sky, "type")? {
+                "sky" => Environment::SkyGradient {
+                    bottom: vector_or(sky, "bottom", Color::new(1.0, 1.0, 1.0))?,
+                    top: vector_or(sky, "top", Color::new(0.5, 0.7, 1.0))?,
+                },
+                kind => {
+                    return Err(PathtracerError::Parse(format!(
+                        "unknown background type '{}'",
+                        kind
+                    )))
+                }
+            },
+            Some(color) => Environment::Constant(to_vector(color, "background")?),
+            None => defaults.background,
+        },
+        filter: match image.get("filter") {
+            Some(filter) => match string(filter, "type")? {
+                "box" => ReconstructionFilter::Box,
+                "triangle" => ReconstructionFilter::Tent {
+                    radius: number_or(filter, "radius", 1.0)?,
+                },
+                "gaussian" => ReconstructionFilter::Gaussian {
+                    radius: number_or(filter, "radius", 1.5)?,
+                    alpha: number_or(filter, "alpha", 2.0)?,
+                },
+                kind => {
+                    return Err(PathtracerError::Parse(format!(
+                        "unknown filter type '{}'",
+                        kind
+                    )))
+                }
+            },
+            None => defaults.filter,
+        },
+        next_event_estimation: match image.get("integrator").map(|kind| kind.as_str()) {
+            None => defaults.next_event_estimation,
+            Some(Some("naive")) => false,
+            Some(Some("importance" | "normals")) => true,
+            Some(_) => {
+                return Err(invalid(
+                    "integrator",
+                    "\"naive\", \"importance\" or \"normals\"",
+                ))
+            }
+        },
+        ray_epsilon: match (
+            image.get("ray_epsilon"),
+            boolean_or(image, "relative_epsilon", false)?,
+        ) {
+            (None, false) => defaults.ray_epsilon,
+            (_, false) => RayEpsilon::Absolute(number(image, "ray_epsilon")?),
+            (_, true) => {
+                RayEpsilon::Relative(number_or(image, "ray_epsilon", DEFAULT_RAY_EPSILON)?)
+            }
+        },
+        render_mode: match image.get("integrator").and_then(|kind| kind.as_str()) {
+            Some("normals") => RenderMode::Normals,
+            _ => defaults.render_mode,
+        },
+        ..defaults
+    })
+}
+
+fn camera(camera: &JsonValue, aspect_ratio: f64) -> Result<CameraBuilder, PathtracerError> {
+    let mut builder = CameraBuilder::new()
+        .look_from(vector(camera, "look_from")?)
+        .look_at(vector(camera, "look_at")?)
+        .fov(number(camera, "fov")?)
+        .aspect_ratio(aspect_ratio)
+        .aperture(number_or(camera, "aperture", 0.0)?);
+    if camera.get("up").is_some() {
+        builder = builder.up(vector(camera, "up")?);
+    }
+    if camera.get("focus_dist").is_some() {
+        builder = builder.focus_dist(number(camera, "focus_dist")?);
+    }
+    Ok(builder)
+}
+
+fn material(
+    description: &JsonValue,
+    materials: &HashMap<String, Arc<dyn Material>>,
+    directory: &Path,
+    rng: &mut dyn RngCore,
+) -> Result<Arc<dyn Material>, PathtracerError> {
+    if let Some(name) = description.as_str() {
+        return materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| PathtracerError::Parse(format!("unknown material '{}'", name)));
+    }
+
+    match string(description, "type")? {
+        "lambertian" => {
+            let sampling = match description
+                .get("sampling")
+                .map(|sampling| sampling.as_str())
+            {
+                None | Some(Some("cosine")) => DiffuseSampling::Cosine,
+                Some(Some("uniform")) => DiffuseSampling::Uniform,
+                _ => return Err(invalid("sampling", "\"uniform\" or \"cosine\"")),
+            };
+            Ok(Arc::new(
+                LambertianMaterial::new(texture(field(description, "albedo")?, directory, rng)?)
+                    .with_sampling(sampling),
+            ))
+        }
+        "metal" => Ok(Arc::new(MetalMaterial::new(
+            texture(field(description, "albedo")?, directory, rng)?,
+            number_or(description, "fuzz", 0.0)?,
+        ))),
+        "dielectric" => {
+            let mut material = DielectricMaterial::new(number(description, "index_of_refraction")?);
+            if description.get("absorption").is_some() {
+                material = material.with_absorption(vector(description, "absorption")?);
+            }
+            Ok(Arc::new(material))
+        }
+        "diffuse_light" => {
+            let mut material =
+                DiffuseLightMaterial::new(texture(field(description, "emit")?, directory, rng)?)
+                    .with_intensity(number_or(description, "intensity", 1.0)?);
+            if boolean_or(description, "two_sided", false)? {
+                material = material.two_sided();
+            }
+            Ok(Arc::new(material))
+        }
+        "spot_light" => Ok(Arc::new(
+            SpotLightMaterial::new(
+                texture(field(description, "emit")?, directory, rng)?,
+                vector(description, "direction")?,
+                number(description, "inner_angle")?,
+                number(description, "outer_angle")?,
+            )
+            .with_intensity(number_or(description, "intensity", 1.0)?),
+        )),
+        kind => Err(PathtracerError::Parse(format!(
+            "unknown material type '{}'",
+            kind
+        ))),
+    }
+}
+
+/// Noise textures draw their seeds from `rng`.
+fn texture(
+    description: &JsonValue,
+    directory: &Path,
+    rng: &mut dyn RngCore,
+) -> Result<Box<dyn Texture>, PathtracerError> {
+    if description.as_array().is_some() {
+        return Ok(Box::new(SolidColorTexture::new(to_vector(
+            description,
+            "color",
+        )?)));
+    }
+
+    match string(description, "type")? {
+        "solid" => Ok(Box::new(SolidColorTexture::new(vector(
+            description,
+            "color",
+        )?))),
+        "blackbody" => Ok(Box::new(SolidColorTexture::new(Color::from_blackbody(
+            number(description, "temperature")?,
+            number_or(description, "scale", 1.0)?,
+        )))),
+        "checker" => {
+            let space = match description.get("space").map(|space| space.as_str()) {
+                None | Some(Some("world")) => CheckerSpace::World,
+                Some(Some("uv")) => CheckerSpace::Uv,
+                Some(_) => return Err(invalid("space", "\"world\" or \"uv\"")),
+            };
+            Ok(Box::new(
+                CheckerTexture::new(
+                    texture(field(description, "odd")?, directory, rng)?,
+                    texture(field(description, "even")?, directory, rng)?,
+                )
+                .with_scale(number_or(description, "scale", 10.0)?)
+                .with_space(space),
+            ))
+        }
+        "perlin" => Ok(Box::new(
+            PerlinNoiseTexture::new(number(description, "scale")?).with_seed(rng.gen()),
+        )),
+        "marble" => {
+            let turbulence = Turbulence::new(
+                number_or(description, "octaves", 7.0)? as usize,
+                number_or(description, "persistence", 0.5)?,
+                number_or(description, "frequency", 1.0)?,
+            )
+            .with_seed(rng.gen());
+            Ok(Box::new(MarbleTexture::new(
+                turbulence,
+                number(description, "scale")?,
+                vector(description, "low")?,
+                vector(description, "high")?,
+            )))
+        }
+        "transform" => {
+            let (scale_u, scale_v) = pair_or(description, "scale", (1.0, 1.0))?;
+            let (offset_u, offset_v) = pair_or(description, "offset", (0.0, 0.0))?;
+            Ok(Box::new(
+                TransformedTexture::new(texture(field(description, "texture")?, directory, rng)?)
+                    .with_scale(scale_u, scale_v)
+                    .with_offset(offset_u, offset_v)
+                    .with_rotation(number_or(description, "rotation", 0.0)?),
+            ))
+        }
+        "triplanar" => Ok(Box::new(
+            TriplanarTexture::new(texture(field(description, "texture")?, directory, rng)?)
+                .with_scale(number_or(description, "scale", 1.0)?),
+        )),
+        "cached" => {
+            let precision = number_or(description, "precision", 1e-3)?;
+            if !(precision.is_finite() && precision > 0.0) {
+                return Err(invalid("precision", "a positive number"));
+            }
+            Ok(Box::new(
+                CachedTexture::new(texture(field(description, "texture")?, directory, rng)?)
+                    .with_precision(precision),
+            ))
+        }
+        "image" => Ok(Box::new(ImageTexture::new_from_path(
+            &directory.join(string(description, "path")?),
+        ))),
+        kind => Err(PathtracerError::Parse(format!(
+            "unknown texture type '{}'",
+            kind
+        ))),
+    }
+}
+
+fn invalid(key: &str, expected: &str) -> PathtracerError {
+    PathtracerError::Parse(format!("'{}' must be {}", key, expected))
+}
+
+fn field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a JsonValue, PathtracerError> {
+    value
+        .get(key)
+        .ok_or_else(|| PathtracerError::Parse(format!("missing '{}'", key)))
+}
+
+fn object<'a>(
+    value: &'a JsonValue,
+    key: &str,
+) -> Result<&'a [(String, JsonValue)], PathtracerError> {
+    value.as_object().ok_or_else(|| invalid(key, "an object"))
+}
+
+fn string<'a>(value: &'a JsonValue, key: &str) -> Result<&'a str, PathtracerError> {
+    field(value, key)?
+        .as_str()
+        .ok_or_else(|| invalid(key, "a string"))
+}
+
+fn number(value: &JsonValue, key: &str) -> Result<f64, PathtracerError> {
+    field(value, key)?
+        .as_f64()
+        .ok_or_else(|| invalid(key, "a number"))
+}
+
+fn number_or(value: &JsonValue, key: &str, default: f64) -> Result<f64, PathtracerError> {
+    match value.get(key) {
+        Some(_) => number(value, key),
+        None => Ok(default),
+    }
+}
+
+fn boolean_or(value: &JsonValue, key: &str, default: bool) -> Result<bool, PathtracerError> {
+    match value.get(key) {
+        Some(flag) => flag.as_bool().ok_or_else(|| invalid(key, "true or false")),
+        None => Ok(default),
+    }
+}
+
+fn vector(value: &JsonValue, key: &str) -> Result<Vec3, PathtracerError> {
+    to_vector(field(value, key)?, key)
+}
+
+fn vector_or(value: &JsonValue, key: &str, default: Vec3) -> Result<Vec3, PathtracerError> {
+    match value.get(key) {
+        Some(vector) => to_vector(vector, key),
+        None => Ok(default),
+    }
+}
+
+fn pair_or(
+    value: &JsonValue,
+    key: &str,
+    default: (f64, f64),
+) -> Result<(f64, f64), PathtracerError> {
+    match value.get(key).map(|pair| pair.as_array()) {
+        None => Ok(default),
+        Some(Some([u, v])) => match (u.as_f64(), v.as_f64()) {
+            (Some(u), Some(v)) => Ok((u, v)),
+            _ => Err(invalid(key, "an array of two numbers")),
+        },
+        Some(_) => Err(invalid(key, "an array of two numbers")),
+    }
+}
+
+fn to_vector(value: &JsonValue, key: &str) -> Result<Color, PathtracerError> {
+    match value.as_array() {
+        Some([x, y, z]) => match (x.as_f64(), y.as_f64(), z.as_f64()) {
+            (Some(x), Some(y), Some(z)) => Ok(Vec3::from((x, y, z))),
+            _ => Err(invalid(key, "an array of three numbers")),
+        },
+        _ => Err(invalid(key, "an array of three numbers")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn load_example_scene() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/example.json");
+        let scene = FileScene::from_file(&path).unwrap();
+
+        let world = scene.get_world().unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit_record = world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit_record.point - Vec3::new(0.0, 1.0, 1.0)).near_zero());
+        match scene.get_output_settings() {
+            OutputSettings::StaticImage { image_settings } => {
+                assert_eq!((400, 300), (image_settings.width, image_settings.height));
+                assert!(matches!(
+                    image_settings.filter,
+                    ReconstructionFilter::Gaussian { .. }
+                ));
+            }
+            _ => panic!("example scene is a still image"),
+        }
+
+        let missing_camera = r#"{ "objects": [] }"#;
+        assert!(FileScene::from_json(missing_camera, PathBuf::new()).is_err());
+        let unknown_material = r#"{
+            "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0], "fov": 40 },
+            "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "gold" }]
+        }"#;
+        let scene = FileScene::from_json(unknown_material, PathBuf::new()).unwrap();
+        assert!(scene.get_world().is_err());
+        let zero_precision = r#"{
+            "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0], "fov": 40 },
+            "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": {
+                "type": "lambertian",
+                "albedo": { "type": "cached", "texture": [1, 1, 1], "precision": 0 }
+            } }]
+        }"#;
+        let scene = FileScene::from_json(zero_precision, PathBuf::new()).unwrap();
+        assert!(matches!(scene.get_world(), Err(PathtracerError::Parse(_))));
+    }
+
+    #[test]
+    fn load_errors_keep_their_kind() {
+        let path = std::env::temp_dir().join("pathtracer_scene_errors.json");
+        let load = |source: &str| {
+            fs::write(&path, source).unwrap();
+            FileScene::from_file(&path)
+        };
+
+        match load("{ \"camera\": ") {
+            Err(PathtracerError::Parse(message)) => {
+                assert!(
+                    message.starts_with(&path.display().to_string()),
+                    "{}",
+                    message
+                )
+            }
+            _ => panic!("syntax errors are parse errors"),
+        }
+        let degenerate_camera = r#"{
+            "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 1], "fov": 40 },
+            "objects": []
+        }"#;
+        assert!(matches!(
+            load(degenerate_camera),
+            Err(PathtracerError::DegenerateCamera(_))
+        ));
+        assert!(matches!(
+            FileScene::from_file(Path::new("does/not/exist.json")),
+            Err(PathtracerError::Io(_))
+        ));
+
+        let missing_model = r#"{
+            "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0], "fov": 40 },
+            "objects": [{ "type": "obj", "path": "does/not/exist.obj" }]
+        }"#;
+        assert!(matches!(
+            load(missing_model).unwrap().get_world(),
+            Err(PathtracerError::Io(_) | PathtracerError::ObjLoad(_))
+        ));
+    }
+}
@@ -1,9 +1,30 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
 use noise::{NoiseFn, Perlin};
 
-use crate::vec3::{Color, Vec3};
+use crate::{
+    error::PathtracerError,
+    jpeg,
+    sampler::splitmix64,
+    vec3::{Color, Vec3},
+};
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, point: Vec3) -> Color;
+    /// Like `value`, for textures which also depend on the surface `normal`
+    /// at `point`. Materials look up their colors through this.
+    fn value_with_normal(&self, u: f64, v: f64, point: Vec3, _normal: Vec3) -> Color {
+        self.value(u, v, point)
+    }
 }
 
 pub struct SolidColorTexture {
@@ -22,28 +43,120 @@ impl Texture for SolidColorTexture {
     }
 }
 
+/// Where a `CheckerTexture` lays out its squares.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckerSpace {
+    /// 3D checkers in world space, with squares `π / scale` wide.
+    #[default]
+    World,
+    /// Checkers on the texture coordinates, `scale` squares per unit of `u`
+    /// and `v`. Follows the surface of spheres and textured meshes.
+    Uv,
+}
+
 pub struct CheckerTexture {
     odd: Box<dyn Texture>,
     even: Box<dyn Texture>,
+    pub scale: f64,
+    pub space: CheckerSpace,
 }
 
 impl CheckerTexture {
     pub fn new(odd: Box<dyn Texture>, even: Box<dyn Texture>) -> Self {
-        Self { odd, even }
+        Self {
+            odd,
+            even,
+            scale: 10.0,
+            space: CheckerSpace::World,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_space(mut self, space: CheckerSpace) -> Self {
+        self.space = space;
+        self
+    }
+
+    fn is_odd(&self, u: f64, v: f64, point: Vec3) -> bool {
+        match self.space {
+            CheckerSpace::World => {
+                let sines = (self.scale * point.x()).sin()
+                    * (self.scale * point.y()).sin()
+                    * (self.scale * point.z()).sin();
+                sines < 0.0
+            }
+            CheckerSpace::Uv => {
+                let squares = (self.scale * u).floor() + (self.scale * v).floor();
+                squares.rem_euclid(2.0) == 1.0
+            }
+        }
     }
 }
 
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
-        let sines = (10.0 * point.x()).sin() * (10.0 * point.y()).sin() * (10.0 * point.z()).sin();
-        if sines < 0.0 {
-            self.odd.value(u, v, point)
+        self.value_with_normal(u, v, point, Vec3::default())
+    }
+
+    fn value_with_normal(&self, u: f64, v: f64, point: Vec3, normal: Vec3) -> Color {
+        if self.is_odd(u, v, point) {
+            self.odd.value_with_normal(u, v, point, normal)
         } else {
-            self.even.value(u, v, point)
+            self.even.value_with_normal(u, v, point, normal)
         }
     }
 }
 
+/// Coordinate a `GradientTexture` blends along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientAxis {
+    X,
+    Y,
+    Z,
+    U,
+    V,
+}
+
+/// Blends linearly from `from` at coordinate `start` to `to` at `end`,
+/// keeping the end colors beyond them.
+pub struct GradientTexture {
+    pub axis: GradientAxis,
+    pub start: f64,
+    pub end: f64,
+    pub from: Color,
+    pub to: Color,
+}
+
+impl GradientTexture {
+    pub fn new(axis: GradientAxis, start: f64, end: f64, from: Color, to: Color) -> Self {
+        Self {
+            axis,
+            start,
+            end,
+            from,
+            to,
+        }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        let coordinate = match self.axis {
+            GradientAxis::X => point.x(),
+            GradientAxis::Y => point.y(),
+            GradientAxis::Z => point.z(),
+            GradientAxis::U => u,
+            GradientAxis::V => v,
+        };
+        let t = ((coordinate - self.start) / (self.end - self.start)).clamp(0.0, 1.0);
+        self.from.lerp(self.to, t)
+    }
+}
+
 pub struct PerlinNoiseTexture {
     noise: Box<Perlin>,
     scale: f64,
@@ -57,6 +170,12 @@ impl PerlinNoiseTexture {
         }
     }
 
+    /// Replaces the random noise with the one for `seed`.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.noise = Box::new(Perlin::new(seed));
+        self
+    }
+
     pub fn turbulance(&self, point: Vec3, depth: usize) -> f64 {
         let mut accumulator = 0.0;
         let mut p = point;
@@ -79,3 +198,839 @@ impl Texture for PerlinNoiseTexture {
             * (1.0 - (self.scale * point.z() + 10.0 * self.turbulance(point, 7)).sin())
     }
 }
+
+/// Fractal sum of Perlin noise: `octaves` layers, each with twice the
+/// frequency of the previous one and its amplitude scaled by `persistence`.
+pub struct Turbulence {
+    noise: Perlin,
+    pub octaves: usize,
+    pub persistence: f64,
+    pub frequency: f64,
+}
+
+impl Turbulence {
+    pub fn new(octaves: usize, persistence: f64, frequency: f64) -> Self {
+        Self {
+            noise: Perlin::new(rand::random()),
+            octaves,
+            persistence,
+            frequency,
+        }
+    }
+
+    /// Replaces the random noise with the one for `seed`.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.noise = Perlin::new(seed);
+        self
+    }
+
+    /// Noise value at `point`, roughly within `[0, 1]` for a persistence of
+    /// 0.5 or less.
+    pub fn value(&self, point: Vec3) -> f64 {
+        let mut accumulator = 0.0;
+        let mut p = self.frequency * point;
+        let mut weight = 1.0;
+
+        for _ in 0..self.octaves {
+            accumulator += weight * self.noise.get(p.e).abs();
+            weight *= self.persistence;
+            p *= 2.0;
+        }
+
+        accumulator
+    }
+}
+
+impl Default for Turbulence {
+    fn default() -> Self {
+        Self::new(7, 0.5, 1.0)
+    }
+}
+
+/// Blends `low` and `high` by the turbulence at the hit point.
+pub struct TurbulenceTexture {
+    pub turbulence: Turbulence,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl TurbulenceTexture {
+    pub fn new(turbulence: Turbulence, low: Color, high: Color) -> Self {
+        Self {
+            turbulence,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, _: f64, _: f64, point: Vec3) -> Color {
+        let t = self.turbulence.value(point).clamp(0.0, 1.0);
+        self.low.lerp(self.high, t)
+    }
+}
+
+/// Veins along the z axis, `scale` of them per 2π units, distorted by
+/// turbulence. The veins are `high`, the material between them `low`.
+pub struct MarbleTexture {
+    pub turbulence: Turbulence,
+    pub scale: f64,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl MarbleTexture {
+    pub fn new(turbulence: Turbulence, scale: f64, low: Color, high: Color) -> Self {
+        Self {
+            turbulence,
+            scale,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _: f64, _: f64, point: Vec3) -> Color {
+        let t = 0.5 * (1.0 - (self.scale * point.z() + 10.0 * self.turbulence.value(point)).sin());
+        self.low.lerp(self.high, t)
+    }
+}
+
+/// Which distance to the feature points a `WorleyNoiseTexture` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyDistance {
+    /// Distance to the nearest feature point, round cells.
+    F1,
+    /// Distance to the second nearest feature point.
+    F2,
+    /// Difference of the two, dark cracks along the cell borders.
+    F2MinusF1,
+}
+
+/// Cellular noise: every unit cell of a grid scaled by `frequency` holds
+/// `points_per_cell` random feature points, and the texture blends `low` and
+/// `high` by the distance of the hit point to them.
+pub struct WorleyNoiseTexture {
+    seed: u64,
+    pub points_per_cell: usize,
+    pub frequency: f64,
+    pub distance: WorleyDistance,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl WorleyNoiseTexture {
+    pub fn new(
+        points_per_cell: usize,
+        frequency: f64,
+        distance: WorleyDistance,
+        low: Color,
+        high: Color,
+    ) -> Self {
+        Self {
+            seed: rand::random(),
+            points_per_cell,
+            frequency,
+            distance,
+            low,
+            high,
+        }
+    }
+
+    /// Replaces the random feature points with the ones for `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Distances from `point` to the nearest and second nearest feature
+    /// point, in cells.
+    pub fn distances(&self, point: Vec3) -> (f64, f64) {
+        let p = self.frequency * point;
+        let cell = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbor = [
+                        cell[0] as i64 + dx,
+                        cell[1] as i64 + dy,
+                        cell[2] as i64 + dz,
+                    ];
+                    let mut hash = neighbor.iter().fold(self.seed, |hash, &coordinate| {
+                        splitmix64(hash ^ splitmix64(coordinate as u64))
+                    });
+                    let mut next = || {
+                        hash = splitmix64(hash);
+                        (hash >> 11) as f64 / (1u64 << 53) as f64
+                    };
+
+                    for _ in 0..self.points_per_cell {
+                        let feature = Vec3::new(
+                            neighbor[0] as f64 + next(),
+                            neighbor[1] as f64 + next(),
+                            neighbor[2] as f64 + next(),
+                        );
+                        let distance = (feature - p).len();
+                        if distance < f1 {
+                            f2 = f1;
+                            f1 = distance;
+                        } else if distance < f2 {
+                            f2 = distance;
+                        }
+                    }
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+}
+
+impl Texture for WorleyNoiseTexture {
+    fn value(&self, _: f64, _: f64, point: Vec3) -> Color {
+        let (f1, f2) = self.distances(point);
+        let t = match self.distance {
+            WorleyDistance::F1 => f1,
+            WorleyDistance::F2 => f2,
+            WorleyDistance::F2MinusF1 => f2 - f1,
+        }
+        .clamp(0.0, 1.0);
+        self.low.lerp(self.high, t)
+    }
+}
+
+/// Moves the texture coordinates before looking up `inner`: they are rotated
+/// by `rotation` degrees around the origin, scaled per axis and offset, then
+/// wrapped into `[0, 1)`. A scale of 3 repeats the texture three times, also
+/// for textures which clamp like `ImageTexture`.
+pub struct TransformedTexture {
+    inner: Box<dyn Texture>,
+    pub scale: (f64, f64),
+    pub offset: (f64, f64),
+    pub rotation: f64,
+}
+
+impl TransformedTexture {
+    pub fn new(inner: Box<dyn Texture>) -> Self {
+        Self {
+            inner,
+            scale: (1.0, 1.0),
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = (u, v);
+        self
+    }
+
+    pub fn with_offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    fn transform(&self, u: f64, v: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (cos * u - sin * v, sin * u + cos * v);
+        (
+            (self.scale.0 * u + self.offset.0).rem_euclid(1.0),
+            (self.scale.1 * v + self.offset.1).rem_euclid(1.0),
+        )
+    }
+}
+
+impl Texture for TransformedTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.value_with_normal(u, v, point, Vec3::default())
+    }
+
+    fn value_with_normal(&self, u: f64, v: f64, point: Vec3, normal: Vec3) -> Color {
+        let (u, v) = self.transform(u, v);
+        self.inner.value_with_normal(u, v, point, normal)
+    }
+}
+
+/// Projects `inner` onto the surface along the three world axes and blends
+/// the projections by the squared components of the normal, so surfaces
+/// without texture coordinates can be textured. The texture repeats every
+/// `1 / scale` world units. Without a normal the projections are averaged.
+pub struct TriplanarTexture {
+    inner: Box<dyn Texture>,
+    pub scale: f64,
+}
+
+impl TriplanarTexture {
+    pub fn new(inner: Box<dyn Texture>) -> Self {
+        Self { inner, scale: 1.0 }
+    }
+
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Weights of the projections along x, y and z, summing to 1.
+    pub fn weights(normal: Vec3) -> Vec3 {
+        let squared = normal * normal;
+        let sum = squared.x() + squared.y() + squared.z();
+        match sum > 0.0 {
+            true => squared / sum,
+            false => Vec3::new(1.0, 1.0, 1.0) / 3.0,
+        }
+    }
+}
+
+impl Texture for TriplanarTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.value_with_normal(u, v, point, Vec3::default())
+    }
+
+    fn value_with_normal(&self, _: f64, _: f64, point: Vec3, normal: Vec3) -> Color {
+        let weights = Self::weights(normal);
+        let p = self.scale * point;
+        let project = |u: f64, v: f64| {
+            self.inner
+                .value(u.rem_euclid(1.0), v.rem_euclid(1.0), point)
+        };
+
+        weights.x() * project(p.z(), p.y())
+            + weights.y() * project(p.x(), p.z())
+            + weights.z() * project(p.x(), p.y())
+    }
+}
+
+/// Number of independently locked parts of a `CachedTexture`, so that render
+/// threads rarely wait for each other.
+const CACHE_SHARDS: usize = 16;
+
+/// Texture coordinates and point rounded to multiples of the precision.
+type CacheKey = [i64; 8];
+
+/// Least recently used cache of texture values with a fixed capacity.
+#[derive(Default)]
+struct LruCache {
+    values: HashMap<CacheKey, (Color, u64)>,
+    /// Keys by the time they were last used, oldest first.
+    by_use: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl LruCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Color> {
+        let (color, last_used) = self.values.get_mut(key)?;
+        self.by_use.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.by_use.insert(self.clock, *key);
+        Some(*color)
+    }
+
+    fn insert(&mut self, key: CacheKey, color: Color, capacity: usize) {
+        while self.values.len() >= capacity.max(1) {
+            let Some((_, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.values.remove(&oldest);
+        }
+        self.clock += 1;
+        if let Some((_, last_used)) = self.values.insert(key, (color, self.clock)) {
+            self.by_use.remove(&last_used);
+        }
+        self.by_use.insert(self.clock, key);
+    }
+}
+
+/// Remembers the values of an expensive texture like `PerlinNoiseTexture`.
+/// Lookups are rounded to a grid of `precision` in `u`, `v` and every axis
+/// of `point`, and all lookups within a grid cell share the value evaluated
+/// at the cell's center. A coarser precision gives more hits, but makes the
+/// texture blocky once cells get as large as its finest details; it should
+/// stay well below the size of a pixel on the surface. The least recently
+/// used values are dropped once `capacity` are stored. The normal is rounded
+/// the same way and is part of the key, for textures depending on it.
+pub struct CachedTexture {
+    inner: Box<dyn Texture>,
+    precision: f64,
+    shard_capacity: usize,
+    shards: Vec<Mutex<LruCache>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CachedTexture {
+    pub fn new(inner: Box<dyn Texture>) -> Self {
+        Self {
+            inner,
+            precision: 1e-3,
+            shard_capacity: 65536 / CACHE_SHARDS,
+            shards: (0..CACHE_SHARDS).map(|_| Mutex::default()).collect(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Panics unless `precision` is positive and finite, which would leave
+    /// no grid to round to.
+    pub fn with_precision(mut self, precision: f64) -> Self {
+        assert!(
+            precision.is_finite() && precision > 0.0,
+            "cache precision must be positive, not {}",
+            precision
+        );
+        self.precision = precision;
+        self
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.shard_capacity = capacity.div_ceil(CACHE_SHARDS);
+        self
+    }
+
+    /// Number of lookups answered from the cache so far.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which had to ask the inner texture so far.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn key(&self, u: f64, v: f64, point: Vec3, normal: Vec3) -> CacheKey {
+        [
+            u,
+            v,
+            point.x(),
+            point.y(),
+            point.z(),
+            normal.x(),
+            normal.y(),
+            normal.z(),
+        ]
+        .map(|value| (value / self.precision).round() as i64)
+    }
+}
+
+impl Texture for CachedTexture {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Color {
+        self.value_with_normal(u, v, point, Vec3::default())
+    }
+
+    fn value_with_normal(&self, u: f64, v: f64, point: Vec3, normal: Vec3) -> Color {
+        let key = self.key(u, v, point, normal);
+        let shard = key
+            .iter()
+            .fold(0, |hash, &part| splitmix64(hash ^ part as u64));
+        let shard = &self.shards[shard as usize % CACHE_SHARDS];
+
+        if let Some(color) = shard.lock().expect("texture cache poisoned").get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return color;
+        }
+        // the inner texture is evaluated without holding the lock
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let [u, v, x, y, z, normal_x, normal_y, normal_z] =
+            key.map(|part| part as f64 * self.precision);
+        let color = self.inner.value_with_normal(
+            u,
+            v,
+            Vec3::new(x, y, z),
+            Vec3::new(normal_x, normal_y, normal_z),
+        );
+        shard
+            .lock()
+            .expect("texture cache poisoned")
+            .insert(key, color, self.shard_capacity);
+        color
+    }
+}
+
+/// Texture backed by a PNG or JPEG image, see `jpeg::read_jpeg` for the
+/// JPEG images which are supported. `u` wraps around horizontally, `v` is
+/// clamped to the image, with `v = 0` at the bottom row.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// Loads the image, falling back to plain magenta if it cannot be read so
+    /// that missing textures stand out in the render.
+    pub fn new_from_path(path: &Path) -> Self {
+        Self::load(path).unwrap_or_else(|_| Self {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::new(1.0, 0.0, 1.0)],
+        })
+    }
+
+    /// Loads a JPEG image for the extensions `.jpg` and `.jpeg`, a PNG image
+    /// otherwise.
+    pub fn load(path: &Path) -> Result<Self, PathtracerError> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let is_jpeg = extension.is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+        });
+        let (width, height, channels, buffer) = if is_jpeg {
+            let (width, height, pixels) = jpeg::read_jpeg(BufReader::new(File::open(path)?))?;
+            (width, height, 3, pixels)
+        } else {
+            let mut decoder = png::Decoder::new(File::open(path)?);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info()?;
+            let mut buffer = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buffer)?;
+            buffer.truncate(info.buffer_size());
+            let (width, height) = (info.width as usize, info.height as usize);
+            (width, height, info.color_type.samples(), buffer)
+        };
+
+        // images are stored in sRGB, but textures are looked up in linear
+        // space
+        let linear = |byte: u8| srgb_to_linear(byte as f64 / 255.0);
+        let pixels = buffer
+            .chunks_exact(channels)
+            .map(|pixel| match channels {
+                1 | 2 => Color::new(linear(pixel[0]), linear(pixel[0]), linear(pixel[0])),
+                _ => Color::new(linear(pixel[0]), linear(pixel[1]), linear(pixel[2])),
+            })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Inverse of the sRGB transfer curve: a linear segment near black and a
+/// 2.4 power above it, close to a plain 2.2 gamma overall.
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _: Vec3) -> Color {
+        // bilinear interpolation between the four nearest pixel centers
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        (1.0 - fy) * ((1.0 - fx) * self.pixel(x0, y0) + fx * self.pixel(x0 + 1, y0))
+            + fy * ((1.0 - fx) * self.pixel(x0, y0 + 1) + fx * self.pixel(x0 + 1, y0 + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_texture_sampling() {
+        let path = std::env::temp_dir().join("pathtracer_image_texture.png");
+        let file = File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+        writer.finish().unwrap();
+
+        let texture = ImageTexture::new_from_path(&path);
+        let point = Vec3::default();
+        assert!((texture.value(0.25, 0.5, point) - Color::new(1.0, 0.0, 0.0)).near_zero());
+        assert!((texture.value(0.75, 2.0, point) - Color::new(0.0, 0.0, 1.0)).near_zero());
+        // halfway between the pixels, also across the wrap around
+        assert!((texture.value(0.5, 0.5, point) - Color::new(0.5, 0.0, 0.5)).near_zero());
+        assert!((texture.value(1.0, 0.5, point) - Color::new(0.5, 0.0, 0.5)).near_zero());
+
+        let missing = ImageTexture::new_from_path(Path::new("does/not/exist.png"));
+        assert!((missing.value(0.3, 0.3, point) - Color::new(1.0, 0.0, 1.0)).near_zero());
+    }
+
+    #[test]
+    fn image_textures_are_decoded_from_srgb() {
+        let path = std::env::temp_dir().join("pathtracer_srgb_texture.png");
+        let file = File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 3, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[10, 128, 188]).unwrap();
+        writer.finish().unwrap();
+
+        let texture = ImageTexture::load(&path).unwrap();
+        let point = Vec3::default();
+        let value = |u: f64| texture.value(u, 0.5, point).x();
+        // linear near black, mid grey is about a fifth, not a quarter
+        assert!((value(1.0 / 6.0) - 10.0 / 255.0 / 12.92).abs() < 1e-9);
+        assert!((value(0.5) - 0.2158605).abs() < 1e-6);
+        assert!((value(5.0 / 6.0) - 0.5028865).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jpeg_images_are_loaded() {
+        let path = std::env::temp_dir().join("pathtracer_image_texture.JPG");
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let pixels = [red, blue]
+            .iter()
+            .flat_map(|color| color.repeat(8 * 8))
+            .collect::<Vec<_>>();
+        // one block of 8x8 pixels per color, one above the other
+        jpeg::write_jpeg(File::create(&path).unwrap(), 8, 16, &pixels, 100).unwrap();
+
+        let texture = ImageTexture::load(&path).unwrap();
+        let point = Vec3::default();
+        let top = texture.value(0.5, 0.9, point);
+        let bottom = texture.value(0.5, 0.1, point);
+        assert!(top.x() > 0.9 && top.z() < 0.1, "{:?}", top);
+        assert!(bottom.x() < 0.1 && bottom.z() > 0.9, "{:?}", bottom);
+
+        // broken images are errors, only `new_from_path` falls back to magenta
+        std::fs::write(&path, b"not a jpeg").unwrap();
+        assert!(matches!(
+            ImageTexture::load(&path),
+            Err(PathtracerError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn uv_checkers_flip_at_square_borders() {
+        let black = Color::default();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let checker = CheckerTexture::new(
+            Box::new(SolidColorTexture::new(black)),
+            Box::new(SolidColorTexture::new(white)),
+        )
+        .with_scale(4.0)
+        .with_space(CheckerSpace::Uv);
+        let point = Vec3::default();
+
+        // squares are 0.25 wide, the first one is even
+        assert!((checker.value(0.1, 0.1, point) - white).near_zero());
+        assert!((checker.value(0.26, 0.1, point) - black).near_zero());
+        assert!((checker.value(0.1, 0.26, point) - black).near_zero());
+        assert!((checker.value(0.26, 0.26, point) - white).near_zero());
+        assert!((checker.value(0.99, 0.74, point) - black).near_zero());
+        assert!((checker.value(0.99, 0.76, point) - white).near_zero());
+        // the world position does not matter
+        assert!((checker.value(0.1, 0.1, Vec3::new(0.2, -0.2, 0.2)) - white).near_zero());
+    }
+
+    #[test]
+    fn gradients_blend_between_the_ends() {
+        let (from, to) = (Color::new(1.0, 0.0, 0.2), Color::new(0.0, 1.0, 0.4));
+        let along_y = GradientTexture::new(GradientAxis::Y, -1.0, 3.0, from, to);
+        let midpoint = along_y.value(0.0, 0.0, Vec3::new(5.0, 1.0, 5.0));
+        assert!((midpoint - 0.5 * (from + to)).near_zero());
+        assert!((along_y.value(0.0, 0.0, Vec3::new(0.0, -2.0, 0.0)) - from).near_zero());
+        assert!((along_y.value(0.0, 0.0, Vec3::new(0.0, 4.0, 0.0)) - to).near_zero());
+
+        let along_v = GradientTexture::new(GradientAxis::V, 0.0, 1.0, from, to);
+        let midpoint = along_v.value(0.9, 0.5, Vec3::new(0.0, 7.0, 0.0));
+        assert!((midpoint - 0.5 * (from + to)).near_zero());
+    }
+
+    #[test]
+    fn more_octaves_add_detail() {
+        // mean squared difference between close samples, which grows with
+        // the amount of high frequency detail
+        let roughness = |octaves| {
+            let turbulence = Turbulence {
+                noise: Perlin::new(3),
+                octaves,
+                persistence: 0.5,
+                frequency: 1.0,
+            };
+            let step = Vec3::new(0.01, 0.007, 0.003);
+            (0..1000)
+                .map(|i| {
+                    let point = i as f64 * Vec3::new(0.37, 0.11, 0.23);
+                    (turbulence.value(point + step) - turbulence.value(point)).powi(2)
+                })
+                .sum::<f64>()
+                / 1000.0
+        };
+
+        let (one, three, seven) = (roughness(1), roughness(3), roughness(7));
+        assert!(one < three && three < seven, "{} {} {}", one, three, seven);
+    }
+
+    #[test]
+    fn worley_f1_is_nearest() {
+        for points_per_cell in [1, 3] {
+            let texture = WorleyNoiseTexture::new(
+                points_per_cell,
+                2.0,
+                WorleyDistance::F2MinusF1,
+                Color::default(),
+                Color::new(1.0, 1.0, 1.0),
+            );
+            for i in 0..1000 {
+                let point = i as f64 * Vec3::new(0.37, -0.11, 0.23);
+                let (f1, f2) = texture.distances(point);
+                assert!(f1 <= f2 && f2.is_finite(), "{} {}", f1, f2);
+            }
+        }
+    }
+
+    #[test]
+    fn transformed_textures_repeat() {
+        let black = Color::default();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let checker = || {
+            CheckerTexture::new(
+                Box::new(SolidColorTexture::new(black)),
+                Box::new(SolidColorTexture::new(white)),
+            )
+            .with_scale(2.0)
+            .with_space(CheckerSpace::Uv)
+        };
+        let doubled = TransformedTexture::new(Box::new(checker())).with_scale(2.0, 2.0);
+        let finer = checker().with_scale(4.0);
+        let point = Vec3::default();
+        for i in 0..64 {
+            let (u, v) = ((i % 8) as f64 / 8.0 + 0.01, (i / 8) as f64 / 8.0 + 0.01);
+            assert_eq!(finer.value(u, v, point).e, doubled.value(u, v, point).e);
+        }
+
+        let offset = TransformedTexture::new(Box::new(checker())).with_offset(0.5, 0.0);
+        assert_eq!(white.e, checker().value(0.1, 0.1, point).e);
+        assert_eq!(black.e, offset.value(0.1, 0.1, point).e);
+        let rotated = TransformedTexture::new(Box::new(checker())).with_rotation(90.0);
+        assert_eq!(
+            checker().value(0.1, 0.6, point).e,
+            rotated.value(0.6, 0.9, point).e
+        );
+    }
+
+    #[test]
+    fn triplanar_projections_are_blended_by_the_normal() {
+        for normal in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            Vec3::new(0.3, -0.5, 0.8),
+            Vec3::default(),
+        ] {
+            let weights = TriplanarTexture::weights(normal);
+            assert!((weights.x() + weights.y() + weights.z() - 1.0).abs() < 1e-12);
+            assert!(weights.x() >= 0.0 && weights.y() >= 0.0 && weights.z() >= 0.0);
+        }
+
+        // red along u, so only projections whose u follows x show red
+        let texture = TriplanarTexture::new(Box::new(GradientTexture::new(
+            GradientAxis::U,
+            0.0,
+            1.0,
+            Color::default(),
+            Color::new(1.0, 0.0, 0.0),
+        )));
+        let point = Vec3::new(0.5, 0.25, 0.75);
+        let along_z = texture.value_with_normal(0.0, 0.0, point, Vec3::new(0.0, 0.0, 1.0));
+        let along_x = texture.value_with_normal(0.0, 0.0, point, Vec3::new(-1.0, 0.0, 0.0));
+        assert!((along_z.x() - 0.5).abs() < 1e-12);
+        assert!((along_x.x() - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn wrapped_textures_pass_the_normal_on() {
+        // red along u, as above
+        let triplanar = || {
+            Box::new(TriplanarTexture::new(Box::new(GradientTexture::new(
+                GradientAxis::U,
+                0.0,
+                1.0,
+                Color::default(),
+                Color::new(1.0, 0.0, 0.0),
+            ))))
+        };
+        let wrapped: [Box<dyn Texture>; 3] = [
+            Box::new(TransformedTexture::new(triplanar()).with_scale(3.0, 2.0)),
+            Box::new(CheckerTexture::new(triplanar(), triplanar())),
+            Box::new(CachedTexture::new(triplanar())),
+        ];
+        let point = Vec3::new(0.5, 0.25, 0.75);
+        for texture in wrapped {
+            let along_z = texture.value_with_normal(0.1, 0.2, point, Vec3::new(0.0, 0.0, 1.0));
+            let along_x = texture.value_with_normal(0.1, 0.2, point, Vec3::new(-1.0, 0.0, 0.0));
+            assert!((along_z.x() - 0.5).abs() < 1e-12);
+            assert!((along_x.x() - 0.75).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn cached_textures_remember_values() {
+        let cached = CachedTexture::new(Box::new(PerlinNoiseTexture::new(4.0)));
+        let point = Vec3::new(0.3, 1.2, -0.7);
+        let first = cached.value(0.25, 0.5, point);
+        for _ in 0..10 {
+            assert_eq!(first.e, cached.value(0.25, 0.5, point).e);
+        }
+        // within the same grid cell
+        assert_eq!(
+            first.e,
+            cached.value(0.25, 0.5, point + Vec3::new(1e-5, 0.0, 0.0)).e
+        );
+        assert_eq!((11, 1), (cached.hits(), cached.misses()));
+
+        // the value of a cell does not depend on which lookup fills it
+        let nearby = point + Vec3::new(2e-4, -3e-4, 1e-4);
+        let noise = || Box::new(PerlinNoiseTexture::new(4.0).with_seed(1331));
+        let (forward, backward) = (CachedTexture::new(noise()), CachedTexture::new(noise()));
+        let forward = [
+            forward.value(0.25, 0.5, point),
+            forward.value(0.25, 0.5, nearby),
+        ];
+        let backward = [
+            backward.value(0.25, 0.5, nearby),
+            backward.value(0.25, 0.5, point),
+        ];
+        assert_eq!(forward[0].e, forward[1].e);
+        assert_eq!(forward[0].e, backward[0].e);
+        assert_eq!(forward[0].e, backward[1].e);
+
+        cached.value(0.25, 0.5, Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(2, cached.misses());
+
+        // the least recently used values are dropped when the cache is full
+        let small = CachedTexture::new(Box::new(SolidColorTexture::new(Color::default())))
+            .with_capacity(CACHE_SHARDS);
+        for i in 0..1000 {
+            small.value(i as f64, 0.0, point);
+        }
+        let stored: usize = small
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().values.len())
+            .sum();
+        assert!(stored <= CACHE_SHARDS, "{}", stored);
+    }
+
+    #[test]
+    #[should_panic(expected = "cache precision must be positive")]
+    fn cache_precision_has_to_be_positive() {
+        CachedTexture::new(Box::new(SolidColorTexture::new(Color::default()))).with_precision(0.0);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::vec3::Color;
+
+/// Compresses linear radiance into the displayable range before gamma
+/// correction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Values above one are clipped.
+    #[default]
+    Linear,
+    /// `c / (1 + c)` per channel, which never reaches white.
+    Reinhard,
+    /// Krzysztof Narkowicz' fit of the ACES filmic curve, with a slight toe
+    /// and a soft shoulder.
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    /// Scales `color` by `exposure` and maps it.
+    pub fn apply(&self, color: Color, exposure: f64) -> Color {
+        let color = exposure * color;
+        match self {
+            Self::Linear => color,
+            Self::Reinhard => color.map(|v| v / (1.0 + v)),
+            Self::AcesFilmic => color.map(|v| {
+                let v = v.max(0.0);
+                ((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mid_gray() {
+        let gray = Color::new(0.18, 0.18, 0.18);
+        let mapped =
+            |tone_mapping: ToneMapping, exposure: f64| tone_mapping.apply(gray, exposure).x();
+
+        assert!((mapped(ToneMapping::Linear, 1.0) - 0.18).abs() < 1e-12);
+        assert!((mapped(ToneMapping::Linear, 2.0) - 0.36).abs() < 1e-12);
+        assert!((mapped(ToneMapping::Reinhard, 1.0) - 0.18 / 1.18).abs() < 1e-12);
+        assert!((mapped(ToneMapping::AcesFilmic, 1.0) - 0.2669).abs() < 1e-4);
+
+        // very bright values stay below white, except for the clipped ACES curve
+        let bright = Color::new(1000.0, 1000.0, 1000.0);
+        assert!(ToneMapping::Reinhard.apply(bright, 1.0).x() < 1.0);
+        assert_eq!(1.0, ToneMapping::AcesFilmic.apply(bright, 1.0).x());
+    }
+}
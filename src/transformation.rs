@@ -0,0 +1,224 @@
+use std::ops::Mul;
+
+use crate::vec3::Vec3;
+
+/// Affine transformation as a row-major 4x4 matrix, applied to column vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4x4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Matrix4x4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Matrix4x4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        Self::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation by `degrees` around `axis`, looking down the
+    /// axis towards the origin.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+
+        Self::new([
+            [
+                cos + x * x * (1.0 - cos),
+                x * y * (1.0 - cos) - z * sin,
+                x * z * (1.0 - cos) + y * sin,
+                0.0,
+            ],
+            [
+                y * x * (1.0 - cos) + z * sin,
+                cos + y * y * (1.0 - cos),
+                y * z * (1.0 - cos) - x * sin,
+                0.0,
+            ],
+            [
+                z * x * (1.0 - cos) - y * sin,
+                z * y * (1.0 - cos) + x * sin,
+                cos + z * z * (1.0 - cos),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.m[column][row];
+            }
+        }
+        Self::new(m)
+    }
+
+    /// Determinant of the upper left 3x3 part, the factor by which the
+    /// transformation scales volumes.
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Inverts the matrix by Gauss-Jordan elimination with partial pivoting.
+    /// Returns `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut m = self.m;
+        let mut inverse = Self::identity().m;
+
+        for column in 0..4 {
+            let pivot_row = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .expect("range is not empty");
+            if m[pivot_row][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot_row);
+            inverse.swap(column, pivot_row);
+
+            let pivot = m[column][column];
+            for k in 0..4 {
+                m[column][k] /= pivot;
+                inverse[column][k] /= pivot;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = m[row][column];
+                for k in 0..4 {
+                    m[row][k] -= factor * m[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+
+        Some(Self::new(inverse))
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let m = &self.m;
+        let transformed = Vec3::new(
+            m[0][0] * point.x() + m[0][1] * point.y() + m[0][2] * point.z() + m[0][3],
+            m[1][0] * point.x() + m[1][1] * point.y() + m[1][2] * point.z() + m[1][3],
+            m[2][0] * point.x() + m[2][1] * point.y() + m[2][2] * point.z() + m[2][3],
+        );
+        let w = m[3][0] * point.x() + m[3][1] * point.y() + m[3][2] * point.z() + m[3][3];
+
+        if w == 1.0 {
+            transformed
+        } else {
+            transformed / w
+        }
+    }
+
+    /// Transforms a direction, ignoring the translation.
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * vector.x() + m[0][1] * vector.y() + m[0][2] * vector.z(),
+            m[1][0] * vector.x() + m[1][1] * vector.y() + m[1][2] * vector.z(),
+            m[2][0] * vector.x() + m[2][1] * vector.y() + m[2][2] * vector.z(),
+        )
+    }
+
+    /// Transforms a surface normal with the inverse transpose, so it stays
+    /// perpendicular to the surface under non-uniform scaling. `inverse` has
+    /// to be the inverse of this matrix. The result is not normalized.
+    pub fn transform_normal(inverse: &Self, normal: Vec3) -> Vec3 {
+        inverse.transpose().transform_vector(normal)
+    }
+}
+
+impl Mul for Matrix4x4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * rhs.m[k][column]).sum();
+            }
+        }
+        Self::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(expected: &Matrix4x4, actual: &Matrix4x4) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!(
+                    (expected.m[row][column] - actual.m[row][column]).abs() < 1e-9,
+                    "{:?} != {:?}",
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_and_transpose() {
+        let m = Matrix4x4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4x4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix4x4::scaling(Vec3::new(2.0, 1.0, 0.5));
+        let inverse = m.inverse().unwrap();
+
+        assert_near(&Matrix4x4::identity(), &(m * inverse));
+        assert_near(&Matrix4x4::identity(), &(inverse * m));
+        assert_eq!(m, m.transpose().transpose());
+        assert!(Matrix4x4::scaling(Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+
+        let point = Vec3::new(0.5, 4.0, -1.0);
+        assert!((inverse.transform_point(m.transform_point(point)) - point).near_zero());
+
+        // the normal of the plane x = y stays perpendicular to it
+        let scaling = Matrix4x4::scaling(Vec3::new(4.0, 1.0, 1.0));
+        let normal =
+            Matrix4x4::transform_normal(&scaling.inverse().unwrap(), Vec3::new(1.0, -1.0, 0.0));
+        let tangent = scaling.transform_vector(Vec3::new(1.0, 1.0, 0.0));
+        assert!(normal.dot(tangent).abs() < 1e-9);
+    }
+}
@@ -22,50 +22,58 @@ impl Vec3 {
         Self { e: [x, y, z] }
     }
 
-    pub fn random() -> Self {
-        Self::new(
-            rand::random::<f64>(),
-            rand::random::<f64>(),
-            rand::random::<f64>(),
-        )
+    pub fn random(rng: &mut (impl Rng + ?Sized)) -> Self {
+        Self::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
+    pub fn random_range(rng: &mut (impl Rng + ?Sized), min: f64, max: f64) -> Self {
         Self::new(
-            rand::thread_rng().gen_range(min..max),
-            rand::thread_rng().gen_range(min..max),
-            rand::thread_rng().gen_range(min..max),
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
         )
     }
 
-    pub fn random_in_unitsphere() -> Self {
+    pub fn random_in_unitsphere(rng: &mut (impl Rng + ?Sized)) -> Self {
         // rejection sampling
         loop {
-            let p = Self::random_range(-1.0, 1.0);
+            let p = Self::random_range(rng, -1.0, 1.0);
             if p.len_squared() < 1.0 {
                 return p;
             }
         }
     }
 
-    pub fn random_on_unitsphere() -> Self {
-        Self::random_in_unitsphere().unit_vector()
+    pub fn random_on_unitsphere(rng: &mut (impl Rng + ?Sized)) -> Self {
+        Self::random_in_unitsphere(rng).unit_vector()
+    }
+
+    /// Random direction in the hemisphere around +z, with a density
+    /// proportional to the cosine to +z. See `OrthonormalBasis` to turn it
+    /// towards a normal.
+    pub fn random_cosine_direction(rng: &mut (impl Rng + ?Sized)) -> Self {
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let radius = r2.sqrt();
+        Self::new(phi.cos() * radius, phi.sin() * radius, (1.0 - r2).sqrt())
     }
 
-    pub fn random_in_unitdisk_xy() -> Self {
+    pub fn random_in_unitdisk_xy(rng: &mut (impl Rng + ?Sized)) -> Self {
         // rejection sampling
         loop {
-            let p = Self::new(
-                rand::thread_rng().gen_range(-1.0..1.0),
-                rand::thread_rng().gen_range(-1.0..1.0),
-                0.0,
-            );
+            let p = Self::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if p.len_squared() < 1.0 {
                 return p;
             }
         }
     }
 
+    /// Takes the first three values of `values`, which must have at least
+    /// three.
+    pub fn from_slice(values: &[f64]) -> Self {
+        Self::new(values[0], values[1], values[2])
+    }
+
     pub fn x(&self) -> f64 {
         self.e[0]
     }
@@ -96,8 +104,27 @@ impl Vec3 {
         )
     }
 
+    /// Vector of length 1 in the same direction. The zero vector comes back
+    /// unchanged instead of turning into NaNs, see `try_unit_vector` to
+    /// detect it.
     pub fn unit_vector(&self) -> Self {
-        *self / self.len()
+        let len = self.len();
+        if len > 0.0 {
+            *self / len
+        } else {
+            *self
+        }
+    }
+
+    /// Like `unit_vector`, but `None` for vectors too short to have a
+    /// meaningful direction.
+    pub fn try_unit_vector(&self) -> Option<Self> {
+        let len = self.len();
+        if len > NEAR_ZERO {
+            Some(*self / len)
+        } else {
+            None
+        }
     }
 
     pub fn map<F>(self, f: F) -> Self
@@ -111,6 +138,42 @@ impl Vec3 {
         self.x().abs() < NEAR_ZERO && self.y().abs() < NEAR_ZERO && self.z().abs() < NEAR_ZERO
     }
 
+    /// Linear interpolation, `self` at `t = 0` and `other` at `t = 1`.
+    pub fn lerp(self, other: Self, t: f64) -> Self {
+        (1.0 - t) * self + t * other
+    }
+
+    /// Clamps every component to `[min, max]`.
+    pub fn clamp(self, min: f64, max: f64) -> Self {
+        self.map(|v| v.clamp(min, max))
+    }
+
+    pub fn min_component(&self) -> f64 {
+        self.x().min(self.y()).min(self.z())
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.x().max(self.y()).max(self.z())
+    }
+
+    /// Component-wise minimum.
+    pub fn min(self, other: Self) -> Self {
+        Self::new(
+            self.x().min(other.x()),
+            self.y().min(other.y()),
+            self.z().min(other.z()),
+        )
+    }
+
+    /// Component-wise maximum.
+    pub fn max(self, other: Self) -> Self {
+        Self::new(
+            self.x().max(other.x()),
+            self.y().max(other.y()),
+            self.z().max(other.z()),
+        )
+    }
+
     pub fn reflect(&self, normal: Self) -> Self {
         *self - 2.0 * self.dot(normal) * normal
     }
@@ -245,14 +308,180 @@ impl IndexMut<usize> for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(e: [f64; 3]) -> Self {
+        Self { e }
+    }
+}
+
+impl From<(f64, f64, f64)> for Vec3 {
+    fn from((x, y, z): (f64, f64, f64)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(vector: Vec3) -> Self {
+        vector.e
+    }
+}
+
 impl Neg for Vec3 {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        self.map(|v| v * -1.0)
+        self.map(|v| -v)
     }
 }
 
+pub type Color = Vec3;
+
+impl Color {
+    /// Hue in degrees, saturation and value in [0, 1].
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Self {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+
+        let (r, g, b) = match hue as usize {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let m = value - chroma;
+        Self::new(r + m, g + m, b + m)
+    }
+
+    /// Color of a blackbody radiator at the given temperature, normalized so
+    /// that its brightest channel is 1.0.
+    pub fn from_kelvin(temperature: f64) -> Self {
+        let color = Self::blackbody(temperature);
+        let max = color.max_component();
+        if max > 0.0 {
+            color / max
+        } else {
+            color
+        }
+    }
+
+    /// Emission of a blackbody radiator at the given temperature with a
+    /// luminance of `scale`, like PBRT's `blackbody` spectra.
+    pub fn from_blackbody(temperature: f64, scale: f64) -> Self {
+        scale * Self::blackbody(temperature)
+    }
+
+    /// Linear sRGB of a blackbody radiator. Planck's law is integrated against
+    /// the analytic CIE 1931 color matching functions from Wyman et al. (2013),
+    /// the result is scaled to a luminance of 1.0.
+    fn blackbody(temperature: f64) -> Self {
+        const PLANCK: f64 = 6.62607015e-34;
+        const LIGHT_SPEED: f64 = 2.99792458e8;
+        const BOLTZMANN: f64 = 1.380649e-23;
+
+        let gaussian = |x: f64, mu: f64, sigma1: f64, sigma2: f64| {
+            let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+            (-0.5 * t * t).exp()
+        };
+
+        let mut xyz = Vec3::default();
+        for lambda in (380..=780).step_by(5).map(|l| l as f64) {
+            let meters = lambda * 1e-9;
+            let radiance = 2.0 * PLANCK * LIGHT_SPEED * LIGHT_SPEED
+                / (meters.powi(5)
+                    * ((PLANCK * LIGHT_SPEED / (meters * BOLTZMANN * temperature)).exp() - 1.0));
+
+            xyz += radiance
+                * Vec3::new(
+                    1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+                        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+                        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2),
+                    0.821 * gaussian(lambda, 568.8, 46.9, 40.5)
+                        + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1),
+                    1.217 * gaussian(lambda, 437.0, 11.8, 36.0)
+                        + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8),
+                );
+        }
+
+        if xyz.y() <= 0.0 {
+            return Self::default();
+        }
+        xyz /= xyz.y();
+
+        Self::new(
+            3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+            -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+            0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+        )
+        .map(|v| v.max(0.0))
+    }
+
+    /// Relative luminance with the Rec. 709 weights.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
+    pub fn r(&self) -> u8 {
+        channel_to_u8(self.x())
+    }
+    pub fn g(&self) -> u8 {
+        channel_to_u8(self.y())
+    }
+    pub fn b(&self) -> u8 {
+        channel_to_u8(self.z())
+    }
+    pub fn rgb(&self) -> [u8; 3] {
+        [self.r(), self.g(), self.b()]
+    }
+}
+
+/// Converts a channel in `[0, 1]` to 8 bit. Values outside are clamped and
+/// NaN becomes 0, so HDR or broken samples can't turn into stray pixels.
+fn channel_to_u8(value: f64) -> u8 {
+    match value.is_nan() {
+        true => 0,
+        false => (value.clamp(0.0, 1.0) * 255.999) as u8,
+    }
+}
+
+/// Right-handed orthonormal basis `u`, `v`, `w` with `w` along a given
+/// direction, built without branches after Duff et al., "Building an
+/// Orthonormal Basis, Revisited" (2017).
+#[derive(Clone, Copy, Debug)]
+pub struct OrthonormalBasis {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl OrthonormalBasis {
+    pub fn from_normal(normal: Vec3) -> Self {
+        let w = normal.unit_vector();
+        let sign = 1.0_f64.copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        Self {
+            u: Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()),
+            v: Vec3::new(b, sign + w.y() * w.y() * a, -w.y()),
+            w,
+        }
+    }
+
+    /// Converts `local`, given in coordinates of this basis, to world space.
+    pub fn local(&self, local: Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+}
+
+/// Gamma corrects a linear color with the exponent `1 / gamma` and converts
+/// it to 8 bit RGB. Channels are clamped to `[0, 1]` first.
+pub fn linear_to_srgb(color: Color, gamma: f64) -> [u8; 3] {
+    color.map(|v| v.clamp(0.0, 1.0).powf(1.0 / gamma)).rgb()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,21 +500,112 @@ mod tests {
         assert_eq!([0.0, 0.0, 1.0], a.cross(b).e);
         assert_eq!([0.0, 1.0, 0.0], d.unit_vector().e);
     }
-}
 
-pub type Color = Vec3;
+    #[test]
+    fn color_constructors() {
+        assert_eq!([1.0, 0.0, 0.0], Color::from_hsv(0.0, 1.0, 1.0).e);
+        assert_eq!([0.0, 1.0, 0.0], Color::from_hsv(120.0, 1.0, 1.0).e);
+        assert_eq!([0.0, 0.0, 0.5], Color::from_hsv(240.0, 1.0, 0.5).e);
+        assert_eq!([0.8, 0.8, 0.8], Color::from_hsv(42.0, 0.0, 0.8).e);
 
-impl Color {
-    pub fn r(&self) -> u8 {
-        (self.x() * 255.999) as u8
+        let warm = Color::from_kelvin(3200.0);
+        assert_eq!(1.0, warm.x());
+        assert!(warm.x() > warm.y() && warm.y() > warm.z());
+
+        let cold = Color::from_kelvin(12000.0);
+        assert_eq!(1.0, cold.z());
+        assert!(cold.z() > cold.x());
+
+        let daylight = Color::from_blackbody(6500.0, 2.0);
+        assert!((daylight.luminance() - 2.0).abs() < 0.05);
+        assert!(daylight.max_component() / daylight.min_component() < 1.15);
+        let hotter = Color::from_blackbody(9000.0, 2.0);
+        assert!(hotter.z() / hotter.x() > daylight.z() / daylight.x());
     }
-    pub fn g(&self) -> u8 {
-        (self.y() * 255.999) as u8
+
+    #[test]
+    fn channels_are_clamped_to_bytes() {
+        let bytes: Vec<u8> = [-1.0, 0.0, 0.5, 1.0, 2.0, f64::NAN]
+            .iter()
+            .map(|&value| Color::new(value, value, value).r())
+            .collect();
+        assert_eq!(vec![0, 0, 127, 255, 255, 0], bytes);
+        assert_eq!(
+            [0, 255, 0],
+            Color::new(f64::NEG_INFINITY, f64::INFINITY, f64::NAN).rgb()
+        );
     }
-    pub fn b(&self) -> u8 {
-        (self.z() * 255.999) as u8
+
+    #[test]
+    fn gamma_correction() {
+        let color = Color::new(0.25, 0.5, 1.0);
+        assert_eq!([63, 127, 255], linear_to_srgb(color, 1.0));
+        assert_eq!([136, 186, 255], linear_to_srgb(color, 2.2));
+        assert_eq!(
+            [0, 255, 0],
+            linear_to_srgb(Color::new(-1.0, 7.0, f64::NAN), 2.2)
+        );
     }
-    pub fn rgb(&self) -> [u8; 3] {
-        [self.r(), self.g(), self.b()]
+
+    #[test]
+    fn cosine_directions_stay_in_the_hemisphere() {
+        let mut rng = rand::thread_rng();
+        for normal in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.3, -2.0, 0.1),
+            Vec3::new(-1.0, 1.0, -1e-9),
+        ] {
+            let basis = OrthonormalBasis::from_normal(normal);
+            assert!((basis.u.cross(basis.v) - basis.w).near_zero());
+            assert!(basis.u.dot(basis.v).abs() < 1e-9);
+            assert!((basis.u.len() - 1.0).abs() < 1e-9);
+
+            for _ in 0..1000 {
+                let direction = basis.local(Vec3::random_cosine_direction(&mut rng));
+                assert!(direction.dot(normal) >= 0.0);
+                assert!((direction.len() - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn component_helpers() {
+        let a = Vec3::new(1.0, -2.0, 3.0);
+        let b = Vec3::new(3.0, 2.0, -1.0);
+
+        assert_eq!([1.0, -2.0, 3.0], a.lerp(b, 0.0).e);
+        assert_eq!([2.0, 0.0, 1.0], a.lerp(b, 0.5).e);
+        assert_eq!([3.0, 2.0, -1.0], a.lerp(b, 1.0).e);
+        assert_eq!([1.0, 0.0, 2.0], a.clamp(0.0, 2.0).e);
+        assert_eq!(-2.0, a.min_component());
+        assert_eq!(3.0, a.max_component());
+        assert_eq!([1.0, -2.0, -1.0], a.min(b).e);
+        assert_eq!([3.0, 2.0, 3.0], a.max(b).e);
+
+        assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < 1e-12);
+        assert_eq!(0.7152, Color::new(0.0, 1.0, 0.0).luminance());
+        assert!(Color::new(0.0, 0.0, 1.0).luminance() < Color::new(1.0, 0.0, 0.0).luminance());
+    }
+
+    #[test]
+    fn zero_vectors_have_no_direction() {
+        let zero = Vec3::default();
+        assert!(zero.try_unit_vector().is_none());
+        assert!(Vec3::new(1e-10, 0.0, -1e-10).try_unit_vector().is_none());
+        assert_eq!([0.0, 0.0, 0.0], zero.unit_vector().e);
+
+        let unit = Vec3::new(0.0, 3.0, -4.0).try_unit_vector().unwrap();
+        assert!((unit - Vec3::new(0.0, 0.6, -0.8)).near_zero());
+    }
+
+    #[test]
+    fn conversions() {
+        let vector = Vec3::new(1.0, -2.0, 3.5);
+        let array: [f64; 3] = vector.into();
+        assert_eq!([1.0, -2.0, 3.5], array);
+        assert_eq!(vector.e, Vec3::from(array).e);
+        assert_eq!(vector.e, Vec3::from((1.0, -2.0, 3.5)).e);
+        assert_eq!(vector.e, Vec3::from_slice(&[1.0, -2.0, 3.5, 7.0]).e);
     }
 }